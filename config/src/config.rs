@@ -14,12 +14,13 @@
 
 //! Configuration file management
 
+use std::collections::BTreeMap;
 use std::env;
 use std::fs::File;
 use std::io::Read;
 use std::path::PathBuf;
 
-use core::config::MinerConfig;
+use core::config::{ApiConfig, HookConfig, MinerConfig};
 use toml;
 use crate::types::{ConfigError, ConfigMembers, GlobalConfig};
 use util::LoggingConfig;
@@ -38,6 +39,8 @@ impl Default for ConfigMembers {
 		ConfigMembers {
 			mining: MinerConfig::default(),
 			logging: Some(LoggingConfig::default()),
+			api: Some(ApiConfig::default()),
+			hooks: Some(HookConfig::default()),
 		}
 	}
 }
@@ -209,3 +212,17 @@ impl GlobalConfig {
 		}
 	}
 }
+
+/// Serializes a mining configuration as a `[mining]` section, with its
+/// subsections, ready to replace the one in a configuration file
+pub fn mining_section(mining: &MinerConfig) -> Result<String, ConfigError> {
+	// going through a toml value puts the plain values ahead of the
+	// tables, as toml wants them
+	let mut section = BTreeMap::new();
+	section.insert(
+		"mining",
+		toml::Value::try_from(mining)
+			.map_err(|e| ConfigError::SerializationError(format!("{}", e)))?,
+	);
+	toml::to_string(&section).map_err(|e| ConfigError::SerializationError(format!("{}", e)))
+}
@@ -33,6 +33,7 @@ extern crate cuckoo_miner as cuckoo;
 mod config;
 mod types;
 
+pub use config::mining_section;
 pub use types::{ConfigError, ConfigMembers, GlobalConfig};
 // pub use config::read_configs;
 
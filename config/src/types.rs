@@ -16,9 +16,9 @@
 
 
 use std::path::PathBuf;
-use std::{fmt, io};
+use std::{error, fmt, io};
 
-use core::config::MinerConfig;
+use core::config::{ApiConfig, HookConfig, MinerConfig};
 
 use util;
 
@@ -66,6 +66,8 @@ impl fmt::Display for ConfigError {
 	}
 }
 
+impl error::Error for ConfigError {}
+
 impl From<io::Error> for ConfigError {
 	fn from(error: io::Error) -> ConfigError {
 		ConfigError::FileIOError(
@@ -104,4 +106,8 @@ pub struct ConfigMembers {
 	pub mining: MinerConfig,
 	/// Logging config
 	pub logging: Option<util::types::LoggingConfig>,
+	/// HTTP API config
+	pub api: Option<ApiConfig>,
+	/// Event hooks config
+	pub hooks: Option<HookConfig>,
 }
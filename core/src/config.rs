@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::fmt;
 use std::path::PathBuf;
 use crate::types::Algorithm;
 
@@ -8,6 +9,28 @@ pub struct GpuConfig {
 	pub driver: u8,
 }
 
+/// What RandomX hashes from, see `RxConfig::mode`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RxMode {
+	/// the dataset, takes ~2.3 GB
+	Full,
+	/// the cache only, takes ~256 MB but hashes several times slower
+	Light,
+	/// full if there's the memory available for it, light otherwise
+	Auto,
+}
+
+impl fmt::Display for RxMode {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			RxMode::Full => write!(f, "full"),
+			RxMode::Light => write!(f, "light"),
+			RxMode::Auto => write!(f, "auto"),
+		}
+	}
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RxConfig {
 	#[serde(default = "default_threads")]
@@ -18,6 +41,25 @@ pub struct RxConfig {
 	pub hard_aes: bool,
 	#[serde(default = "default_rx_bool")]
 	pub large_pages: bool,
+	/// builds the dataset of the next epoch while mining with the current
+	/// one, for another ~2.3 GB of memory. Auto mode gives it up before
+	/// falling back to light mode
+	#[serde(default = "default_double_buffer")]
+	pub double_buffer: bool,
+	#[serde(default = "default_rx_mode")]
+	pub mode: RxMode,
+	/// MiB of memory auto mode leaves to the rest of the system, it only
+	/// mines in full mode when the datasets fit with this much to spare
+	#[serde(default = "default_memory_headroom")]
+	pub memory_headroom: u64,
+	/// builds a dataset on each NUMA node and pins solver threads to
+	/// cores of theirs, when there's more than one node
+	#[serde(default = "default_numa")]
+	pub numa: bool,
+	/// CPU lists of the nodes, like "0-7,16-23", instead of the ones
+	/// the kernel reports
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub numa_nodes: Vec<String>,
 }
 
 fn default_threads() -> u64 {
@@ -28,17 +70,204 @@ fn default_rx_bool() -> bool {
 	false
 }
 
+fn default_double_buffer() -> bool {
+	false
+}
+
+fn default_rx_mode() -> RxMode {
+	RxMode::Auto
+}
+
+fn default_memory_headroom() -> u64 {
+	256
+}
+
+fn default_numa() -> bool {
+	true
+}
+
 impl Default for RxConfig {
 	fn default() -> Self {
 		RxConfig {
 			jit: true,
 			hard_aes: true,
 			large_pages: false,
+			double_buffer: false,
+			mode: RxMode::Auto,
+			memory_headroom: default_memory_headroom(),
+			numa: true,
+			numa_nodes: vec![],
 			threads: 1
 		}
 	}
 }
 
+/// Embedded HTTP status and control API configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiConfig {
+	/// Whether to run the API server
+	#[serde(default)]
+	pub enabled: bool,
+	/// Address the API server listens on
+	#[serde(default = "default_api_listen_addr")]
+	pub listen_addr: String,
+	/// Bearer token required in the Authorization header of every request.
+	/// Without one the API is read only
+	pub api_secret: Option<String>,
+}
+
+fn default_api_listen_addr() -> String {
+	String::from("127.0.0.1:3420")
+}
+
+impl Default for ApiConfig {
+	fn default() -> Self {
+		ApiConfig {
+			enabled: false,
+			listen_addr: default_api_listen_addr(),
+			api_secret: None,
+		}
+	}
+}
+
+/// Hooks run on notable miner events, a webhook, a local command or both
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HookConfig {
+	/// URL the event is POSTed to as JSON, http or https
+	pub webhook_url: Option<String>,
+	/// Command run with the event in EPIC_MINER_* environment variables
+	pub command: Option<String>,
+	/// Events to fire on: block_found, device_error, disconnected,
+	/// login_failed
+	#[serde(default = "default_hook_events")]
+	pub events: Vec<String>,
+	/// Minimum seconds between two firings for the same event, the ones in
+	/// between are dropped and counted
+	#[serde(default = "default_hook_min_interval")]
+	pub min_interval: u64,
+}
+
+fn default_hook_events() -> Vec<String> {
+	vec![
+		"block_found".to_owned(),
+		"device_error".to_owned(),
+		"disconnected".to_owned(),
+		"login_failed".to_owned(),
+	]
+}
+
+fn default_hook_min_interval() -> u64 {
+	60
+}
+
+impl Default for HookConfig {
+	fn default() -> Self {
+		HookConfig {
+			webhook_url: None,
+			command: None,
+			events: default_hook_events(),
+			min_interval: default_hook_min_interval(),
+		}
+	}
+}
+
+/// Restarting of Cuckoo plugin solvers that error or stall
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WatchdogConfig {
+	/// Whether to restart solvers at all, errored ones stay down otherwise
+	#[serde(default = "default_watchdog_enabled")]
+	pub enabled: bool,
+	/// Seconds a single solver run may take before it's considered stuck
+	#[serde(default = "default_watchdog_stall_timeout")]
+	pub stall_timeout: u64,
+	/// Restarts of a solver instance before giving up on it, counted
+	/// since it was last up longer than the stall timeout
+	#[serde(default = "default_watchdog_max_restarts")]
+	pub max_restarts: u32,
+	/// Seconds to wait before the first restart, doubled for each one after
+	#[serde(default = "default_watchdog_backoff")]
+	pub backoff: u64,
+}
+
+fn default_watchdog_enabled() -> bool {
+	true
+}
+
+fn default_watchdog_stall_timeout() -> u64 {
+	120
+}
+
+fn default_watchdog_max_restarts() -> u32 {
+	5
+}
+
+fn default_watchdog_backoff() -> u64 {
+	5
+}
+
+impl Default for WatchdogConfig {
+	fn default() -> Self {
+		WatchdogConfig {
+			enabled: default_watchdog_enabled(),
+			stall_timeout: default_watchdog_stall_timeout(),
+			max_restarts: default_watchdog_max_restarts(),
+			backoff: default_watchdog_backoff(),
+		}
+	}
+}
+
+/// How solver threads are scheduled, see `CpuConfig::policy`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SchedPolicy {
+	/// the default, time shared with everything else
+	Other,
+	/// time shared, but treated as CPU bound and preempted less often
+	Batch,
+	/// only runs when nothing else wants the CPU
+	Idle,
+}
+
+impl fmt::Display for SchedPolicy {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			SchedPolicy::Other => write!(f, "other"),
+			SchedPolicy::Batch => write!(f, "batch"),
+			SchedPolicy::Idle => write!(f, "idle"),
+		}
+	}
+}
+
+/// Where and how the solver threads of CPU miners run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CpuConfig {
+	/// CPUs they may run on, as a list like "0-3,8" or a mask like "0xff",
+	/// all of them when not set
+	pub cores: Option<String>,
+	/// cores left to other processes, the last ones of those above
+	#[serde(default)]
+	pub free_cores: usize,
+	/// nice level, -20 to 19, higher yields to others more
+	pub nice: Option<i32>,
+	#[serde(default = "default_sched_policy")]
+	pub policy: SchedPolicy,
+}
+
+fn default_sched_policy() -> SchedPolicy {
+	SchedPolicy::Other
+}
+
+impl Default for CpuConfig {
+	fn default() -> Self {
+		CpuConfig {
+			cores: None,
+			free_cores: 0,
+			nice: None,
+			policy: default_sched_policy(),
+		}
+	}
+}
+
 /// CuckooMinerPlugin configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EpicMinerPluginConfig {
@@ -58,6 +287,37 @@ impl Default for EpicMinerPluginConfig {
 	}
 }
 
+/// A mining pipeline, an algorithm mined on its own devices for its own
+/// pool. Whatever is left out is taken from the `[mining]` section, except
+/// for the share history which is only kept when set here.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PipelineConfig {
+	/// name the pipeline is shown and selected by
+	pub name: String,
+
+	pub algorithm: Algorithm,
+
+	pub stratum_server_addr: Option<String>,
+
+	pub stratum_server_login: Option<String>,
+
+	pub stratum_server_password: Option<String>,
+
+	pub stratum_server_tls_enabled: Option<bool>,
+
+	pub stratum_server_failover_addrs: Option<Vec<String>>,
+
+	pub share_history_file: Option<String>,
+
+	pub randomx_config: Option<RxConfig>,
+
+	pub miner_plugin_config: Option<Vec<EpicMinerPluginConfig>>,
+
+	pub gpu_config: Option<Vec<GpuConfig>>,
+
+	pub cpu_config: Option<CpuConfig>,
+}
+
 /// basic mining configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MinerConfig {
@@ -81,14 +341,38 @@ pub struct MinerConfig {
 	/// whether tls is enabled for the stratum server
 	pub stratum_server_tls_enabled: Option<bool>,
 
+	/// seconds without a new job before a fresh template is requested,
+	/// the connection is dropped after twice that long
+	pub stratum_job_timeout: Option<u64>,
+
+	/// stratum servers to fail over to when the current one goes quiet
+	pub stratum_server_failover_addrs: Option<Vec<String>>,
+
+	/// append-only file every answered share is recorded in, used to
+	/// restore the solution counters on restart
+	pub share_history_file: Option<String>,
+
 	/// plugin dir
 	pub miner_plugin_dir: Option<PathBuf>,
 
 	/// Cuckoo miner plugin configuration, one for each plugin
 	pub miner_plugin_config: Vec<EpicMinerPluginConfig>,
 
+	/// restarting of errored or stuck plugin solvers
+	#[serde(default)]
+	pub watchdog_config: WatchdogConfig,
+
+	/// affinity and priority of CPU solver threads
+	#[serde(default)]
+	pub cpu_config: CpuConfig,
+
 	// gpu devices
 	pub gpu_config: Vec<GpuConfig>,
+
+	/// further mining pipelines run next to each other in the same process,
+	/// when any are given the values above only serve as their defaults
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub pipelines: Vec<PipelineConfig>,
 }
 
 impl Default for MinerConfig {
@@ -99,11 +383,66 @@ impl Default for MinerConfig {
 			run_tui: false,
 			miner_plugin_dir: None,
 			miner_plugin_config: vec![],
+			watchdog_config: WatchdogConfig::default(),
+			cpu_config: CpuConfig::default(),
 			stratum_server_addr: String::from("http://127.0.0.1:13416"),
 			stratum_server_login: None,
 			stratum_server_password: None,
 			stratum_server_tls_enabled: None,
+			stratum_job_timeout: None,
+			stratum_server_failover_addrs: None,
+			share_history_file: None,
 			gpu_config: vec![],
+			pipelines: vec![],
+		}
+	}
+}
+
+impl MinerConfig {
+	/// The pipelines to run, named, each with a configuration of its own.
+	/// Without any `pipelines` configured it's this configuration alone,
+	/// named `default`.
+	pub fn pipelines(&self) -> Vec<(String, MinerConfig)> {
+		if self.pipelines.is_empty() {
+			return vec![("default".to_owned(), self.clone())];
 		}
+		self.pipelines
+			.iter()
+			.map(|p| {
+				let mut config = self.clone();
+				config.pipelines = vec![];
+				config.algorithm = Some(p.algorithm);
+				// sharing the file would mix the shares of different pools
+				config.share_history_file = p.share_history_file.clone();
+				if let Some(ref addr) = p.stratum_server_addr {
+					config.stratum_server_addr = addr.clone();
+				}
+				if p.stratum_server_login.is_some() {
+					config.stratum_server_login = p.stratum_server_login.clone();
+				}
+				if p.stratum_server_password.is_some() {
+					config.stratum_server_password = p.stratum_server_password.clone();
+				}
+				if p.stratum_server_tls_enabled.is_some() {
+					config.stratum_server_tls_enabled = p.stratum_server_tls_enabled;
+				}
+				if p.stratum_server_failover_addrs.is_some() {
+					config.stratum_server_failover_addrs = p.stratum_server_failover_addrs.clone();
+				}
+				if let Some(ref rx) = p.randomx_config {
+					config.randomx_config = rx.clone();
+				}
+				if let Some(ref plugins) = p.miner_plugin_config {
+					config.miner_plugin_config = plugins.clone();
+				}
+				if let Some(ref gpus) = p.gpu_config {
+					config.gpu_config = gpus.clone();
+				}
+				if let Some(ref cpu) = p.cpu_config {
+					config.cpu_config = cpu.clone();
+				}
+				(p.name.clone(), config)
+			})
+			.collect()
 	}
 }
@@ -0,0 +1,237 @@
+// Copyright 2019 The Epic Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! CPU lists, and where and how solver threads run, see `CpuConfig`
+
+use crate::config::{CpuConfig, SchedPolicy};
+
+/// Parses a CPU list the way the kernel writes them in sysfs, like
+/// `0-3,8,10-11`
+pub fn parse_cpulist(list: &str) -> Result<Vec<usize>, String> {
+	let mut cpus = vec![];
+	for part in list.split(',').map(|p| p.trim()).filter(|p| !p.is_empty()) {
+		let cpu = |s: &str| {
+			s.trim()
+				.parse::<usize>()
+				.map_err(|_| format!("Invalid CPU list {}", list))
+		};
+		match part.find('-') {
+			Some(i) => {
+				let (first, last) = (cpu(&part[..i])?, cpu(&part[i + 1..])?);
+				if first > last {
+					return Err(format!("Invalid CPU list {}", list));
+				}
+				cpus.extend(first..=last);
+			}
+			None => cpus.push(cpu(part)?),
+		}
+	}
+	cpus.sort();
+	cpus.dedup();
+	Ok(cpus)
+}
+
+/// Writes a CPU list the way the kernel does, the reverse of
+/// `parse_cpulist`
+pub fn format_cpulist(cpus: &[usize]) -> String {
+	let mut ranges: Vec<(usize, usize)> = vec![];
+	for &cpu in cpus {
+		match ranges.last_mut() {
+			Some(last) if last.1 + 1 == cpu => last.1 = cpu,
+			_ => ranges.push((cpu, cpu)),
+		}
+	}
+	ranges
+		.iter()
+		.map(|&(first, last)| {
+			if first == last {
+				first.to_string()
+			} else {
+				format!("{}-{}", first, last)
+			}
+		})
+		.collect::<Vec<String>>()
+		.join(",")
+}
+
+/// Parses the CPUs of `CpuConfig::cores`, a CPU list or a hex mask
+/// starting with 0x, CPU 0 being the lowest bit
+pub fn parse_cores(cores: &str) -> Result<Vec<usize>, String> {
+	let cores = cores.trim();
+	let mask = match cores.strip_prefix("0x").or(cores.strip_prefix("0X")) {
+		Some(mask) => mask,
+		None => return parse_cpulist(cores),
+	};
+	let mut cpus = vec![];
+	for (i, digit) in mask.chars().rev().enumerate() {
+		let bits = digit
+			.to_digit(16)
+			.ok_or(format!("Invalid CPU mask {}", cores))?;
+		cpus.extend((0..4).filter(|b| bits & (1 << b) != 0).map(|b| i * 4 + b));
+	}
+	Ok(cpus)
+}
+
+/// CPUs solver threads may run on as configured, none when they aren't
+/// restricted at all
+pub fn allowed_cpus(config: &CpuConfig) -> Result<Option<Vec<usize>>, String> {
+	if config.cores.is_none() && config.free_cores == 0 {
+		return Ok(None);
+	}
+	let mut cpus = match config.cores {
+		Some(ref cores) => parse_cores(cores)?,
+		None => current_affinity(),
+	};
+	if config.free_cores >= cpus.len() {
+		return Err(format!(
+			"Leaving {} cores free leaves none of {} to mine on",
+			config.free_cores,
+			format_cpulist(&cpus)
+		));
+	}
+	cpus.truncate(cpus.len() - config.free_cores);
+	Ok(Some(cpus))
+}
+
+/// Gives the calling thread the configured priority and restricts it to
+/// those CPUs, if any. Either is done even if the other fails
+pub fn apply(config: &CpuConfig, cpus: Option<&[usize]>) -> Result<(), String> {
+	let priority = set_current_thread_priority(config.policy, config.nice);
+	let pinned = match cpus {
+		Some(cpus) => pin_current_thread(cpus),
+		None => Ok(()),
+	};
+	priority.and(pinned)
+}
+
+/// CPUs the calling thread may run on
+#[cfg(target_os = "linux")]
+pub fn current_affinity() -> Vec<usize> {
+	use std::mem;
+
+	unsafe {
+		let mut set: libc::cpu_set_t = mem::zeroed();
+		if libc::sched_getaffinity(0, mem::size_of::<libc::cpu_set_t>(), &mut set) != 0 {
+			return all_cpus();
+		}
+		(0..libc::CPU_SETSIZE as usize)
+			.filter(|&cpu| libc::CPU_ISSET(cpu, &set))
+			.collect()
+	}
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn current_affinity() -> Vec<usize> {
+	all_cpus()
+}
+
+fn all_cpus() -> Vec<usize> {
+	let count = std::thread::available_parallelism().map_or(1, |n| n.get());
+	(0..count).collect()
+}
+
+/// Sets the scheduling policy and nice level of the calling thread. The
+/// default policy is left alone, so is the nice level when not given
+#[cfg(target_os = "linux")]
+pub fn set_current_thread_priority(policy: SchedPolicy, nice: Option<i32>) -> Result<(), String> {
+	use std::io;
+
+	let policy = match policy {
+		SchedPolicy::Other => None,
+		SchedPolicy::Batch => Some(libc::SCHED_BATCH),
+		SchedPolicy::Idle => Some(libc::SCHED_IDLE),
+	};
+	unsafe {
+		if let Some(policy) = policy {
+			let param = libc::sched_param { sched_priority: 0 };
+			if libc::sched_setscheduler(0, policy, &param) != 0 {
+				return Err(io::Error::last_os_error().to_string());
+			}
+		}
+		if let Some(nice) = nice {
+			// the nice level of a Linux thread is that of its thread id
+			let tid = libc::syscall(libc::SYS_gettid) as libc::id_t;
+			if libc::setpriority(libc::PRIO_PROCESS, tid, nice) != 0 {
+				return Err(io::Error::last_os_error().to_string());
+			}
+		}
+	}
+	Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn set_current_thread_priority(policy: SchedPolicy, nice: Option<i32>) -> Result<(), String> {
+	match (policy, nice) {
+		(SchedPolicy::Other, None) => Ok(()),
+		_ => Err("Thread priorities are only supported on Linux".to_owned()),
+	}
+}
+
+/// Restricts the calling thread to those CPUs. Threads it spawns after
+/// inherit that
+#[cfg(target_os = "linux")]
+pub fn pin_current_thread(cpus: &[usize]) -> Result<(), String> {
+	use std::{io, mem};
+
+	unsafe {
+		let mut set: libc::cpu_set_t = mem::zeroed();
+		for &cpu in cpus {
+			if cpu >= libc::CPU_SETSIZE as usize {
+				return Err(format!("No such CPU {}", cpu));
+			}
+			libc::CPU_SET(cpu, &mut set);
+		}
+		if libc::sched_setaffinity(0, mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+			return Err(io::Error::last_os_error().to_string());
+		}
+	}
+	Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn pin_current_thread(_cpus: &[usize]) -> Result<(), String> {
+	Err("Pinning threads is only supported on Linux".to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn cpulists() {
+		assert_eq!(parse_cpulist("0-3,8,10-11\n"), Ok(vec![0, 1, 2, 3, 8, 10, 11]));
+		assert_eq!(parse_cpulist("5"), Ok(vec![5]));
+		assert_eq!(parse_cpulist(""), Ok(vec![]));
+		assert_eq!(parse_cpulist("2,0-2"), Ok(vec![0, 1, 2]));
+		assert!(parse_cpulist("3-1").is_err());
+		assert!(parse_cpulist("0-x").is_err());
+		assert_eq!(format_cpulist(&[0, 1, 2, 3, 8, 10, 11]), "0-3,8,10-11");
+		assert_eq!(format_cpulist(&[]), "");
+	}
+
+	#[test]
+	fn allowed() {
+		assert_eq!(parse_cores("0xf0"), Ok(vec![4, 5, 6, 7]));
+		assert_eq!(parse_cores("0x101"), Ok(vec![0, 8]));
+		assert!(parse_cores("0xg").is_err());
+
+		let mut config = CpuConfig::default();
+		assert_eq!(allowed_cpus(&config), Ok(None));
+		config.cores = Some("0-5".to_owned());
+		config.free_cores = 2;
+		assert_eq!(allowed_cpus(&config), Ok(Some(vec![0, 1, 2, 3])));
+		config.free_cores = 6;
+		assert!(allowed_cpus(&config).is_err());
+	}
+}
@@ -1,4 +1,4 @@
-// Copyright 2018 The Grin Developers
+// Copyright 2019 The Epic Developers
 //
 // Licensed under the Apache License, Version 2.0 (the "License");
 // you may not use this file except in compliance with the License.
@@ -12,17 +12,26 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-/// Difficulty calculation as from Epic
+//! Difficulty and target math for every algorithm mined.
+//!
+//! A share of difficulty `d` is expected to take `d` tries: hashes for
+//! RandomX and ProgPow, whose hashes must be at or below the 256-bit
+//! target `U256::MAX / d`, and proofs for Cuckoo, whose difficulty is taken
+//! from the first 64 bits of the proof hash and, for blocks, scaled by the
+//! graph weight or the secondary scaling of the header.
 
+use bigint::uint::U256;
 use blake2::blake2b::Blake2b;
+use byteorder::{BigEndian, ByteOrder};
 use std::cmp::{max, min};
 use std::fmt;
-use byteorder::{BigEndian, ByteOrder};
+
+use crate::types::Algorithm;
 
 // constants from epic
-const DEFAULT_MIN_EDGE_BITS: u8 = 31;
-const SECOND_POW_EDGE_BITS: u8 = 29;
-const PROOF_SIZE: usize = 42;
+pub const DEFAULT_MIN_EDGE_BITS: u8 = 31;
+pub const SECOND_POW_EDGE_BITS: u8 = 29;
+pub const PROOF_SIZE: usize = 42;
 const BLOCK_TIME_SEC: u64 = 60;
 
 const HOUR_HEIGHT: u64 = 3600 / BLOCK_TIME_SEC;
@@ -32,6 +41,48 @@ const YEAR_HEIGHT: u64 = 52 * WEEK_HEIGHT;
 
 const BASE_EDGE_BITS: u8 = 24;
 
+/// A cuckatoo graph holds a 42-cycle once in 42 graphs on average, so
+/// every share difficulty point takes about that many graphs
+pub const CUCKOO_GRAPHS_PER_PROOF: f64 = 42.0;
+
+/// Target the hash of a share of that difficulty must not exceed
+pub fn target(difficulty: u64) -> U256 {
+	U256::max_value() / U256::from(max(difficulty, 1))
+}
+
+/// The first 64 bits of the target, as GPU kernels compare against
+pub fn target_u64(difficulty: u64) -> u64 {
+	(target(difficulty) >> 192).low_u64()
+}
+
+/// Difficulty a hash, or a target, stands for: the highest difficulty
+/// whose target it's still at or below
+pub fn hash_difficulty(hash: &U256) -> u64 {
+	if hash.is_zero() {
+		return u64::MAX;
+	}
+	let difficulty = U256::max_value() / *hash;
+	if difficulty > U256::from(u64::MAX) {
+		u64::MAX
+	} else {
+		difficulty.low_u64()
+	}
+}
+
+/// Whether the hash makes a share of that difficulty
+pub fn meets_target(hash: &U256, difficulty: u64) -> bool {
+	*hash <= target(difficulty)
+}
+
+/// Tries expected to find a share of that difficulty, in the unit the
+/// miner reports its rate in: graphs for Cuckoo, hashes otherwise
+pub fn expected_work(algorithm: Algorithm, difficulty: u64) -> f64 {
+	match algorithm {
+		Algorithm::Cuckoo => difficulty as f64 * CUCKOO_GRAPHS_PER_PROOF,
+		_ => difficulty as f64,
+	}
+}
+
 /// Compute weight of a graph as number of siphash bits defining the graph
 /// Must be made dependent on height to phase out smaller size over the years
 /// This can wait until end of 2019 at latest
@@ -130,7 +181,7 @@ impl Proof {
 	pub fn new(mut in_nonces: Vec<u64>, edge_bits: u8) -> Proof {
 		in_nonces.sort();
 		Proof {
-			edge_bits: edge_bits,
+			edge_bits,
 			nonces: in_nonces,
 		}
 	}
@@ -138,17 +189,17 @@ impl Proof {
 	/// Difficulty achieved by this proof with given scaling factor
 	fn scaled_difficulty(&self, scale: u64) -> u64 {
 		let diff = ((scale as u128) << 64) / (max(1, self.hash().to_u64()) as u128);
-		min(diff, <u64>::max_value() as u128) as u64
+		min(diff, u64::MAX as u128) as u64
 	}
 
 	/// Hash, as in Epic
-	fn hash(&self) -> Hash {
+	pub fn hash(&self) -> Hash {
 		let nonce_bits = self.edge_bits as usize;
 		let mut bitvec = BitVec::new(nonce_bits * PROOF_SIZE);
 		for (n, nonce) in self.nonces.iter().enumerate() {
 			for bit in 0..nonce_bits {
 				if nonce & (1 << bit) != 0 {
-					bitvec.set_bit_at(n * nonce_bits + (bit as usize))
+					bitvec.set_bit_at(n * nonce_bits + bit)
 				}
 			}
 		}
@@ -164,15 +215,15 @@ impl Proof {
 		// 2 proof of works, Cuckoo29 (for now) and Cuckoo30+, which are scaled
 		// differently (scaling not controlled for now)
 		if self.edge_bits == SECOND_POW_EDGE_BITS {
-			Difficulty::from_proof_scaled(&self, sec_scaling)
+			Difficulty::from_proof_scaled(self, sec_scaling)
 		} else {
-			Difficulty::from_proof_adjusted(height, &self)
+			Difficulty::from_proof_adjusted(height, self)
 		}
 	}
 
-	/// unscaled difficulty
+	/// unscaled difficulty, what a share is measured by
 	pub fn to_difficulty_unscaled(&self) -> Difficulty {
-		Difficulty::from_proof_unscaled(&self)
+		Difficulty::from_proof_unscaled(self)
 	}
 }
 
@@ -183,7 +234,7 @@ struct BitVec {
 impl BitVec {
 	/// Number of bytes required to store the provided number of bits
 	fn bytes_len(bits_len: usize) -> usize {
-		(bits_len + 7) / 8
+		bits_len.div_ceil(8)
 	}
 
 	fn new(bits_len: usize) -> BitVec {
@@ -198,14 +249,29 @@ impl BitVec {
 }
 
 impl Hash {
+	/// Hash of those bytes as they are
+	pub fn from_bytes(bytes: [u8; 32]) -> Hash {
+		Hash(bytes)
+	}
+
+	/// The raw bytes of the hash
+	pub fn as_bytes(&self) -> &[u8; 32] {
+		&self.0
+	}
+
 	/// to u64
 	pub fn to_u64(&self) -> u64 {
 		BigEndian::read_u64(&self.0)
 	}
 
+	/// The whole hash as a 256-bit number, big-endian
+	pub fn to_u256(&self) -> U256 {
+		U256::from(&self.0[..])
+	}
+
 	/// to hex
 	pub fn to_hex(&self) -> String {
-		util::to_hex(self.0.to_vec())
+		self.0.iter().map(|b| format!("{:02x}", b)).collect()
 	}
 }
 
@@ -229,32 +295,78 @@ impl fmt::Display for Hash {
 	}
 }
 
-
 #[cfg(test)]
 mod tests {
 	use super::*;
 
+	#[test]
+	fn targets() {
+		let max = U256::max_value();
+		assert_eq!(target(0), max);
+		assert_eq!(target(1), max);
+		assert_eq!(target(2), max >> 1);
+		assert_eq!(target(1 << 32), max >> 32);
+		assert_eq!(target_u64(1), u64::MAX);
+		assert_eq!(target_u64(1 << 20), u64::MAX >> 20);
+
+		for &d in &[1, 2, 3, 7, 1000, 123_456_789, 1 << 40, u64::MAX] {
+			// the top 64 bits of the target are the 64-bit division
+			assert_eq!(target_u64(d), u64::MAX / d);
+			// a target stands for the difficulty it was made of
+			assert_eq!(hash_difficulty(&target(d)), d);
+			assert!(meets_target(&target(d), d));
+			if d > 1 {
+				assert!(!meets_target(&(target(d) + U256::one()), d));
+			}
+		}
+		assert_eq!(hash_difficulty(&U256::zero()), u64::MAX);
+		assert_eq!(hash_difficulty(&U256::one()), u64::MAX);
+		assert_eq!(hash_difficulty(&max), 1);
+
+		// a hash of 0x00000000ffff... is a difficulty 2^32 share, just
+		assert_eq!(hash_difficulty(&(max >> 32)), 1 << 32);
+		assert_eq!(hash_difficulty(&((max >> 32) + U256::one())), (1 << 32) - 1);
+	}
+
+	#[test]
+	fn work() {
+		assert_eq!(expected_work(Algorithm::RandomX, 100), 100.0);
+		assert_eq!(expected_work(Algorithm::ProgPow, 100), 100.0);
+		assert_eq!(expected_work(Algorithm::Cuckoo, 100), 4200.0);
+	}
+
+	#[test]
+	fn graph_weights() {
+		// as in epic's and grin's consensus tests
+		assert_eq!(graph_weight(1, 31), 256 * 31);
+		assert_eq!(graph_weight(1, 32), 512 * 32);
+		assert_eq!(graph_weight(1, 33), 1024 * 33);
+		assert_eq!(graph_weight(YEAR_HEIGHT, 31), 256 * 30);
+		assert_eq!(graph_weight(YEAR_HEIGHT + WEEK_HEIGHT, 31), 256 * 29);
+		assert_eq!(graph_weight(YEAR_HEIGHT + 2 * WEEK_HEIGHT, 31), 256 * 28);
+		assert_eq!(graph_weight(2 * YEAR_HEIGHT, 32), 512 * 31);
+		assert_eq!(graph_weight(2 * YEAR_HEIGHT - 1, 32), 512 * 32);
+		assert_eq!(graph_weight(YEAR_HEIGHT, 32), 512 * 32);
+	}
+
 	#[test]
 	fn proof_hash() {
-		let mut in_nonces: Vec<u64> = [0u64; 42].to_vec(); 
+		let mut in_nonces: Vec<u64> = [0u64; 42].to_vec();
 		let proof = Proof::new(in_nonces.clone(), DEFAULT_MIN_EDGE_BITS);
 		let hash_str = format!("{}", proof.hash());
-		println!("Hash is: {}", hash_str);
 		assert_eq!(&hash_str, "5fa5af8a4c86");
 
 		in_nonces[41] = 23402320128419283;
 		in_nonces[11] = 81239481234781924;
 		let proof = Proof::new(in_nonces.clone(), DEFAULT_MIN_EDGE_BITS);
 		let hash_str = format!("{}", proof.hash());
-		println!("Hash is: {}", hash_str);
 		assert_eq!(&hash_str, "378594bac9a4");
 
 		for i in in_nonces.iter_mut() {
-			*i = std::u64::MAX;
+			*i = u64::MAX;
 		}
 		let proof = Proof::new(in_nonces.clone(), DEFAULT_MIN_EDGE_BITS);
 		let hash_str = format!("{}", proof.hash());
-		println!("Hash is: {}", hash_str);
 		assert_eq!(&hash_str, "99f04aafcbc1");
 	}
 
@@ -263,23 +375,36 @@ mod tests {
 		let mut in_nonces: Vec<u64> = [0u64; 42].to_vec();
 		let proof = Proof::new(in_nonces.clone(), DEFAULT_MIN_EDGE_BITS);
 		let difficulty = proof.to_difficulty(20, 1);
-		println!("Diff is: {}", difficulty);
 		assert_eq!(difficulty, Difficulty::from_num(21240));
+		// weighted by the graph, unscaled it's the hash alone
+		assert_eq!(
+			difficulty.to_num() / graph_weight(20, DEFAULT_MIN_EDGE_BITS),
+			proof.to_difficulty_unscaled().to_num()
+		);
 
 		in_nonces[41] = 23402320128419283;
 		in_nonces[11] = 81239481234781924;
 		let proof = Proof::new(in_nonces.clone(), 31);
 		let difficulty = proof.to_difficulty(120000, 32348);
-		println!("Diff is: {}", difficulty);
 		assert_eq!(difficulty, Difficulty::from_num(36591));
 
 		for i in in_nonces.iter_mut() {
-			*i = std::u64::MAX;
+			*i = u64::MAX;
 		}
 		let proof = Proof::new(in_nonces.clone(), 35);
 		let difficulty = proof.to_difficulty(1300000, 92348);
-		println!("Diff is: {}", difficulty);
 		assert_eq!(difficulty, Difficulty::from_num(296303));
+
+		// secondary proofs are scaled by the header's scaling instead
+		let proof = Proof::new(in_nonces.clone(), SECOND_POW_EDGE_BITS);
+		let unscaled = proof.to_difficulty_unscaled().to_num();
+		assert_eq!(proof.to_difficulty(1300000, 1).to_num(), unscaled);
+		let scaled = proof.to_difficulty(1300000, 92348).to_num();
+		assert_eq!(scaled / 92348, unscaled);
+
+		// the 64-bit proof difficulty agrees with the 256-bit hash one
+		let hash = proof.hash();
+		let wide = hash_difficulty(&hash.to_u256());
+		assert!(wide == unscaled || wide + 1 == unscaled);
 	}
 }
-
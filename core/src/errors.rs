@@ -1,6 +1,10 @@
+use std::error;
+use std::fmt;
 use std::io;
 use std::string;
 
+use crate::header::HeaderError;
+
 #[derive(Debug)]
 pub enum MinerError {
 	/// Occurs when trying to call a plugin function when a
@@ -31,18 +35,106 @@ pub enum MinerError {
 
 	/// Error getting stats or stats not implemented
 	StatsError(String),
+
+	/// The requested miner isn't part of this build
+	MinerNotAvailableError(String),
+
+	/// Reading a plugin, its description or the plugin directory failed,
+	/// with what was being read
+	PluginReadError(String, Box<dyn error::Error + Send + Sync>),
+
+	/// A parameter or job header is invalid, with what it was
+	InvalidParameterError(String, Box<dyn error::Error + Send + Sync>),
+}
+
+impl fmt::Display for MinerError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			MinerError::UnexpectedResultError(code) => {
+				write!(f, "Unexpected result code {} from plugin", code)
+			}
+			MinerError::PluginNotLoadedError(ref message)
+			| MinerError::PluginSymbolNotFoundError(ref message)
+			| MinerError::PluginNotFoundError(ref message)
+			| MinerError::NoPluginsFoundError(ref message)
+			| MinerError::ParameterError(ref message)
+			| MinerError::PluginIOError(ref message)
+			| MinerError::PluginProcessingError(ref message)
+			| MinerError::StatsError(ref message)
+			| MinerError::MinerNotAvailableError(ref message)
+			| MinerError::PluginReadError(ref message, _)
+			| MinerError::InvalidParameterError(ref message, _) => write!(f, "{}", message),
+		}
+	}
+}
+
+impl error::Error for MinerError {
+	fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+		match *self {
+			MinerError::PluginReadError(_, ref e) | MinerError::InvalidParameterError(_, ref e) => Some(&**e),
+			_ => None,
+		}
+	}
+}
+
+impl MinerError {
+	/// Whether trying again later may succeed, as opposed to errors that
+	/// need the configuration or the installation fixed first
+	pub fn is_retryable(&self) -> bool {
+		match *self {
+			MinerError::UnexpectedResultError(_)
+			| MinerError::PluginProcessingError(_)
+			| MinerError::StatsError(_) => true,
+			MinerError::PluginNotLoadedError(_)
+			| MinerError::PluginSymbolNotFoundError(_)
+			| MinerError::PluginNotFoundError(_)
+			| MinerError::NoPluginsFoundError(_)
+			| MinerError::ParameterError(_)
+			| MinerError::PluginIOError(_)
+			| MinerError::MinerNotAvailableError(_)
+			| MinerError::PluginReadError(_, _)
+			| MinerError::InvalidParameterError(_, _) => false,
+		}
+	}
 }
 
 impl From<io::Error> for MinerError {
 	fn from(error: io::Error) -> Self {
-		MinerError::PluginIOError(String::from(format!("Error loading plugin: {}", error)))
+		MinerError::PluginReadError("Error loading plugin".to_owned(), Box::new(error))
 	}
 }
 
 impl From<string::FromUtf8Error> for MinerError {
 	fn from(error: string::FromUtf8Error) -> Self {
-		MinerError::PluginIOError(String::from(
-			format!("Error loading plugin description: {}", error),
-		))
+		MinerError::PluginReadError("Error loading plugin description".to_owned(), Box::new(error))
+	}
+}
+
+impl From<HeaderError> for MinerError {
+	fn from(error: HeaderError) -> Self {
+		MinerError::InvalidParameterError("Invalid job header".to_owned(), Box::new(error))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::error::Error;
+
+	#[test]
+	fn sources() {
+		let e = MinerError::from(io::Error::new(io::ErrorKind::NotFound, "no such file"));
+		assert_eq!(e.to_string(), "Error loading plugin");
+		assert_eq!(e.source().map(|s| s.to_string()), Some("no such file".to_owned()));
+		assert!(!e.is_retryable());
+
+		let e = MinerError::from(HeaderError::OddHexLength(3));
+		assert_eq!(e.to_string(), "Invalid job header");
+		assert_eq!(
+			e.source().map(|s| s.to_string()),
+			Some("Odd number of hex digits (3)".to_owned())
+		);
+
+		assert!(MinerError::StatsError("busy".to_owned()).source().is_none());
 	}
 }
@@ -0,0 +1,344 @@
+// Copyright 2019 The Epic Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The Epic block header as pools hand it out in jobs.
+//!
+//! A job carries the `pre_pow` part of the header: everything up to the
+//! nonce, big-endian, ending with the total difficulty and the secondary
+//! scaling. Miners hash that with the nonce appended.
+
+use std::error;
+use std::fmt;
+
+use crate::difficulty::Hash;
+
+/// Bytes of the fixed fields ahead of the difficulty fields
+const FIXED_SIZE: usize = 2 + 8 + 8 + 6 * 32 + 8 + 8;
+/// Bytes of the secondary scaling closing the pre_pow
+const SCALING_SIZE: usize = 4;
+/// Bytes of a single difficulty field
+const DIFFICULTY_SIZE: usize = 8;
+/// Shortest pre_pow there is, with a single difficulty field
+pub const MIN_PRE_POW_SIZE: usize = FIXED_SIZE + DIFFICULTY_SIZE + SCALING_SIZE;
+
+/// What's wrong with a header
+#[derive(Debug, Clone, PartialEq)]
+pub enum HeaderError {
+	/// A character that isn't a hex digit, at that position
+	InvalidHex(usize),
+	/// Hex strings take two digits per byte
+	OddHexLength(usize),
+	/// Fewer bytes than the fields need
+	TooShort(usize),
+	/// The bytes between the fixed fields and the secondary scaling
+	/// don't make up whole difficulty fields
+	InvalidDifficulty(usize),
+}
+
+impl fmt::Display for HeaderError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			HeaderError::InvalidHex(pos) => write!(f, "Invalid hex digit at position {}", pos),
+			HeaderError::OddHexLength(len) => write!(f, "Odd number of hex digits ({})", len),
+			HeaderError::TooShort(len) => write!(
+				f,
+				"Header of {} bytes is too short, at least {} are needed",
+				len, MIN_PRE_POW_SIZE
+			),
+			HeaderError::InvalidDifficulty(len) => write!(
+				f,
+				"{} bytes of difficulty fields, expected a multiple of {}",
+				len, DIFFICULTY_SIZE
+			),
+		}
+	}
+}
+
+impl error::Error for HeaderError {}
+
+/// An Epic block header
+#[derive(Debug, Clone, PartialEq)]
+pub struct Header {
+	pub version: u16,
+	pub height: u64,
+	/// Seconds since the epoch
+	pub timestamp: i64,
+	pub prev_hash: Hash,
+	pub prev_root: Hash,
+	pub output_root: Hash,
+	pub range_proof_root: Hash,
+	pub kernel_root: Hash,
+	pub total_kernel_offset: Hash,
+	pub output_mmr_size: u64,
+	pub kernel_mmr_size: u64,
+	/// Total difficulty of the chain, one field per difficulty the
+	/// chain tracks
+	pub total_difficulty: Vec<u64>,
+	pub secondary_scaling: u32,
+	/// Not part of the pre_pow, set by the miner
+	pub nonce: u64,
+}
+
+/// Reads the big-endian fields of a header in order
+struct Reader<'a> {
+	bytes: &'a [u8],
+	pos: usize,
+}
+
+impl<'a> Reader<'a> {
+	fn take(&mut self, len: usize) -> &'a [u8] {
+		let bytes = &self.bytes[self.pos..self.pos + len];
+		self.pos += len;
+		bytes
+	}
+
+	fn u16(&mut self) -> u16 {
+		let mut buf = [0; 2];
+		buf.copy_from_slice(self.take(2));
+		u16::from_be_bytes(buf)
+	}
+
+	fn u32(&mut self) -> u32 {
+		let mut buf = [0; 4];
+		buf.copy_from_slice(self.take(4));
+		u32::from_be_bytes(buf)
+	}
+
+	fn u64(&mut self) -> u64 {
+		let mut buf = [0; 8];
+		buf.copy_from_slice(self.take(8));
+		u64::from_be_bytes(buf)
+	}
+
+	fn hash(&mut self) -> Hash {
+		let mut buf = [0; 32];
+		buf.copy_from_slice(self.take(32));
+		Hash::from_bytes(buf)
+	}
+}
+
+impl Header {
+	/// Parses the pre_pow bytes of a job, the nonce is left at 0
+	pub fn from_pre_pow(pre_pow: &[u8]) -> Result<Header, HeaderError> {
+		if pre_pow.len() < MIN_PRE_POW_SIZE {
+			return Err(HeaderError::TooShort(pre_pow.len()));
+		}
+		let difficulty_size = pre_pow.len() - FIXED_SIZE - SCALING_SIZE;
+		if !difficulty_size.is_multiple_of(DIFFICULTY_SIZE) {
+			return Err(HeaderError::InvalidDifficulty(difficulty_size));
+		}
+		let mut r = Reader {
+			bytes: pre_pow,
+			pos: 0,
+		};
+		Ok(Header {
+			version: r.u16(),
+			height: r.u64(),
+			timestamp: r.u64() as i64,
+			prev_hash: r.hash(),
+			prev_root: r.hash(),
+			output_root: r.hash(),
+			range_proof_root: r.hash(),
+			kernel_root: r.hash(),
+			total_kernel_offset: r.hash(),
+			output_mmr_size: r.u64(),
+			kernel_mmr_size: r.u64(),
+			total_difficulty: (0..difficulty_size / DIFFICULTY_SIZE).map(|_| r.u64()).collect(),
+			secondary_scaling: r.u32(),
+			nonce: 0,
+		})
+	}
+
+	/// Parses the hex-encoded pre_pow of a job
+	pub fn from_hex(pre_pow: &str) -> Result<Header, HeaderError> {
+		Header::from_pre_pow(&from_hex(pre_pow)?)
+	}
+
+	/// The pre_pow bytes of the header, without the nonce
+	pub fn pre_pow(&self) -> Vec<u8> {
+		let mut bytes = Vec::with_capacity(
+			FIXED_SIZE + self.total_difficulty.len() * DIFFICULTY_SIZE + SCALING_SIZE,
+		);
+		bytes.extend_from_slice(&self.version.to_be_bytes());
+		bytes.extend_from_slice(&self.height.to_be_bytes());
+		bytes.extend_from_slice(&self.timestamp.to_be_bytes());
+		for hash in &[
+			self.prev_hash,
+			self.prev_root,
+			self.output_root,
+			self.range_proof_root,
+			self.kernel_root,
+			self.total_kernel_offset,
+		] {
+			bytes.extend_from_slice(hash.as_bytes());
+		}
+		bytes.extend_from_slice(&self.output_mmr_size.to_be_bytes());
+		bytes.extend_from_slice(&self.kernel_mmr_size.to_be_bytes());
+		for difficulty in &self.total_difficulty {
+			bytes.extend_from_slice(&difficulty.to_be_bytes());
+		}
+		bytes.extend_from_slice(&self.secondary_scaling.to_be_bytes());
+		bytes
+	}
+
+	/// The pre_pow as hex, the way pools send it
+	pub fn to_hex(&self) -> String {
+		to_hex(&self.pre_pow())
+	}
+
+	/// The bytes that get hashed: the pre_pow followed by the nonce
+	pub fn pow_bytes(&self) -> Vec<u8> {
+		let mut bytes = self.pre_pow();
+		bytes.extend_from_slice(&self.nonce.to_be_bytes());
+		bytes
+	}
+}
+
+impl fmt::Display for Header {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(
+			f,
+			"v{} height {} time {} prev {} difficulty {:?} scaling {}",
+			self.version,
+			self.height,
+			self.timestamp,
+			self.prev_hash,
+			self.total_difficulty,
+			self.secondary_scaling
+		)
+	}
+}
+
+/// Secondary scaling of a pre_pow, its last 4 bytes, without parsing the
+/// rest of it
+pub fn secondary_scaling(pre_pow: &[u8]) -> Result<u32, HeaderError> {
+	if pre_pow.len() < MIN_PRE_POW_SIZE {
+		return Err(HeaderError::TooShort(pre_pow.len()));
+	}
+	let mut buf = [0; 4];
+	buf.copy_from_slice(&pre_pow[pre_pow.len() - SCALING_SIZE..]);
+	Ok(u32::from_be_bytes(buf))
+}
+
+/// Writes the nonce over the 8 bytes at the end of the pre_pow in
+/// pow bytes made with `insert_nonce` or `Header::pow_bytes`, so a solver
+/// can walk through nonces without building the header again
+pub fn set_nonce(pow_bytes: &mut [u8], pre_pow_len: usize, nonce: u64) {
+	pow_bytes[pre_pow_len..pre_pow_len + 8].copy_from_slice(&nonce.to_be_bytes());
+}
+
+/// The pre_pow, the nonce and whatever comes after it, as hashed
+pub fn insert_nonce(pre_pow: &[u8], post_nonce: &[u8], nonce: u64) -> Vec<u8> {
+	let mut bytes = Vec::with_capacity(pre_pow.len() + 8 + post_nonce.len());
+	bytes.extend_from_slice(pre_pow);
+	bytes.extend_from_slice(&nonce.to_be_bytes());
+	bytes.extend_from_slice(post_nonce);
+	bytes
+}
+
+/// Decodes a hex string, with or without a 0x prefix
+pub fn from_hex(hex: &str) -> Result<Vec<u8>, HeaderError> {
+	let digits = hex.trim();
+	let (offset, digits) = match digits.strip_prefix("0x") {
+		Some(rest) => (2, rest),
+		None => (0, digits),
+	};
+	if digits.len() % 2 != 0 {
+		return Err(HeaderError::OddHexLength(digits.len()));
+	}
+	let digit = |pos: usize, c: u8| match c {
+		b'0'..=b'9' => Ok(c - b'0'),
+		b'a'..=b'f' => Ok(c - b'a' + 10),
+		b'A'..=b'F' => Ok(c - b'A' + 10),
+		_ => Err(HeaderError::InvalidHex(offset + pos)),
+	};
+	digits
+		.as_bytes()
+		.chunks(2)
+		.enumerate()
+		.map(|(i, pair)| Ok(digit(2 * i, pair[0])? << 4 | digit(2 * i + 1, pair[1])?))
+		.collect()
+}
+
+/// Encodes bytes as lowercase hex
+pub fn to_hex(bytes: &[u8]) -> String {
+	bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn header() -> Header {
+		Header {
+			version: 2,
+			height: 123_456,
+			timestamp: 1_546_300_800,
+			prev_hash: Hash::from_bytes([1; 32]),
+			prev_root: Hash::from_bytes([2; 32]),
+			output_root: Hash::from_bytes([3; 32]),
+			range_proof_root: Hash::from_bytes([4; 32]),
+			kernel_root: Hash::from_bytes([5; 32]),
+			total_kernel_offset: Hash::from_bytes([6; 32]),
+			output_mmr_size: 1_000,
+			kernel_mmr_size: 900,
+			total_difficulty: vec![1 << 40, 12_345, 7],
+			secondary_scaling: 1_856,
+			nonce: 0,
+		}
+	}
+
+	#[test]
+	fn round_trip() {
+		let h = header();
+		let pre_pow = h.pre_pow();
+		assert_eq!(pre_pow.len(), FIXED_SIZE + 3 * 8 + 4);
+		assert_eq!(&pre_pow[..2], &[0, 2]);
+		assert_eq!(&pre_pow[pre_pow.len() - 4..], &1_856u32.to_be_bytes());
+		assert_eq!(Header::from_pre_pow(&pre_pow), Ok(h.clone()));
+		assert_eq!(Header::from_hex(&h.to_hex()), Ok(h.clone()));
+		assert_eq!(Header::from_hex(&format!("0x{}", h.to_hex().to_uppercase())), Ok(h.clone()));
+		assert_eq!(secondary_scaling(&pre_pow), Ok(1_856));
+
+		let mut with_nonce = h.clone();
+		with_nonce.nonce = 0x0102_0304_0506_0708;
+		let mut pow = with_nonce.pow_bytes();
+		assert_eq!(pow, insert_nonce(&pre_pow, &[], with_nonce.nonce));
+		assert_eq!(&pow[pre_pow.len()..], &[1, 2, 3, 4, 5, 6, 7, 8]);
+		set_nonce(&mut pow, pre_pow.len(), 0);
+		assert_eq!(pow, insert_nonce(&pre_pow, &[], 0));
+		assert_eq!(insert_nonce(&pre_pow, &[9, 9], 0).len(), pre_pow.len() + 10);
+	}
+
+	#[test]
+	fn malformed() {
+		let pre_pow = header().pre_pow();
+		assert_eq!(from_hex("0a0B"), Ok(vec![10, 11]));
+		assert_eq!(from_hex("0a0"), Err(HeaderError::OddHexLength(3)));
+		assert_eq!(from_hex("0a0g"), Err(HeaderError::InvalidHex(3)));
+		assert_eq!(from_hex("0xzz"), Err(HeaderError::InvalidHex(2)));
+		assert_eq!(from_hex("0a0é"), Err(HeaderError::OddHexLength(5)));
+		assert_eq!(Header::from_pre_pow(&[]), Err(HeaderError::TooShort(0)));
+		assert_eq!(
+			Header::from_pre_pow(&pre_pow[..MIN_PRE_POW_SIZE - 1]),
+			Err(HeaderError::TooShort(MIN_PRE_POW_SIZE - 1))
+		);
+		assert_eq!(
+			Header::from_pre_pow(&pre_pow[..pre_pow.len() - 1]),
+			Err(HeaderError::InvalidDifficulty(3 * 8 - 1))
+		);
+		assert_eq!(secondary_scaling(&pre_pow[..10]), Err(HeaderError::TooShort(10)));
+		assert!(Header::from_hex("not a header").is_err());
+	}
+}
@@ -0,0 +1,197 @@
+// Copyright 2019 The Epic Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The job solver threads work on.
+//!
+//! A job never changes once published, a new one replaces it as a whole.
+//! Solvers load it once per iteration without taking a lock, so they
+//! always see all the fields of the same job, and compare generations to
+//! find out whether it was replaced meanwhile.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use arc_swap::ArcSwap;
+
+use crate::header::{self, HeaderError};
+
+/// A job as handed to solvers
+#[derive(Debug, Clone, PartialEq)]
+pub struct Job {
+	/// Counts up with every job published, 0 before the first one
+	pub generation: u64,
+	pub job_id: u32,
+	pub height: u64,
+	/// The part of the header before the nonce
+	pub pre_nonce: Vec<u8>,
+	/// The part of the header after the nonce
+	pub post_nonce: Vec<u8>,
+	/// Only solutions of at least that difficulty are kept
+	pub difficulty: u64,
+}
+
+impl Job {
+	/// Decodes the hex header parts a job comes with
+	pub fn new(
+		job_id: u32,
+		height: u64,
+		pre_nonce: &str,
+		post_nonce: &str,
+		difficulty: u64,
+	) -> Result<Job, HeaderError> {
+		let pre_nonce = header::from_hex(pre_nonce)?;
+		// checks there's a whole header before the nonce
+		header::secondary_scaling(&pre_nonce)?;
+		Ok(Job {
+			generation: 0,
+			job_id: job_id,
+			height: height,
+			pre_nonce: pre_nonce,
+			post_nonce: header::from_hex(post_nonce)?,
+			difficulty: difficulty,
+		})
+	}
+
+	/// The header bytes to hash for that nonce
+	pub fn header(&self, nonce: u64) -> Vec<u8> {
+		header::insert_nonce(&self.pre_nonce, &self.post_nonce, nonce)
+	}
+}
+
+impl Default for Job {
+	fn default() -> Job {
+		Job {
+			generation: 0,
+			job_id: 0,
+			height: 0,
+			pre_nonce: vec![],
+			post_nonce: vec![],
+			difficulty: 1,
+		}
+	}
+}
+
+/// Holds the current job for solver threads
+pub struct JobCell {
+	current: ArcSwap<Job>,
+	generation: AtomicU64,
+}
+
+impl JobCell {
+	pub fn new() -> JobCell {
+		JobCell {
+			current: ArcSwap::from_pointee(Job::default()),
+			generation: AtomicU64::new(0),
+		}
+	}
+
+	/// Replaces the current job, returns its generation
+	pub fn publish(&self, mut job: Job) -> u64 {
+		job.generation = self.generation.load(Ordering::Relaxed) + 1;
+		let generation = job.generation;
+		self.current.store(Arc::new(job));
+		// after the store, so whoever sees the generation finds the job
+		self.generation.store(generation, Ordering::Release);
+		generation
+	}
+
+	/// The current job
+	pub fn load(&self) -> Arc<Job> {
+		self.current.load_full()
+	}
+
+	/// Generation of the current job, cheap enough to check every
+	/// iteration
+	pub fn generation(&self) -> u64 {
+		self.generation.load(Ordering::Acquire)
+	}
+
+	/// Whether the job is still the current one
+	pub fn is_current(&self, job: &Job) -> bool {
+		job.generation == self.generation()
+	}
+
+	/// Whether results of the job are no use anymore, which is once a job
+	/// for another height came in. Jobs at the same height only change
+	/// what's mined next
+	pub fn is_stale(&self, job: &Job) -> bool {
+		!self.is_current(job) && self.current.load().height != job.height
+	}
+}
+
+impl Default for JobCell {
+	fn default() -> JobCell {
+		JobCell::new()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::thread;
+
+	fn job(job_id: u32, height: u64) -> Job {
+		let pre_nonce = header::to_hex(&vec![height as u8; header::MIN_PRE_POW_SIZE]);
+		Job::new(job_id, height, &pre_nonce, "", 1).unwrap()
+	}
+
+	#[test]
+	fn generations() {
+		let cell = JobCell::new();
+		let first = cell.load();
+		assert_eq!(first.generation, 0);
+		assert!(cell.is_current(&first));
+
+		assert_eq!(cell.publish(job(1, 10)), 1);
+		assert!(!cell.is_current(&first));
+		assert!(cell.is_stale(&first));
+		let second = cell.load();
+		assert_eq!((second.generation, second.job_id, second.height), (1, 1, 10));
+
+		// a new job at the same height replaces it without making it stale
+		assert_eq!(cell.publish(job(2, 10)), 2);
+		assert!(!cell.is_current(&second));
+		assert!(!cell.is_stale(&second));
+		assert_eq!(second.header(7).len(), header::MIN_PRE_POW_SIZE + 8);
+
+		assert!(Job::new(1, 1, "00", "", 1).is_err());
+		let pre_nonce = header::to_hex(&second.pre_nonce);
+		assert!(Job::new(1, 1, &pre_nonce, "zz", 1).is_err());
+		assert!(Job::new(1, 1, &pre_nonce, "0102", 1).is_ok());
+	}
+
+	#[test]
+	fn consistent_snapshots() {
+		let cell = Arc::new(JobCell::new());
+		let reader = {
+			let cell = cell.clone();
+			thread::spawn(move || {
+				let mut last = 0;
+				while last < 200 {
+					let job = cell.load();
+					// every field comes from the same job
+					assert_eq!(job.job_id as u64, job.generation);
+					assert_eq!(job.height, job.generation);
+					assert!(job.pre_nonce.iter().all(|&b| b == job.height as u8));
+					assert!(job.generation >= last);
+					last = job.generation;
+				}
+			})
+		};
+		for i in 1..=200 {
+			cell.publish(job(i, i as u64));
+		}
+		reader.join().unwrap();
+	}
+}
@@ -3,22 +3,40 @@ extern crate serde;
 extern crate serde_derive;
 extern crate rand;
 extern crate byteorder;
+extern crate bigint;
+extern crate blake2_rfc as blake2;
+extern crate arc_swap;
+extern crate libc;
 
 //extern crate epic_miner_util as util;
 
 pub mod errors;
 pub mod types;
 pub mod config;
+pub mod cpu;
+pub mod difficulty;
+pub mod header;
+pub mod job;
 pub mod miner;
+pub mod registry;
 pub mod util;
 
 pub use errors::MinerError;
-pub use miner::Miner;
+pub use header::{Header, HeaderError};
+pub use job::{Job, JobCell};
+pub use miner::{next_control_message, EventSender, Miner, SolverStates};
+pub use registry::{MinerEntry, Registry};
 pub use types::{
+    Backend,
+    Capabilities,
+    DeviceInfo,
+    ParamInfo,
     Stats,
     Solution,
+    SolverState,
     Algorithm,
     AlgorithmParams,
     ControlMessage,
+    MinerEvent,
     JobSharedData,
     JobSharedDataType};
\ No newline at end of file
@@ -1,11 +1,21 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, RwLock};
+use std::time::{Duration, Instant};
+
 use crate::config::MinerConfig;
 use crate::errors::MinerError;
-use crate::types::{Solution, Stats};
+use crate::types::{Capabilities, ControlMessage, MinerEvent, Solution, SolverState, Stats};
+
+/// Solvers push their stats at most this often, solutions go out right away
+const STATS_EVENT_INTERVAL: Duration = Duration::from_secs(1);
 
 pub trait Miner: Send + Sync {
 	/// Creates a new instance of a CuckooMiner with the given configuration.
-	/// One PluginConfig per device
-	fn new(configs: &MinerConfig) -> Self;
+	/// One PluginConfig per device. Fails when the configuration can't be
+	/// mined with, say plugins that can't be read
+	fn new(configs: &MinerConfig) -> Result<Self, MinerError>
+	where
+		Self: Sized;
 
 	/// An asynchronous -esque version of the plugin miner, which takes
 	/// parts of the header and the target difficulty as input, and begins
@@ -25,12 +35,30 @@ pub trait Miner: Send + Sync {
 		difficulty: u64,
 	) -> Result<(), MinerError>;
 
+	/// Reports the supported algorithms, the devices found on this machine
+	/// and the configurable parameters, without starting anything
+	fn capabilities(_configs: &MinerConfig) -> Result<Capabilities, MinerError>
+	where
+		Self: Sized,
+	{
+		Ok(Capabilities::default())
+	}
+
 	/// Starts solvers, ready for jobs via job control
 	fn start_solvers(&mut self) -> Result<(), MinerError>;
 
 	/// get stats for all running solvers
 	fn get_stats(&self) -> Result<Vec<Stats>, MinerError>;
 
+	/// Asks the miner to push solutions and stats updates into `tx` the
+	/// moment solvers produce them, instead of waiting for `get_solutions`
+	/// and `get_stats` to be polled. Needs to be called before
+	/// `start_solvers`. Returns false if the miner only supports polling,
+	/// which is the default.
+	fn subscribe(&mut self, _tx: mpsc::Sender<MinerEvent>) -> bool {
+		false
+	}
+
 	/// Returns solutions if currently waiting.
 	fn get_solutions(&self) -> Option<Vec<Solution>>;
 
@@ -53,5 +81,155 @@ pub trait Miner: Send + Sync {
 	/// block until solvers have all exited
 	fn wait_for_solver_shutdown(&self);
 
+	/// Pauses a single solver instance, it stays paused across new jobs
+	/// and `resume_solvers` until `resume_solver` is called
+	fn pause_solver(&self, instance: usize) -> Result<(), MinerError> {
+		Err(MinerError::ParameterError(format!(
+			"Pausing solver instance {} isn't supported by this miner",
+			instance
+		)))
+	}
+
+	/// Resumes a solver instance paused with `pause_solver`
+	fn resume_solver(&self, instance: usize) -> Result<(), MinerError> {
+		Err(MinerError::ParameterError(format!(
+			"Resuming solver instance {} isn't supported by this miner",
+			instance
+		)))
+	}
+
+	/// Stops a single solver instance for good, the others keep going
+	fn remove_solver(&self, instance: usize) -> Result<(), MinerError> {
+		Err(MinerError::ParameterError(format!(
+			"Removing solver instance {} isn't supported by this miner",
+			instance
+		)))
+	}
+
 	fn add_epoch(&mut self, _start_height: u64, _end_height: u64, _seed: [u8; 32]) {}
+
+	/// Returns (start height, end height) of the epochs whose dataset
+	/// finished loading since the last call
+	fn get_loaded_epochs(&self) -> Vec<(u64, u64)> {
+		vec![]
+	}
+}
+
+/// Bookkeeping behind `Miner::pause_solver`, `resume_solver` and
+/// `remove_solver`, so the miners know which instances to leave alone
+pub struct SolverStates {
+	states: RwLock<Vec<SolverState>>,
+	/// whether the solvers as a whole are paused, see `Miner::pause_solvers`
+	paused: AtomicBool,
+}
+
+impl SolverStates {
+	/// All enabled, solvers start out paused until the first job
+	pub fn new(count: usize) -> SolverStates {
+		SolverStates {
+			states: RwLock::new(vec![SolverState::Enabled; count]),
+			paused: AtomicBool::new(true),
+		}
+	}
+
+	/// Moves an instance to a new state, removed instances can't come back
+	pub fn set(&self, instance: usize, state: SolverState) -> Result<(), MinerError> {
+		let mut states = self.states.write().unwrap();
+		match states.get(instance) {
+			None => Err(MinerError::ParameterError(format!(
+				"There's no solver instance {}",
+				instance
+			))),
+			Some(&SolverState::Removed) => Err(MinerError::ParameterError(format!(
+				"Solver instance {} was removed",
+				instance
+			))),
+			Some(_) => {
+				states[instance] = state;
+				Ok(())
+			}
+		}
+	}
+
+	pub fn is_enabled(&self, instance: usize) -> bool {
+		self.states.read().unwrap().get(instance) == Some(&SolverState::Enabled)
+	}
+
+	pub fn set_paused(&self, paused: bool) {
+		self.paused.store(paused, Ordering::Relaxed);
+	}
+
+	pub fn is_paused(&self) -> bool {
+		self.paused.load(Ordering::Relaxed)
+	}
+}
+
+/// Solver thread side of `Miner::subscribe`, one per solver
+pub struct EventSender {
+	tx: Option<mpsc::Sender<MinerEvent>>,
+	last_stats: Option<Instant>,
+}
+
+impl EventSender {
+	pub fn new(tx: Option<mpsc::Sender<MinerEvent>>) -> EventSender {
+		EventSender {
+			tx: tx,
+			last_stats: None,
+		}
+	}
+
+	/// Whether there's a subscriber at all
+	pub fn is_subscribed(&self) -> bool {
+		self.tx.is_some()
+	}
+
+	/// Pushes the solution to the subscriber, hands it back if there's
+	/// none so it can be queued for `get_solutions`
+	pub fn solution(&self, solution: Solution) -> Result<(), Solution> {
+		match self.tx {
+			Some(ref tx) => {
+				let _ = tx.send(MinerEvent::Solution(solution));
+				Ok(())
+			}
+			None => Err(solution),
+		}
+	}
+
+	/// Pushes the stats to the subscriber, unless the last ones went out
+	/// less than a second ago
+	pub fn stats(&mut self, instance: usize, stats: &Stats) {
+		if let Some(last) = self.last_stats {
+			if last.elapsed() < STATS_EVENT_INTERVAL {
+				return;
+			}
+		}
+		self.stats_now(instance, stats);
+	}
+
+	/// Pushes the stats to the subscriber right away, for changes that
+	/// shouldn't wait, like a solver erroring
+	pub fn stats_now(&mut self, instance: usize, stats: &Stats) {
+		if let Some(ref tx) = self.tx {
+			let _ = tx.send(MinerEvent::Stats(instance, Box::new(stats.clone())));
+			self.last_stats = Some(Instant::now());
+		}
+	}
+}
+
+/// Next message for a solver loop. With `wait`, for a paused solver or
+/// one with nothing to work on, blocks until one comes in so the thread
+/// sleeps instead of polling, otherwise only picks up what's there. A
+/// miner gone away counts as `Stop`
+pub fn next_control_message(
+	rx: &mpsc::Receiver<ControlMessage>,
+	wait: bool,
+) -> Option<ControlMessage> {
+	if wait {
+		return Some(rx.recv().unwrap_or(ControlMessage::Stop));
+	}
+	match rx.try_recv() {
+		Ok(message) => Some(message),
+		Err(mpsc::TryRecvError::Empty) => None,
+		Err(mpsc::TryRecvError::Disconnected) => Some(ControlMessage::Stop),
+	}
 }
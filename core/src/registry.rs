@@ -0,0 +1,177 @@
+//! Registry of the miners compiled into a build. Each miner crate adds
+//! itself with a constructor and some metadata, so callers can build
+//! miners by name or algorithm without knowing which crates are linked.
+
+use crate::config::MinerConfig;
+use crate::errors::MinerError;
+use crate::miner::Miner;
+use crate::types::{Algorithm, Capabilities};
+
+/// A miner implementation that can be built by name
+#[derive(Clone)]
+pub struct MinerEntry {
+	/// Name to look the miner up by, like "randomx"
+	pub name: &'static str,
+	pub algorithm: Algorithm,
+	pub description: &'static str,
+	/// Builds the miner, see `Miner::new`, failing instead of panicking
+	/// when what it needs can't be loaded
+	pub build: fn(&MinerConfig) -> Result<Box<dyn Miner>, MinerError>,
+	/// See `Miner::capabilities`
+	pub capabilities: fn(&MinerConfig) -> Result<Capabilities, MinerError>,
+}
+
+/// A miner left out of this build
+#[derive(Clone)]
+struct MissingEntry {
+	name: &'static str,
+	algorithm: Algorithm,
+	/// cargo features any of which would bring it in
+	features: &'static [&'static str],
+}
+
+impl MissingEntry {
+	fn error(&self) -> MinerError {
+		let features: Vec<String> = self
+			.features
+			.iter()
+			.map(|f| format!("--features {}", f))
+			.collect();
+		MinerError::MinerNotAvailableError(format!(
+			"The {} miner ({:?}) is not compiled in, rebuild with {}",
+			self.name,
+			self.algorithm,
+			features.join(" or ")
+		))
+	}
+}
+
+#[derive(Clone, Default)]
+pub struct Registry {
+	entries: Vec<MinerEntry>,
+	missing: Vec<MissingEntry>,
+}
+
+impl Registry {
+	pub fn new() -> Registry {
+		Registry::default()
+	}
+
+	pub fn register(&mut self, entry: MinerEntry) {
+		self.entries.push(entry);
+	}
+
+	/// Records a miner this build doesn't include and the cargo features
+	/// that would, so looking it up gives a useful error
+	pub fn register_missing(
+		&mut self,
+		name: &'static str,
+		algorithm: Algorithm,
+		features: &'static [&'static str],
+	) {
+		self.missing.push(MissingEntry {
+			name: name,
+			algorithm: algorithm,
+			features: features,
+		});
+	}
+
+	/// Miners compiled in, in registration order
+	pub fn entries(&self) -> &[MinerEntry] {
+		&self.entries
+	}
+
+	/// Errors for the miners left out of this build
+	pub fn missing(&self) -> Vec<MinerError> {
+		self.missing.iter().map(|m| m.error()).collect()
+	}
+
+	pub fn get(&self, name: &str) -> Result<&MinerEntry, MinerError> {
+		if let Some(entry) = self.entries.iter().find(|e| e.name == name) {
+			return Ok(entry);
+		}
+		match self.missing.iter().find(|m| m.name == name) {
+			Some(m) => Err(m.error()),
+			None => Err(MinerError::MinerNotAvailableError(format!(
+				"Unknown miner {}",
+				name
+			))),
+		}
+	}
+
+	/// The miner for the algorithm, the first one registered if several are
+	pub fn for_algorithm(&self, algorithm: Algorithm) -> Result<&MinerEntry, MinerError> {
+		if let Some(entry) = self.entries.iter().find(|e| e.algorithm == algorithm) {
+			return Ok(entry);
+		}
+		match self.missing.iter().find(|m| m.algorithm == algorithm) {
+			Some(m) => Err(m.error()),
+			None => Err(MinerError::MinerNotAvailableError(format!(
+				"No miner for {:?}",
+				algorithm
+			))),
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::types::{MinerEvent, Solution, Stats};
+	use std::sync::mpsc;
+
+	struct NullMiner;
+
+	impl Miner for NullMiner {
+		fn new(_configs: &MinerConfig) -> Result<Self, MinerError> {
+			Ok(NullMiner)
+		}
+		fn notify(&mut self, _: u32, _: u64, _: &str, _: &str, _: u64) -> Result<(), MinerError> {
+			Ok(())
+		}
+		fn start_solvers(&mut self) -> Result<(), MinerError> {
+			Ok(())
+		}
+		fn subscribe(&mut self, _tx: mpsc::Sender<MinerEvent>) -> bool {
+			false
+		}
+		fn get_stats(&self) -> Result<Vec<Stats>, MinerError> {
+			Ok(vec![])
+		}
+		fn get_solutions(&self) -> Option<Vec<Solution>> {
+			None
+		}
+		fn stop_solvers(&self) {}
+		fn pause_solvers(&self) {}
+		fn resume_solvers(&self) {}
+		fn wait_for_solver_shutdown(&self) {}
+	}
+
+	#[test]
+	fn lookup() {
+		let mut registry = Registry::new();
+		registry.register(MinerEntry {
+			name: "null",
+			algorithm: Algorithm::RandomX,
+			description: "does nothing",
+			build: |config| Ok(Box::new(NullMiner::new(config)?)),
+			capabilities: NullMiner::capabilities,
+		});
+		registry.register_missing("progpow", Algorithm::ProgPow, &["opencl", "cuda"]);
+
+		let entry = registry.get("null").unwrap();
+		let mut miner = (entry.build)(&MinerConfig::default()).unwrap();
+		assert!(miner.start_solvers().is_ok());
+		assert_eq!(registry.for_algorithm(Algorithm::RandomX).unwrap().name, "null");
+
+		match registry.for_algorithm(Algorithm::ProgPow) {
+			Err(MinerError::MinerNotAvailableError(e)) => assert_eq!(
+				e,
+				"The progpow miner (ProgPow) is not compiled in, rebuild with --features opencl or --features cuda"
+			),
+			_ => panic!("progpow shouldn't be available"),
+		}
+		assert!(registry.get("cuckoo").is_err());
+		assert!(registry.for_algorithm(Algorithm::Cuckoo).is_err());
+	}
+}
@@ -1,10 +1,15 @@
 
+use serde::ser::{Serialize, SerializeStruct, Serializer};
 use std::ffi::CString;
-use std::sync::{Arc, RwLock};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use crate::cpu;
+use crate::job::JobCell;
 
 const MAX_NAME_LEN: usize = 256;
 
-pub type JobSharedDataType = Arc<RwLock<JobSharedData>>;
+pub type JobSharedDataType = Arc<JobSharedData>;
 
 #[derive(Copy, Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum Algorithm {
@@ -36,7 +41,23 @@ pub enum ControlMessage {
 	/// Solver reporting stopped
 	SolverStopped(usize),
 
+	/// A new dataset is in use, wakes up solvers waiting for one
 	SeedChanged,
+
+	/// Tear the solver down and load it again, sent when a run stalls
+	Restart,
+}
+
+/// Whether a solver instance was taken out of mining at runtime
+#[derive(Copy, Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SolverState {
+	#[default]
+	Enabled,
+	/// paused on its own until resumed, see `Miner::pause_solver`
+	Disabled,
+	/// stopped for good, see `Miner::remove_solver`
+	Removed,
 }
 
 #[derive(Clone)]
@@ -52,6 +73,17 @@ pub struct Stats {
 	pub last_end_time: u64,
 	pub last_solution_time: u64,
 	pub hashes_per_sec: u64,
+	pub state: SolverState,
+	/// times the solver instance was brought back up after erroring or
+	/// stalling
+	pub restarts: u32,
+	/// bytes of memory the solver instance holds, 0 when unknown
+	pub memory: u64,
+	/// CPUs the solver thread may run on, empty when unknown
+	pub affinity: Vec<usize>,
+	/// settings given up on to keep mining, in the order they were, empty
+	/// if none
+	pub fallback: String,
 }
 
 impl Default for Stats {
@@ -68,6 +100,11 @@ impl Default for Stats {
 			last_end_time: 0,
 			last_solution_time: 0,
 			hashes_per_sec: 0,
+			state: SolverState::Enabled,
+			restarts: 0,
+			memory: 0,
+			affinity: vec![],
+			fallback: String::new(),
 		}
 	}
 }
@@ -115,14 +152,75 @@ impl Stats {
 			self.device_name[i] = c_vec[i];
 		}
 	}
+
+	/// set error reason, replacing the last one and cut short to fit
+	pub fn set_error_reason(&mut self, reason: &str) {
+		self.error_reason = [0; MAX_NAME_LEN];
+		for (i, &b) in reason
+			.as_bytes()
+			.iter()
+			.filter(|&&b| b != 0)
+			.take(MAX_NAME_LEN - 1)
+			.enumerate()
+		{
+			self.error_reason[i] = b;
+		}
+	}
+}
+
+/// Serialized by hand, the fixed size name buffers go out as strings
+impl Serialize for Stats {
+	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+	where
+		S: Serializer,
+	{
+		let mut state = serializer.serialize_struct("Stats", 16)?;
+		state.serialize_field("device_id", &self.device_id)?;
+		state.serialize_field("edge_bits", &self.edge_bits)?;
+		state.serialize_field("plugin_name", &self.get_plugin_name())?;
+		state.serialize_field("device_name", &self.get_device_name())?;
+		state.serialize_field("has_errored", &self.has_errored)?;
+		state.serialize_field("error_reason", &self.get_error_reason())?;
+		state.serialize_field("iterations", &self.iterations)?;
+		state.serialize_field("last_start_time", &self.last_start_time)?;
+		state.serialize_field("last_end_time", &self.last_end_time)?;
+		state.serialize_field("last_solution_time", &self.last_solution_time)?;
+		state.serialize_field("hashes_per_sec", &self.hashes_per_sec)?;
+		state.serialize_field("state", &self.state)?;
+		state.serialize_field("restarts", &self.restarts)?;
+		state.serialize_field("memory", &self.memory)?;
+		state.serialize_field("affinity", &cpu::format_cpulist(&self.affinity))?;
+		state.serialize_field("fallback", &self.fallback)?;
+		state.end()
+	}
 }
 
+impl fmt::Debug for Stats {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.debug_struct("Stats")
+			.field("device_id", &self.device_id)
+			.field("plugin_name", &self.get_plugin_name())
+			.field("device_name", &self.get_device_name())
+			.field("has_errored", &self.has_errored)
+			.field("iterations", &self.iterations)
+			.field("hashes_per_sec", &self.hashes_per_sec)
+			.field("state", &self.state)
+			.field("restarts", &self.restarts)
+			.field("memory", &self.memory)
+			.field("affinity", &cpu::format_cpulist(&self.affinity))
+			.field("fallback", &self.fallback)
+			.finish()
+	}
+}
+
+/// A solution found by a solver: job id, nonce, proof and the index of
+/// the solver instance that found it
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Solution(u64, u64, AlgorithmParams);
+pub struct Solution(u64, u64, AlgorithmParams, usize);
 
 impl Solution {
-	pub fn new(id: u64, nonce: u64, algo_params: AlgorithmParams) -> Self {
-		Solution(id, nonce, algo_params)
+	pub fn new(id: u64, nonce: u64, algo_params: AlgorithmParams, instance: usize) -> Self {
+		Solution(id, nonce, algo_params, instance)
 	}
 
 	pub fn get_id(&self) -> u64 {
@@ -136,58 +234,110 @@ impl Solution {
 	pub fn get_algorithm_params(&self) -> AlgorithmParams {
 		self.2.clone()
 	}
+
+	/// Index of the solver instance (and its stats) that found the solution
+	pub fn get_instance(&self) -> usize {
+		self.3
+	}
 }
 
-/// Data intended to be shared across threads
-pub struct JobSharedData {
-	/// ID of the current running job (not currently used)
-	pub job_id: u32,
+/// How a device is driven
+#[derive(Copy, Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum Backend {
+	Cpu,
+	OpenCL,
+	Cuda,
+}
 
-	/// block height of current running job
-	pub height: u64,
+/// A device solvers can run on
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceInfo {
+	/// index to use in the configuration
+	pub id: u32,
+	pub name: String,
+	pub backend: Backend,
+	/// memory in bytes, if known
+	pub memory: Option<u64>,
+	/// cores or compute units, if known
+	pub compute_units: Option<u32>,
+	/// instruction set or driver features relevant for mining
+	pub features: Vec<String>,
+	/// parameters specific to this device, with their defaults
+	pub params: Vec<ParamInfo>,
+}
+
+/// A configurable parameter and its default value
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ParamInfo {
+	pub name: String,
+	pub default: String,
+}
+
+impl ParamInfo {
+	pub fn new<T: ToString>(name: &str, default: T) -> ParamInfo {
+		ParamInfo {
+			name: name.to_owned(),
+			default: default.to_string(),
+		}
+	}
+}
 
-	/// The part of the header before the nonce, which this
-	/// module will mutate in search of a solution
-	pub pre_nonce: String,
+/// What a miner can do on this machine, see `Miner::capabilities`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Capabilities {
+	pub algorithms: Vec<Algorithm>,
+	pub devices: Vec<DeviceInfo>,
+	/// parameters of the miner as a whole, with their defaults
+	pub params: Vec<ParamInfo>,
+}
 
-	/// The part of the header after the nonce
-	pub post_nonce: String,
+/// Pushed by a miner to its subscriber as soon as a solver produces it,
+/// see `Miner::subscribe`
+#[derive(Debug, Clone)]
+pub enum MinerEvent {
+	/// A solution meeting the target difficulty
+	Solution(Solution),
+	/// Fresh stats of the solver instance, boxed as they're far bigger
+	/// than a solution
+	Stats(usize, Box<Stats>),
+}
 
-	/// The target difficulty. Only solutions >= this
-	/// target will be put into the output queue
-	pub difficulty: u64,
+/// Data intended to be shared across threads. The job is swapped without
+/// locking, solutions and the stats of each solver have locks of their
+/// own so solvers don't wait on each other
+pub struct JobSharedData {
+	/// The job being mined
+	pub job: JobCell,
 
 	/// Output solutions
-	pub solutions: Vec<Solution>,
+	pub solutions: Mutex<Vec<Solution>>,
 
-	/// Current stats
-	pub stats: Vec<Stats>,
+	/// Current stats, per solver instance
+	pub stats: Vec<Mutex<Stats>>,
 }
 
 impl Default for JobSharedData {
 	fn default() -> JobSharedData {
-		JobSharedData {
-			job_id: 0,
-			height: 0,
-			pre_nonce: String::from(""),
-			post_nonce: String::from(""),
-			difficulty: 0,
-			solutions: Vec::new(),
-			stats: vec![],
-		}
+		JobSharedData::new(0)
 	}
 }
 
 impl JobSharedData {
 	pub fn new(num_solvers: usize) -> JobSharedData {
 		JobSharedData {
-			job_id: 0,
-			height: 0,
-			pre_nonce: String::from(""),
-			post_nonce: String::from(""),
-			difficulty: 1,
-			solutions: Vec::new(),
-			stats: vec![Stats::default(); num_solvers],
+			job: JobCell::new(),
+			solutions: Mutex::new(Vec::new()),
+			stats: (0..num_solvers).map(|_| Mutex::new(Stats::default())).collect(),
 		}
 	}
+
+	/// Takes the solutions found so far
+	pub fn take_solutions(&self) -> Vec<Solution> {
+		self.solutions.lock().unwrap().drain(..).collect()
+	}
+
+	/// Copies the stats of all solver instances
+	pub fn stats(&self) -> Vec<Stats> {
+		self.stats.iter().map(|s| s.lock().unwrap().clone()).collect()
+	}
 }
@@ -24,4 +24,4 @@
 pub mod types;
 pub mod read;
 
-pub use self::read::read_configs;
\ No newline at end of file
+pub use self::read::{param_defaults, read_configs, resolve_plugin_dir};
\ No newline at end of file
@@ -3,16 +3,15 @@ use std::path::PathBuf;
 
 use core::config::EpicMinerPluginConfig;
 use core::errors::MinerError;
+use core::ParamInfo;
+use plugin::SolverParams;
 use util::LOGGER;
 use crate::PluginConfig;
 
-/// Transforms a set of epic-miner plugin configs to cuckoo-miner plugins configs
-pub fn read_configs(
-	plugin_dir: Option<PathBuf>,
-	conf_in: Vec<EpicMinerPluginConfig>,
-) -> Result<Vec<PluginConfig>, MinerError> {
-	// Resolve a final plugin path, either config-provided or from the current executable path
-	let plugin_dir_absolute_path = match plugin_dir {
+/// Resolves the final plugin dir, either config-provided or next to the
+/// current executable
+pub fn resolve_plugin_dir(plugin_dir: Option<PathBuf>) -> Result<PathBuf, MinerError> {
+	match plugin_dir {
 		Some(path) => {
 			let absolute_path = path.canonicalize().map_err(MinerError::from);
 			if let Ok(path) = &absolute_path {
@@ -44,7 +43,15 @@ pub fn read_configs(
 			};
 			absolute_path
 		}
-	}?;
+	}
+}
+
+/// Transforms a set of epic-miner plugin configs to cuckoo-miner plugins configs
+pub fn read_configs(
+	plugin_dir: Option<PathBuf>,
+	conf_in: Vec<EpicMinerPluginConfig>,
+) -> Result<Vec<PluginConfig>, MinerError> {
+	let plugin_dir_absolute_path = resolve_plugin_dir(plugin_dir)?;
 
 	let mut return_vec = vec![];
 	for conf in conf_in {
@@ -68,6 +75,29 @@ pub fn read_configs(
 	Ok(return_vec)
 }
 
+/// Parameters that can be set in a plugin's config, with the plugin's
+/// defaults. Names as accepted by `resolve_param`
+pub fn param_defaults(params: &SolverParams) -> Vec<ParamInfo> {
+	vec![
+		ParamInfo::new("nthreads", params.nthreads),
+		ParamInfo::new("ntrims", params.ntrims),
+		ParamInfo::new("cpuload", params.cpuload as u32),
+		ParamInfo::new("device", params.device),
+		ParamInfo::new("blocks", params.blocks),
+		ParamInfo::new("tbp", params.tpb),
+		ParamInfo::new("expand", params.expand),
+		ParamInfo::new("genablocks", params.genablocks),
+		ParamInfo::new("genatpb", params.genatpb),
+		ParamInfo::new("genbtpb", params.genbtpb),
+		ParamInfo::new("trimtpb", params.trimtpb),
+		ParamInfo::new("tailtpb", params.tailtpb),
+		ParamInfo::new("recoverblocks", params.recoverblocks),
+		ParamInfo::new("recovertpb", params.recovertpb),
+		ParamInfo::new("platform", params.platform),
+		ParamInfo::new("edge_bits", params.edge_bits),
+	]
+}
+
 /// resolve a read parameter to a solver param, (or not if it isn't found)
 fn resolve_param(config: &mut PluginConfig, name: &str, value: u32) {
 	match name {
@@ -16,7 +16,7 @@
 
 use plugin::SolverParams;
 use std::path::PathBuf;
-use std::{fmt, io};
+use std::{error, fmt, io};
 use crate::PluginLibrary;
 
 use core::errors::MinerError;
@@ -96,6 +96,14 @@ impl fmt::Display for ConfigError {
 	}
 }
 
+impl error::Error for ConfigError {}
+
+impl From<ConfigError> for MinerError {
+	fn from(error: ConfigError) -> MinerError {
+		MinerError::InvalidParameterError("Invalid plugin configuration".to_owned(), Box::new(error))
+	}
+}
+
 impl From<io::Error> for ConfigError {
 	fn from(error: io::Error) -> ConfigError {
 		ConfigError::FileIOError(
@@ -36,8 +36,6 @@ extern crate serde;
 extern crate serde_derive;
 extern crate serde_json;
 
-extern crate blake2_rfc as blake2;
-extern crate byteorder;
 extern crate crypto;
 extern crate regex;
 
@@ -58,4 +56,4 @@ mod miner;
 pub use config::types::PluginConfig;
 pub use cuckoo_sys::ffi::PluginLibrary;
 pub use error::error::CuckooMinerError;
-pub use miner::miner::CuckooMiner;
+pub use miner::miner::{register, CuckooMiner};
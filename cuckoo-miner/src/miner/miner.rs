@@ -17,32 +17,104 @@
 //! return any resulting solutions.
 
 use std::ptr::NonNull;
-use std::sync::{mpsc, Arc, RwLock};
+use std::sync::atomic::Ordering;
+use std::sync::{mpsc, Arc};
 use std::{thread, time};
 use crate::util::LOGGER;
 
 use crate::config;
 
-use config::types::PluginConfig;
+use config::types::{PluginConfig, SO_SUFFIX};
 use crate::miner::types::{JobSharedData, JobSharedDataType, SolverInstance};
 
-use crate::miner::util;
-use crate::miner::consensus::Proof;
-use plugin::{SolverCtxWrapper, SolverSolutions, Solution};
+use core::util;
+use core::difficulty::Proof;
+use plugin::{SolverCtxWrapper, SolverSolutions, SolverStats, Solution};
 use crate::PluginLibrary;
 
-use core::config::MinerConfig;
+use core::config::{CpuConfig, MinerConfig, WatchdogConfig};
+use core::cpu;
 use core::{
+	Algorithm,
+	Backend,
+	Capabilities,
+	DeviceInfo,
+	EventSender,
 	Miner,
+	MinerEvent,
 	Stats,
 	ControlMessage,
+	MinerEntry,
+	Job,
 	MinerError,
+	next_control_message,
+	Registry,
 	Solution as CrSolution,
+	SolverState,
+	SolverStates,
 	AlgorithmParams};
 
 
 
-	
+
+/// Adds the Cuckoo miner to the registry
+pub fn register(registry: &mut Registry) {
+	registry.register(MinerEntry {
+		name: "cuckoo",
+		algorithm: Algorithm::Cuckoo,
+		description: "Cuckoo cycle miner running cuckoo-miner plugins",
+		build: |config| Ok(Box::new(CuckooMiner::new(config)?)),
+		capabilities: CuckooMiner::capabilities,
+	});
+}
+
+/// How a run of a loaded plugin ended
+enum SolverExit {
+	/// told to stop, for good
+	Stopped,
+	/// the plugin reported an error
+	Errored,
+	/// the watchdog found a run taking too long
+	Stalled,
+}
+
+/// When to bring a solver instance back up after it went down, see
+/// `WatchdogConfig`. An instance that stayed up longer than the stall
+/// timeout was healthy, the count and with it the backoff start over
+struct Supervisor {
+	watchdog: WatchdogConfig,
+	/// restarts since the instance was last healthy
+	restarts: u32,
+}
+
+impl Supervisor {
+	fn new(watchdog: WatchdogConfig) -> Supervisor {
+		Supervisor {
+			watchdog: watchdog,
+			restarts: 0,
+		}
+	}
+
+	/// Whether an instance up that long counts as healthy
+	fn healthy(watchdog: &WatchdogConfig, uptime: time::Duration) -> bool {
+		uptime > time::Duration::from_secs(watchdog.stall_timeout)
+	}
+
+	/// The instance went down after being up that long. Seconds to wait
+	/// before restarting it, none to give up on it
+	fn next_restart(&mut self, uptime: time::Duration) -> Option<u64> {
+		if Supervisor::healthy(&self.watchdog, uptime) {
+			self.restarts = 0;
+		}
+		if self.restarts >= self.watchdog.max_restarts {
+			return None;
+		}
+		let delay = self.watchdog.backoff.saturating_mul(1 << self.restarts.min(16));
+		self.restarts += 1;
+		Some(delay)
+	}
+}
+
 /// An instance of a miner, which loads a cuckoo-miner plugin
 /// and calls its mine function according to the provided configuration
 pub struct CuckooMiner {
@@ -50,7 +122,7 @@ pub struct CuckooMiner {
 	configs: Vec<PluginConfig>,
 
 	/// Data shared across threads
-	pub shared_data: Arc<RwLock<JobSharedData>>,
+	pub shared_data: JobSharedDataType,
 
 	/// Job control tx
 	control_txs: Vec<mpsc::Sender<ControlMessage>>,
@@ -60,6 +132,24 @@ pub struct CuckooMiner {
 
 	/// Solver has stopped and cleanly shutdown
 	solver_stopped_rxs: Vec<mpsc::Receiver<ControlMessage>>,
+
+	/// which solver instances were paused or removed on their own
+	solver_states: SolverStates,
+
+	/// subscriber solutions and stats are pushed to, if any
+	events_tx: Option<mpsc::Sender<MinerEvent>>,
+
+	/// When and how often errored or stuck solvers are restarted
+	watchdog: WatchdogConfig,
+
+	/// Stops the watchdog thread
+	watchdog_tx: Option<mpsc::Sender<ControlMessage>>,
+
+	/// affinity and priority of the solver threads of CPU plugins
+	cpu_config: CpuConfig,
+
+	/// CPUs those may run on, if restricted
+	allowed: Option<Vec<usize>>,
 }
 
 unsafe impl Send for CuckooMiner{}
@@ -73,19 +163,197 @@ impl CuckooMiner {
 			mining_config.miner_plugin_config.clone())
 	}
 
-	/// Solver's instance of a thread
+	/// Plugins don't report their backend, it's part of their names
+	fn plugin_backend(name: &str) -> Backend {
+		if name.contains("cuda") {
+			Backend::Cuda
+		} else if name.contains("ocl") {
+			Backend::OpenCL
+		} else {
+			Backend::Cpu
+		}
+	}
+
+	/// Converts the solutions of one solver run
+	fn to_solutions(instance: usize, sol: &SolverSolutions) -> Vec<CrSolution> {
+		let mut solutions = Vec::new();
+		for i in 0..sol.num_sols {
+			solutions.push(CrSolution::new(
+				sol.sols[i as usize].id,
+				sol.sols[i as usize].nonce,
+				AlgorithmParams::Cuckoo(sol.edge_bits, sol.sols[i as usize].proof.to_vec()),
+				instance,
+			));
+		}
+		solutions
+	}
+
+	/// Copies plugin stats into the miner independent ones
+	fn to_stats(i: &SolverStats, restarts: u32, affinity: &[usize]) -> Stats {
+		Stats {
+			device_id: i.device_id,
+			edge_bits: i.edge_bits,
+			plugin_name: i.plugin_name,
+			device_name: i.device_name,
+			has_errored: i.has_errored,
+			error_reason: i.error_reason,
+			iterations: i.iterations,
+			last_start_time: i.last_start_time,
+			last_end_time: i.last_end_time,
+			last_solution_time: i.last_solution_time,
+			hashes_per_sec: 0,
+			state: SolverState::Enabled,
+			restarts: restarts,
+			memory: 0,
+			affinity: affinity.to_vec(),
+			fallback: String::new(),
+		}
+	}
+
+	/// Solver's instance of a thread. Brings the plugin back up when it
+	/// errors or stalls, see `Supervisor`
 	fn solver_thread(
-		mut solver: SolverInstance,
+		solver: SolverInstance,
 		instance: usize,
 		shared_data: JobSharedDataType,
-		control_rx: mpsc::Receiver<ControlMessage>,
+		mut events: EventSender,
+		mut control_rx: mpsc::Receiver<ControlMessage>,
+		control_tx: mpsc::Sender<ControlMessage>,
 		solver_loop_rx: mpsc::Receiver<ControlMessage>,
 		solver_stopped_tx: mpsc::Sender<ControlMessage>,
+		watchdog: WatchdogConfig,
+		cpu_config: CpuConfig,
+		allowed: Option<Vec<usize>>,
 	) {
-		{
-			let mut s = shared_data.write().unwrap();
-			s.stats[instance].set_plugin_name(&solver.config.name);
+		let config = solver.config.clone();
+		// GPU plugins only feed their device from here
+		if CuckooMiner::plugin_backend(&config.name) == Backend::Cpu {
+			// the plugin's own threads, started from this one, inherit it
+			if let Err(e) = cpu::apply(&cpu_config, allowed.as_deref()) {
+				warn!(
+					LOGGER,
+					"Solver instance {} ({}): can't set its CPU affinity or priority: {}",
+					instance,
+					config.name,
+					e
+				);
+			}
+			*shared_data.affinity[instance].lock().unwrap() = cpu::current_affinity();
+		}
+		let mut solver = Some(solver);
+		let mut paused = true;
+		let mut supervisor = Supervisor::new(watchdog.clone());
+		loop {
+			let started = time::Instant::now();
+			if let Some(mut s) = solver.take() {
+				let (exit, rx) = CuckooMiner::run_solver_instance(
+					&mut s,
+					instance,
+					&shared_data,
+					&mut events,
+					control_rx,
+					&control_tx,
+					&solver_loop_rx,
+					&mut paused,
+					&watchdog,
+				);
+				control_rx = rx;
+				s.unload();
+				if let SolverExit::Stopped = exit {
+					break;
+				}
+			}
+			if !watchdog.enabled {
+				break;
+			}
+			let delay = match supervisor.next_restart(started.elapsed()) {
+				Some(delay) => delay,
+				None => {
+					error!(
+						LOGGER,
+						"Solver instance {} ({}) failed after {} restarts, giving up on it",
+						instance,
+						config.name,
+						supervisor.restarts
+					);
+					break;
+				}
+			};
+			let restarts = supervisor.restarts;
+			shared_data.restarts[instance].store(restarts, Ordering::Relaxed);
+			warn!(
+				LOGGER,
+				"Restarting solver instance {} ({}) in {}s, attempt {} of {}",
+				instance,
+				config.name,
+				delay,
+				restarts,
+				watchdog.max_restarts
+			);
+			if !CuckooMiner::backoff(&solver_loop_rx, time::Duration::from_secs(delay), &mut paused) {
+				break;
+			}
+			match SolverInstance::new(config.clone()) {
+				Ok(s) => {
+					let mut stats = SolverStats::default();
+					stats.set_plugin_name(&config.name);
+					*shared_data.stats[instance].lock().unwrap() = stats;
+					solver = Some(s);
+				}
+				Err(e) => {
+					error!(
+						LOGGER,
+						"Can't load plugin {} for solver instance {}: {:?}",
+						config.name,
+						instance,
+						e
+					);
+				}
+			}
+		}
+		let _ = solver_stopped_tx.send(ControlMessage::SolverStopped(instance));
+	}
+
+	/// Waits out the delay before a restart, following pauses and resumes
+	/// meanwhile. Returns false if the solver was told to stop
+	fn backoff(
+		solver_loop_rx: &mpsc::Receiver<ControlMessage>,
+		delay: time::Duration,
+		paused: &mut bool,
+	) -> bool {
+		let deadline = time::Instant::now() + delay;
+		loop {
+			let now = time::Instant::now();
+			if now >= deadline {
+				return true;
+			}
+			match solver_loop_rx.recv_timeout(deadline - now) {
+				Ok(ControlMessage::Stop) => return false,
+				Ok(ControlMessage::Pause) => *paused = true,
+				Ok(ControlMessage::Resume) => *paused = false,
+				Ok(_) => {}
+				Err(mpsc::RecvTimeoutError::Timeout) => return true,
+				Err(mpsc::RecvTimeoutError::Disconnected) => return false,
+			}
 		}
+	}
+
+	/// Runs the loaded plugin until it's stopped, errors or is found
+	/// stalled. Hands back the control receiver for the next run
+	fn run_solver_instance(
+		solver: &mut SolverInstance,
+		instance: usize,
+		shared_data: &JobSharedDataType,
+		events: &mut EventSender,
+		control_rx: mpsc::Receiver<ControlMessage>,
+		control_tx: &mpsc::Sender<ControlMessage>,
+		solver_loop_rx: &mpsc::Receiver<ControlMessage>,
+		paused: &mut bool,
+		watchdog: &WatchdogConfig,
+	) -> (SolverExit, mpsc::Receiver<ControlMessage>) {
+		let started = time::Instant::now();
+		shared_data.stats[instance].lock().unwrap().set_plugin_name(&solver.config.name);
+		let affinity = shared_data.affinity[instance].lock().unwrap().clone();
 		// "Detach" a stop function from the solver, to let us keep a control thread going
 		let ctx = solver.lib.create_solver_ctx(&mut solver.config.params);
 		let control_ctx = SolverCtxWrapper(NonNull::new(ctx).unwrap());
@@ -94,15 +362,15 @@ impl CuckooMiner {
 
 		// monitor whether to send a stop signal to the solver, which should
 		// end the current solve attempt below
-		let stop_handle = thread::spawn(move || loop {
+		let stop_handle = thread::spawn(move || {
 			let _ = &control_ctx;
 			let ctx_ptr = control_ctx.0.as_ptr();
 
-			while let Some(message) = control_rx.iter().next() {
+			for message in control_rx.iter() {
 				match message {
 					ControlMessage::Stop => {
 						PluginLibrary::stop_solver_from_instance(stop_fn.clone(), ctx_ptr);
-						return;
+						break;
 					}
 					ControlMessage::Pause => {
 						PluginLibrary::stop_solver_from_instance(stop_fn.clone(), ctx_ptr);
@@ -110,50 +378,44 @@ impl CuckooMiner {
 					_ => {}
 				};
 			}
+			control_rx
 		});
 
 		let mut iter_count = 0;
-		let mut paused = true;
-		loop {
-			if let Some(message) = solver_loop_rx.try_iter().next() {
+		let mut job = shared_data.job.load();
+		let exit = loop {
+			if let Some(message) = next_control_message(solver_loop_rx, *paused) {
 				debug!(LOGGER, "solver_thread - solver_loop_rx got msg: {:?}", message);
 				match message {
-					ControlMessage::Stop => break,
-					ControlMessage::Pause => paused = true,
-					ControlMessage::Resume => paused = false,
+					ControlMessage::Stop => break SolverExit::Stopped,
+					ControlMessage::Pause => *paused = true,
+					ControlMessage::Resume => *paused = false,
+					ControlMessage::Restart => break SolverExit::Stalled,
 					_ => {}
 				}
 			}
-			if paused {
-				thread::sleep(time::Duration::from_micros(100));
+			if *paused {
 				continue;
 			}
-			{
-				let mut s = shared_data.write().unwrap();
-				s.stats[instance].set_plugin_name(&solver.config.name);
+			if !shared_data.job.is_current(&job) {
+				job = shared_data.job.load();
 			}
-			let header_pre = { shared_data.read().unwrap().pre_nonce.clone() };
-			let header_post = { shared_data.read().unwrap().post_nonce.clone() };
-			let height = { shared_data.read().unwrap().height.clone() };
-			let job_id = { shared_data.read().unwrap().job_id.clone() };
-			let target_difficulty = { shared_data.read().unwrap().difficulty.clone() };
-			let header = util::get_next_header_data(&header_pre, &header_post);
-			let nonce = header.0;
-			//let sec_scaling = header.2;
+			*shared_data.running_since[instance].lock().unwrap() = Some(time::Instant::now());
+			let target_difficulty = job.difficulty;
+			let nonce = util::get_random_nonce();
 			solver.lib.run_solver(
 				ctx,
-				header.1,
+				job.header(nonce),
 				0,
 				1,
 				&mut solver.solutions,
 				&mut solver.stats,
 			);
 			iter_count += 1;
-			let still_valid = { height == shared_data.read().unwrap().height };
-			if still_valid {
-				let mut s = shared_data.write().unwrap();
-				s.stats[instance] = solver.stats.clone();
-				s.stats[instance].iterations = iter_count;
+			*shared_data.running_since[instance].lock().unwrap() = None;
+			if !shared_data.job.is_stale(&job) {
+				let mut stats = solver.stats.clone();
+				stats.iterations = iter_count;
 				if solver.solutions.num_sols > 0 {
 					// Filter solutions that don't meet difficulty check
 					let mut filtered_sols:Vec<Solution> = vec![];
@@ -174,54 +436,156 @@ impl CuckooMiner {
 						.collect();
 					for ss in filtered_sols.iter_mut() {
 						ss.nonce = nonce;
-						ss.id = job_id as u64;
+						ss.id = job.job_id as u64;
 					}
 					solver.solutions.num_sols = filtered_sols.len() as u32;
 					for i in 0..solver.solutions.num_sols as usize {
 						solver.solutions.sols[i] = filtered_sols[i];
 					}
-					s.solutions.push(solver.solutions.clone());
+					if events.is_subscribed() {
+						for solution in CuckooMiner::to_solutions(instance, &solver.solutions) {
+							let _ = events.solution(solution);
+						}
+					} else {
+						shared_data.solutions.lock().unwrap().push((instance, solver.solutions.clone()));
+					}
+				}
+				// the supervisor starts counting over when this goes down
+				if Supervisor::healthy(watchdog, started.elapsed()) {
+					shared_data.restarts[instance].store(0, Ordering::Relaxed);
 				}
-				if s.stats[instance].has_errored {
-					s.stats[instance].set_plugin_name(&solver.config.name);
+				let restarts = shared_data.restarts[instance].load(Ordering::Relaxed);
+				let errored = stats.has_errored;
+				if errored {
+					stats.set_plugin_name(&solver.config.name);
+					events.stats_now(instance, &CuckooMiner::to_stats(&stats, restarts, &affinity));
 					error!(
 						LOGGER,
 						"Plugin {} has errored, device: {}. Reason: {}",
-						s.stats[instance].get_plugin_name(),
-						s.stats[instance].get_device_name(),
-						s.stats[instance].get_error_reason(),
+						stats.get_plugin_name(),
+						stats.get_device_name(),
+						stats.get_error_reason(),
 					);
-					break;
+				} else {
+					events.stats(instance, &CuckooMiner::to_stats(&stats, restarts, &affinity));
+				}
+				*shared_data.stats[instance].lock().unwrap() = stats;
+				if errored {
+					break SolverExit::Errored;
 				}
 			}
 			solver.solutions = SolverSolutions::default();
 			thread::sleep(time::Duration::from_micros(100));
-		}
+		};
 
-		let _ = stop_handle.join();
+		// the control thread may have stopped on its own already
+		let _ = control_tx.send(ControlMessage::Stop);
+		let control_rx = stop_handle.join().unwrap();
 		solver.lib.destroy_solver_ctx(ctx);
-		solver.unload();
-		let _ = solver_stopped_tx.send(ControlMessage::SolverStopped(instance));
+		(exit, control_rx)
+	}
+
+	/// Looks for solver runs taking longer than the stall timeout and has
+	/// their solver threads restart them
+	fn watchdog_thread(
+		shared_data: JobSharedDataType,
+		control_txs: Vec<mpsc::Sender<ControlMessage>>,
+		solver_loop_txs: Vec<mpsc::Sender<ControlMessage>>,
+		stall_timeout: time::Duration,
+		watchdog_rx: mpsc::Receiver<ControlMessage>,
+	) {
+		loop {
+			match watchdog_rx.recv_timeout(time::Duration::from_secs(1)) {
+				Ok(ControlMessage::Stop) | Err(mpsc::RecvTimeoutError::Disconnected) => break,
+				_ => {}
+			}
+			for i in 0..shared_data.running_since.len() {
+				let mut running_since = shared_data.running_since[i].lock().unwrap();
+				let stalled = match *running_since {
+					Some(t) => t.elapsed() >= stall_timeout,
+					None => false,
+				};
+				if stalled {
+					warn!(
+						LOGGER,
+						"Solver instance {} made no progress in {}s, restarting it",
+						i,
+						stall_timeout.as_secs()
+					);
+					*running_since = None;
+					// ends the run in progress, if the plugin still listens
+					let _ = control_txs[i].send(ControlMessage::Pause);
+					let _ = solver_loop_txs[i].send(ControlMessage::Restart);
+				}
+			}
+		}
 	}
 }
 
 impl Miner for CuckooMiner {
 	/// Creates a new instance of a CuckooMiner with the given configuration.
 	/// One PluginConfig per device
-	fn new(mining_config: &MinerConfig) -> Self {
-		let configs = CuckooMiner::read_plugins(mining_config).unwrap();
+	fn new(mining_config: &MinerConfig) -> Result<CuckooMiner, MinerError> {
+		let configs = CuckooMiner::read_plugins(mining_config)?;
 		let len = configs.len();
-		CuckooMiner {
+		let allowed = cpu::allowed_cpus(&mining_config.cpu_config).map_err(MinerError::ParameterError)?;
+		Ok(CuckooMiner {
 			configs: configs,
-			shared_data: Arc::new(RwLock::new(JobSharedData::new(len))),
+			shared_data: Arc::new(JobSharedData::new(len)),
 			control_txs: vec![],
 			solver_loop_txs: vec![],
 			solver_stopped_rxs: vec![],
+			solver_states: SolverStates::new(0),
+			events_tx: None,
+			watchdog: mining_config.watchdog_config.clone(),
+			watchdog_tx: None,
+			cpu_config: mining_config.cpu_config.clone(),
+			allowed: allowed,
+		})
+	}
+
+	/// Lists the plugins found in the plugin dir as devices, with the
+	/// defaults their `fill_default_params` reports
+	fn capabilities(mining_config: &MinerConfig) -> Result<Capabilities, MinerError> {
+		let dir = config::resolve_plugin_dir(mining_config.miner_plugin_dir.clone())?;
+		let pattern = dir.join(format!("*{}", SO_SUFFIX));
+		let paths = glob::glob(&pattern.to_string_lossy())
+			.map_err(|e| {
+				MinerError::PluginReadError(format!("Invalid plugin directory {}", dir.display()), Box::new(e))
+			})?;
+		let mut devices = vec![];
+		for path in paths.filter_map(Result::ok) {
+			let file_name = path.file_name().unwrap().to_string_lossy().into_owned();
+			let name = file_name.trim_end_matches(SO_SUFFIX);
+			match PluginConfig::new(dir.clone(), name) {
+				Ok(c) => devices.push(DeviceInfo {
+					id: c.params.device,
+					name: c.name.clone(),
+					backend: CuckooMiner::plugin_backend(&c.name),
+					memory: None,
+					compute_units: None,
+					features: vec![],
+					params: config::param_defaults(&c.params),
+				}),
+				Err(e) => warn!(LOGGER, "Can't load plugin {}: {:?}", name, e),
+			}
+		}
+		if devices.is_empty() {
+			return Err(MinerError::NoPluginsFoundError(format!(
+				"No plugins found in {}",
+				dir.display()
+			)));
 		}
+		Ok(Capabilities {
+			algorithms: vec![Algorithm::Cuckoo],
+			devices: devices,
+			params: vec![],
+		})
 	}
 
 	/// Starts solvers, ready for jobs via job control
 	fn start_solvers(&mut self) -> Result<(), MinerError> {
+		self.solver_states = SolverStates::new(self.configs.len());
 		let mut solvers = Vec::new();
 		for c in self.configs.clone() {
 			solvers.push(SolverInstance::new(c)?);
@@ -229,18 +593,48 @@ impl Miner for CuckooMiner {
 		let mut i = 0;
 		for s in solvers {
 			let sd = self.shared_data.clone();
+			let events = EventSender::new(self.events_tx.clone());
 			let (control_tx, control_rx) = mpsc::channel::<ControlMessage>();
 			let (solver_tx, solver_rx) = mpsc::channel::<ControlMessage>();
 			let (solver_stopped_tx, solver_stopped_rx) = mpsc::channel::<ControlMessage>();
+			let own_control_tx = control_tx.clone();
+			let watchdog = self.watchdog.clone();
+			let cpu_config = self.cpu_config.clone();
+			let allowed = self.allowed.clone();
 			self.control_txs.push(control_tx);
 			self.solver_loop_txs.push(solver_tx);
 			self.solver_stopped_rxs.push(solver_stopped_rx);
 			thread::spawn(move || {
-				let _ =
-					CuckooMiner::solver_thread(s, i, sd, control_rx, solver_rx, solver_stopped_tx);
+				let _ = CuckooMiner::solver_thread(
+					s,
+					i,
+					sd,
+					events,
+					control_rx,
+					own_control_tx,
+					solver_rx,
+					solver_stopped_tx,
+					watchdog,
+					cpu_config,
+					allowed,
+				);
 			});
 			i += 1;
 		}
+		if self.watchdog.enabled {
+			let sd = self.shared_data.clone();
+			let control_txs = self.control_txs.clone();
+			let solver_loop_txs = self.solver_loop_txs.clone();
+			let stall_timeout = time::Duration::from_secs(self.watchdog.stall_timeout);
+			let (watchdog_tx, watchdog_rx) = mpsc::channel::<ControlMessage>();
+			self.watchdog_tx = Some(watchdog_tx);
+			thread::Builder::new()
+				.name("solver_watchdog".to_string())
+				.spawn(move || {
+					CuckooMiner::watchdog_thread(sd, control_txs, solver_loop_txs, stall_timeout, watchdog_rx);
+				})
+				.map_err(|e| MinerError::PluginProcessingError(format!("{}", e)))?;
+		}
 		Ok(())
 	}
 
@@ -263,79 +657,56 @@ impl Miner for CuckooMiner {
 		difficulty: u64,  /* The target difficulty, only sols greater than this difficulty will
 		                   * be returned. */
 	) -> Result<(), MinerError> {
-		let mut sd = self.shared_data.write().unwrap();
+		let job = Job::new(job_id, height, pre_nonce, post_nonce, difficulty)?;
 		let mut paused = false;
-		if height != sd.height {
+		if height != self.shared_data.job.load().height {
 			// stop/pause any existing jobs if job is for a new
 			// height
 			self.pause_solvers();
 			paused = true;
 		}
-		sd.job_id = job_id;
-		sd.height = height;
-		sd.pre_nonce = pre_nonce.to_owned();
-		sd.post_nonce = post_nonce.to_owned();
-		sd.difficulty = difficulty;
+		self.shared_data.job.publish(job);
 		if paused {
 			self.resume_solvers();
 		}
 		Ok(())
 	}
 
-	/// Returns solutions if currently waiting.
+	fn subscribe(&mut self, tx: mpsc::Sender<MinerEvent>) -> bool {
+		self.events_tx = Some(tx);
+		true
+	}
 
-	fn get_solutions(&self) -> Option<Vec<CrSolution>> {
-		// just to prevent endless needless locking of this
-		// when using fast test miners, in real cuckoo30 terms
-		// this shouldn't be an issue
-		// TODO: Make this less blocky
-		// let time_pre_lock=Instant::now();
-		{
-			let mut s = self.shared_data.write().unwrap();
-			// let time_elapsed=Instant::now()-time_pre_lock;
-			// println!("Get_solution Time spent waiting for lock: {}",
-			// time_elapsed.as_secs()*1000 +(time_elapsed.subsec_nanos()/1_000_000)as u64);
-			if s.solutions.len() > 0 {
-				let mut solutions = Vec::new();
-				let sol = s.solutions.pop().unwrap();
-				let edge_bits = sol.edge_bits;
-
-				for i in 0..sol.num_sols {
-					solutions.push(CrSolution::new(
-						sol.sols[i as usize].id,
-						sol.sols[i as usize].nonce,
-						AlgorithmParams::Cuckoo(edge_bits, sol.sols[i as usize].proof.to_vec())
-					));
-				}
+	/// Returns solutions if currently waiting, all queued solver runs at once.
 
-				return Some(solutions);
-			}
+	fn get_solutions(&self) -> Option<Vec<CrSolution>> {
+		let mut s = self.shared_data.solutions.lock().unwrap();
+		if s.is_empty() {
+			return None;
 		}
-		None
+		let solutions = s
+			.drain(..)
+			.flat_map(|(instance, sol)| CuckooMiner::to_solutions(instance, &sol))
+			.collect();
+		Some(solutions)
 	}
 
 	/// get stats for all running solvers
 	fn get_stats(&self) -> Result<Vec<Stats>, MinerError> {
-		let s = self.shared_data.read().unwrap();
-		let mut stats = Vec::new();
-
-		for i in s.stats.clone() {
-			stats.push(Stats {
-				device_id: i.device_id,
-				edge_bits: i.edge_bits,
-				plugin_name: i.plugin_name,
-				device_name: i.device_name,
-				has_errored: i.has_errored,
-				error_reason: i.error_reason,
-				iterations: i.iterations,
-				last_start_time: i.last_start_time,
-				last_end_time: i.last_end_time,
-				last_solution_time: i.last_solution_time,
-				hashes_per_sec: 0,
-			});
-		}
-
-		Ok(stats)
+		let s = &self.shared_data;
+		Ok(s
+			.stats
+			.iter()
+			.zip(s.restarts.iter())
+			.zip(s.affinity.iter())
+			.map(|((stats, restarts), affinity)| {
+				CuckooMiner::to_stats(
+					&stats.lock().unwrap(),
+					restarts.load(Ordering::Relaxed),
+					&affinity.lock().unwrap(),
+				)
+			})
+			.collect())
 	}
 
 	/// #Description
@@ -348,6 +719,9 @@ impl Miner for CuckooMiner {
 	/// Nothing
 
 	fn stop_solvers(&self) {
+		if let Some(ref t) = self.watchdog_tx {
+			let _ = t.send(ControlMessage::Stop);
+		}
 		for t in self.control_txs.iter() {
 			let _ = t.send(ControlMessage::Stop);
 		}
@@ -359,6 +733,7 @@ impl Miner for CuckooMiner {
 
 	/// Tells current solvers to stop and wait
 	fn pause_solvers(&self) {
+		self.solver_states.set_paused(true);
 		for t in self.control_txs.iter() {
 			let _ = t.send(ControlMessage::Pause);
 		}
@@ -370,15 +745,44 @@ impl Miner for CuckooMiner {
 
 	/// Tells current solvers to stop and wait
 	fn resume_solvers(&self) {
-		for t in self.control_txs.iter() {
-			let _ = t.send(ControlMessage::Resume);
+		self.solver_states.set_paused(false);
+		for (i, t) in self.control_txs.iter().enumerate() {
+			if self.solver_states.is_enabled(i) {
+				let _ = t.send(ControlMessage::Resume);
+			}
 		}
-		for t in self.solver_loop_txs.iter() {
-			let _ = t.send(ControlMessage::Resume);
+		for (i, t) in self.solver_loop_txs.iter().enumerate() {
+			if self.solver_states.is_enabled(i) {
+				let _ = t.send(ControlMessage::Resume);
+			}
 		}
 		debug!(LOGGER, "Resume message sent");
 	}
 
+	fn pause_solver(&self, instance: usize) -> Result<(), MinerError> {
+		self.solver_states.set(instance, SolverState::Disabled)?;
+		let _ = self.control_txs[instance].send(ControlMessage::Pause);
+		let _ = self.solver_loop_txs[instance].send(ControlMessage::Pause);
+		Ok(())
+	}
+
+	fn resume_solver(&self, instance: usize) -> Result<(), MinerError> {
+		self.solver_states.set(instance, SolverState::Enabled)?;
+		// picked up by resume_solvers otherwise
+		if !self.solver_states.is_paused() {
+			let _ = self.control_txs[instance].send(ControlMessage::Resume);
+			let _ = self.solver_loop_txs[instance].send(ControlMessage::Resume);
+		}
+		Ok(())
+	}
+
+	fn remove_solver(&self, instance: usize) -> Result<(), MinerError> {
+		self.solver_states.set(instance, SolverState::Removed)?;
+		let _ = self.control_txs[instance].send(ControlMessage::Stop);
+		let _ = self.solver_loop_txs[instance].send(ControlMessage::Stop);
+		Ok(())
+	}
+
 	/// block until solvers have all exited
 	fn wait_for_solver_shutdown(&self) {
 		for r in self.solver_stopped_rxs.iter() {
@@ -394,3 +798,38 @@ impl Miner for CuckooMiner {
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn supervisor() {
+		let mut supervisor = Supervisor::new(WatchdogConfig {
+			enabled: true,
+			stall_timeout: 120,
+			max_restarts: 3,
+			backoff: 5,
+		});
+		let down = time::Duration::from_secs(1);
+		let healthy = time::Duration::from_secs(121);
+
+		// waits longer before each restart, until giving up
+		assert_eq!(supervisor.next_restart(down), Some(5));
+		assert_eq!(supervisor.next_restart(down), Some(10));
+		assert_eq!(supervisor.next_restart(down), Some(20));
+		assert_eq!(supervisor.restarts, 3);
+		assert_eq!(supervisor.next_restart(down), None);
+
+		// up for the stall timeout exactly isn't enough
+		assert_eq!(supervisor.next_restart(time::Duration::from_secs(120)), None);
+
+		// after a healthy run it starts over
+		assert_eq!(supervisor.next_restart(healthy), Some(5));
+		assert_eq!(supervisor.restarts, 1);
+		assert_eq!(supervisor.next_restart(down), Some(10));
+		assert_eq!(supervisor.next_restart(healthy), Some(5));
+		assert_eq!(supervisor.next_restart(healthy), Some(5));
+		assert_eq!(supervisor.restarts, 1);
+	}
+}
@@ -23,5 +23,3 @@
 
 pub mod miner;
 pub mod types;
-pub mod util;
-pub mod consensus;
@@ -13,13 +13,16 @@
 // limitations under the License.
 
 //! Miner types
-use std::sync::{Arc, RwLock};
+use std::sync::atomic::AtomicU32;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
 use core::errors::MinerError;
+use core::JobCell;
 use plugin::{SolverSolutions, SolverStats};
 use crate::{PluginConfig, PluginLibrary};
 
-pub type JobSharedDataType = Arc<RwLock<JobSharedData>>;
+pub type JobSharedDataType = Arc<JobSharedData>;
 
 /// Holds a loaded lib + config + stats
 /// 1 instance = 1 device on 1 controlling thread
@@ -52,56 +55,44 @@ impl SolverInstance {
 	}
 }
 
-/// Data intended to be shared across threads
+/// Data intended to be shared across threads. The job is swapped without
+/// locking, everything solvers report back has locks of its own
 pub struct JobSharedData {
-	/// ID of the current running job (not currently used)
-	pub job_id: u32,
+	/// The job being mined
+	pub job: JobCell,
 
-	/// block height of current running job
-	pub height: u64,
+	/// Output solutions, with the solver instance that found them
+	pub solutions: Mutex<Vec<(usize, SolverSolutions)>>,
 
-	/// The part of the header before the nonce, which this
-	/// module will mutate in search of a solution
-	pub pre_nonce: String,
+	/// Current stats, per solver instance
+	pub stats: Vec<Mutex<SolverStats>>,
 
-	/// The part of the header after the nonce
-	pub post_nonce: String,
+	/// Times each solver instance was restarted
+	pub restarts: Vec<AtomicU32>,
 
-	/// The target difficulty. Only solutions >= this
-	/// target will be put into the output queue
-	pub difficulty: u64,
+	/// When the solver run in progress started, per instance, checked by
+	/// the watchdog
+	pub running_since: Vec<Mutex<Option<Instant>>>,
 
-	/// Output solutions
-	pub solutions: Vec<SolverSolutions>,
-
-	/// Current stats
-	pub stats: Vec<SolverStats>,
+	/// CPUs each solver instance may run on, empty until it's started
+	pub affinity: Vec<Mutex<Vec<usize>>>,
 }
 
 impl Default for JobSharedData {
 	fn default() -> JobSharedData {
-		JobSharedData {
-			job_id: 0,
-			height: 0,
-			pre_nonce: String::from(""),
-			post_nonce: String::from(""),
-			difficulty: 0,
-			solutions: Vec::new(),
-			stats: vec![],
-		}
+		JobSharedData::new(0)
 	}
 }
 
 impl JobSharedData {
 	pub fn new(num_solvers: usize) -> JobSharedData {
 		JobSharedData {
-			job_id: 0,
-			height: 0,
-			pre_nonce: String::from(""),
-			post_nonce: String::from(""),
-			difficulty: 1,
-			solutions: Vec::new(),
-			stats: vec![SolverStats::default(); num_solvers],
+			job: JobCell::new(),
+			solutions: Mutex::new(Vec::new()),
+			stats: (0..num_solvers).map(|_| Mutex::new(SolverStats::default())).collect(),
+			restarts: (0..num_solvers).map(|_| AtomicU32::new(0)).collect(),
+			running_since: (0..num_solvers).map(|_| Mutex::new(None)).collect(),
+			affinity: (0..num_solvers).map(|_| Mutex::new(vec![])).collect(),
 		}
 	}
 }
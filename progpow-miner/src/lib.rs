@@ -11,4 +11,4 @@ extern crate progpow_opencl as progpow;
 extern crate progpow_cuda as progpow;
 
 pub mod miner;
-pub use miner::PpMiner;
+pub use miner::{register, PpMiner};
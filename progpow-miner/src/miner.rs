@@ -1,17 +1,20 @@
 
-use std::sync::{mpsc, Arc, RwLock};
+use std::sync::{mpsc, Arc};
 use std::thread;
-use std::time;
 use std::time::{ SystemTime, UNIX_EPOCH};
 
 use keccak_hash::keccak_256;
 
 use core::config::{MinerConfig, GpuConfig};
 use core::errors::MinerError;
-use core::miner::Miner;
+use core::miner::{next_control_message, EventSender, Miner, SolverStates};
+use core::registry::{MinerEntry, Registry};
 use core::types::AlgorithmParams;
 use core::util;
-use core::{ControlMessage, JobSharedData, JobSharedDataType, Solution, Stats};
+use core::{
+	difficulty, Algorithm, Backend, Capabilities, ControlMessage, DeviceInfo, Job, JobSharedData, JobSharedDataType,
+	MinerEvent, ParamInfo, Solution, SolverState, Stats,
+};
 
 use bigint::uint::U256;
 
@@ -24,6 +27,9 @@ const ALGORITHM_NAME: &str = "progpow";
 const GLOBAL_WORK_SIZE: u64 = 2048;
 const LOCAL_WORK_SIZE: u64 = 256;
 const WORK_PER_CALL: u64 = GLOBAL_WORK_SIZE * LOCAL_WORK_SIZE;
+/// `GpuConfig::driver` values
+const DRIVER_CUDA: u8 = 1;
+const DRIVER_OPENCL: u8 = 2;
 
 fn timestamp() -> u64 {
 	let start = SystemTime::now();
@@ -33,9 +39,24 @@ fn timestamp() -> u64 {
 	since_the_epoch.as_millis() as u64
 }
 
+/// Adds the ProgPow miner to the registry
+pub fn register(registry: &mut Registry) {
+	registry.register(MinerEntry {
+		name: "progpow",
+		algorithm: Algorithm::ProgPow,
+		description: if cfg!(feature = "cuda") {
+			"ProgPow CUDA miner"
+		} else {
+			"ProgPow OpenCL miner"
+		},
+		build: |config| Ok(Box::new(PpMiner::new(config)?)),
+		capabilities: PpMiner::capabilities,
+	});
+}
+
 pub struct PpMiner {
 	/// Data shared across threads
-	pub shared_data: Arc<RwLock<JobSharedData>>,
+	pub shared_data: JobSharedDataType,
 
 	pub gpus: Vec<GpuConfig>,
 
@@ -47,6 +68,12 @@ pub struct PpMiner {
 
 	/// Solver has stopped and cleanly shutdown
 	solver_stopped_rxs: Vec<mpsc::Receiver<ControlMessage>>,
+
+	/// which solver instances were paused or removed on their own
+	solver_states: SolverStates,
+
+	/// subscriber solutions and stats are pushed to, if any
+	events_tx: Option<mpsc::Sender<MinerEvent>>,
 }
 
 unsafe impl Send for PpMiner {}
@@ -57,25 +84,27 @@ impl PpMiner {
 		instance: usize,
 		config: GpuConfig,
 		shared_data: JobSharedDataType,
+		mut events: EventSender,
 		control_rx: mpsc::Receiver<ControlMessage>,
 		solver_loop_rx: mpsc::Receiver<ControlMessage>,
 		solver_stopped_tx: mpsc::Sender<ControlMessage>,
 	) {
-		{
-			let mut s = shared_data.write().unwrap();
-			s.stats[instance].set_plugin_name(ALGORITHM_NAME);
-		}
+		shared_data.stats[instance].lock().unwrap().set_plugin_name(ALGORITHM_NAME);
 
 		let mut last_solution_time = 0;
 		let mut iter_count = 0;
 		let mut paused = true;
+		let mut job = shared_data.job.load();
+		// keccak of the pre_nonce, only changes along with the job
+		let mut header = [0u8; 32];
+		keccak_256(&job.pre_nonce, &mut header);
 
 		let cpu = PpCPU::new();
 		let mut gpu = PpGPU::new(config.device, config.driver);
 		let _ = gpu.init();
 
 		loop {
-			if let Some(message) = solver_loop_rx.try_iter().next() {
+			if let Some(message) = next_control_message(&solver_loop_rx, paused) {
 				//debug!(LOGGER, "solver_thread - solver_loop_rx got msg: {:?}", message);
 				match message {
 					ControlMessage::Stop => break,
@@ -86,36 +115,23 @@ impl PpMiner {
 			}
 
 			if paused {
-				thread::sleep(time::Duration::from_micros(100));
 				continue;
 			}
-			{
-				let mut s = shared_data.write().unwrap();
-				s.stats[instance].set_plugin_name(ALGORITHM_NAME);
+			if !shared_data.job.is_current(&job) {
+				job = shared_data.job.load();
+				keccak_256(&job.pre_nonce, &mut header);
 			}
+			let height = job.height;
+			let target_difficulty = job.difficulty;
 
-			let header_pre =
-				util::from_hex_string({ shared_data.read().unwrap().pre_nonce.clone() }.as_str());
-
-			let height = { shared_data.read().unwrap().height.clone() };
-			let job_id = { shared_data.read().unwrap().job_id.clone() };
-			let target_difficulty = { shared_data.read().unwrap().difficulty.clone() };
-
-			let boundary = U256::max_value() / U256::from(if target_difficulty > 0 { target_difficulty } else { 1 });
-
-			let target = (boundary >> 192).low_u64();
-			let mut header = [0u8; 32];
-
-			keccak_256(&header_pre, &mut header);
+			let target = difficulty::target_u64(target_difficulty);
 
 			let start = timestamp();
 			gpu.compute_with_startnonce(header, height, (height / 30000) as i32, target, util::get_random_nonce());
 			let end = timestamp();
 
 			iter_count += WORK_PER_CALL;
-			let still_valid = { height == shared_data.read().unwrap().height };
-			if still_valid {
-				let mut s = shared_data.write().unwrap();
+			if !shared_data.job.is_stale(&job) {
 				let solutions = gpu.get_solutions();
 
 				if let Some(solution) = solutions {
@@ -126,12 +142,16 @@ impl PpMiner {
 					let digest: [u8; 32] = unsafe { ::std::mem::transmute(v) };
 					let h256_digest: U256 = digest.into();
 
-					if h256_digest <= boundary {
-						s.solutions.push(Solution::new(
-							job_id as u64,
+					if difficulty::meets_target(&h256_digest, target_difficulty) {
+						let solution = Solution::new(
+							job.job_id as u64,
 							nonce,
 							AlgorithmParams::ProgPow(mix),
-						));
+							instance,
+						);
+						if let Err(solution) = events.solution(solution) {
+							shared_data.solutions.lock().unwrap().push(solution);
+						}
 					}
 				}
 
@@ -148,7 +168,8 @@ impl PpMiner {
 				};
  
 				stats.set_plugin_name(ALGORITHM_NAME);
-				s.stats[instance] = stats;
+				events.stats(instance, &stats);
+				*shared_data.stats[instance].lock().unwrap() = stats;
 			}
 		}
 
@@ -157,21 +178,57 @@ impl PpMiner {
 }
 
 impl Miner for PpMiner {
-	fn new(configs: &MinerConfig) -> PpMiner {
+	fn new(configs: &MinerConfig) -> Result<PpMiner, MinerError> {
 		let count = configs.gpu_config.len();
-		PpMiner {
-			shared_data: Arc::new(RwLock::new(JobSharedData::new(count))),
+		Ok(PpMiner {
+			shared_data: Arc::new(JobSharedData::new(count)),
 			gpus: configs.gpu_config.clone(),
 			control_txs: vec![],
 			solver_loop_txs: vec![],
 			solver_stopped_rxs: vec![],
-		}
+			solver_states: SolverStates::new(0),
+			events_tx: None,
+		})
+	}
+
+	/// The progpow bindings can't enumerate GPUs, so the devices are the
+	/// ones in `gpu_config`
+	fn capabilities(configs: &MinerConfig) -> Result<Capabilities, MinerError> {
+		let devices = configs
+			.gpu_config
+			.iter()
+			.map(|gpu| DeviceInfo {
+				id: gpu.device,
+				name: format!("GPU {}", gpu.device),
+				backend: if gpu.driver == DRIVER_CUDA {
+					Backend::Cuda
+				} else {
+					Backend::OpenCL
+				},
+				memory: None,
+				compute_units: None,
+				features: vec![],
+				params: vec![],
+			})
+			.collect();
+		let driver = if cfg!(feature = "cuda") {
+			DRIVER_CUDA
+		} else {
+			DRIVER_OPENCL
+		};
+		Ok(Capabilities {
+			algorithms: vec![Algorithm::ProgPow],
+			devices: devices,
+			params: vec![ParamInfo::new("device", 0), ParamInfo::new("driver", driver)],
+		})
 	}
 
 	fn start_solvers(&mut self) -> Result<(), MinerError> {
+		self.solver_states = SolverStates::new(self.gpus.len());
 		for i in 0..self.gpus.len() {
 			let config = self.gpus[i].clone();
 			let shared_data = self.shared_data.clone();
+			let events = EventSender::new(self.events_tx.clone());
 			let (control_tx, control_rx) = mpsc::channel::<ControlMessage>();
 			let (solver_tx, solver_rx) = mpsc::channel::<ControlMessage>();
 			let (solver_stopped_tx, solver_stopped_rx) = mpsc::channel::<ControlMessage>();
@@ -185,6 +242,7 @@ impl Miner for PpMiner {
 					i,
 					config,
 					shared_data,
+					events,
 					control_rx,
 					solver_rx,
 					solver_stopped_tx,
@@ -195,18 +253,17 @@ impl Miner for PpMiner {
 		Ok(())
 	}
 
-	fn get_solutions(&self) -> Option<Vec<Solution>> {
-		let mut s = self.shared_data.write().unwrap();
-
-		let solutions = s.solutions.clone();
-		s.solutions.clear();
+	fn subscribe(&mut self, tx: mpsc::Sender<MinerEvent>) -> bool {
+		self.events_tx = Some(tx);
+		true
+	}
 
-		Some(solutions)
+	fn get_solutions(&self) -> Option<Vec<Solution>> {
+		Some(self.shared_data.take_solutions())
 	}
 
 	fn get_stats(&self) -> Result<Vec<Stats>, MinerError> {
-		//println!("{:?}", self.shared_data.read().unwrap().stats[0].get_plugin_name());
-		Ok(self.shared_data.read().unwrap().stats.clone())
+		Ok(self.shared_data.stats())
 	}
 
 	fn notify(
@@ -218,19 +275,15 @@ impl Miner for PpMiner {
 		difficulty: u64,  /* The target difficulty, only sols greater than this difficulty will
 		                   * be returned. */
 	) -> Result<(), MinerError> {
-		let mut sd = self.shared_data.write().unwrap();
+		let job = Job::new(job_id, height, pre_nonce, post_nonce, difficulty)?;
 		let mut paused = false;
-		if height != sd.height {
+		if height != self.shared_data.job.load().height {
 			// stop/pause any existing jobs if job is for a new
 			// height
 			self.pause_solvers();
 			paused = true;
 		}
-		sd.job_id = job_id;
-		sd.height = height;
-		sd.pre_nonce = pre_nonce.to_owned();
-		sd.post_nonce = post_nonce.to_owned();
-		sd.difficulty = difficulty;
+		self.shared_data.job.publish(job);
 		if paused {
 			self.resume_solvers();
 		}
@@ -257,6 +310,7 @@ impl Miner for PpMiner {
 
 	/// Tells current solvers to stop and wait
 	fn pause_solvers(&self) {
+		self.solver_states.set_paused(true);
 		for t in self.control_txs.iter() {
 			let _ = t.send(ControlMessage::Pause);
 		}
@@ -268,15 +322,44 @@ impl Miner for PpMiner {
 
 	/// Tells current solvers to stop and wait
 	fn resume_solvers(&self) {
-		for t in self.control_txs.iter() {
-			let _ = t.send(ControlMessage::Resume);
+		self.solver_states.set_paused(false);
+		for (i, t) in self.control_txs.iter().enumerate() {
+			if self.solver_states.is_enabled(i) {
+				let _ = t.send(ControlMessage::Resume);
+			}
 		}
-		for t in self.solver_loop_txs.iter() {
-			let _ = t.send(ControlMessage::Resume);
+		for (i, t) in self.solver_loop_txs.iter().enumerate() {
+			if self.solver_states.is_enabled(i) {
+				let _ = t.send(ControlMessage::Resume);
+			}
 		}
 		//debug!(LOGGER, "Resume message sent");
 	}
 
+	fn pause_solver(&self, instance: usize) -> Result<(), MinerError> {
+		self.solver_states.set(instance, SolverState::Disabled)?;
+		let _ = self.control_txs[instance].send(ControlMessage::Pause);
+		let _ = self.solver_loop_txs[instance].send(ControlMessage::Pause);
+		Ok(())
+	}
+
+	fn resume_solver(&self, instance: usize) -> Result<(), MinerError> {
+		self.solver_states.set(instance, SolverState::Enabled)?;
+		// picked up by resume_solvers otherwise
+		if !self.solver_states.is_paused() {
+			let _ = self.control_txs[instance].send(ControlMessage::Resume);
+			let _ = self.solver_loop_txs[instance].send(ControlMessage::Resume);
+		}
+		Ok(())
+	}
+
+	fn remove_solver(&self, instance: usize) -> Result<(), MinerError> {
+		self.solver_states.set(instance, SolverState::Removed)?;
+		let _ = self.control_txs[instance].send(ControlMessage::Stop);
+		let _ = self.solver_loop_txs[instance].send(ControlMessage::Stop);
+		Ok(())
+	}
+
 	/// block until solvers have all exited
 	fn wait_for_solver_shutdown(&self) {
 		for r in self.solver_stopped_rxs.iter() {
@@ -11,4 +11,5 @@ extern crate randomx;
 
 //pub mod plugin;
 pub mod miner;
-pub use miner::RxMiner;
+pub mod numa;
+pub use miner::{register, RxMiner};
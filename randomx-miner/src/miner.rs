@@ -1,22 +1,33 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
 use std::string;
-use std::sync::{mpsc, Arc, RwLock};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Mutex, RwLock};
 use std::thread;
-use std::time;
-use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use core::config::{MinerConfig, RxConfig};
+use core::config::{CpuConfig, MinerConfig, RxConfig, RxMode};
 use core::errors::MinerError;
-use core::miner::Miner;
+use core::miner::{next_control_message, EventSender, Miner, SolverStates};
+use core::registry::{MinerEntry, Registry};
 use core::types::AlgorithmParams;
+use core::cpu;
 use core::util;
-use core::{ControlMessage, JobSharedData, JobSharedDataType, Solution, Stats};
+use core::{
+	difficulty, Algorithm, Backend, Capabilities, ControlMessage, DeviceInfo, Job, JobSharedData, JobSharedDataType,
+	MinerEvent, ParamInfo, Solution, SolverState, Stats,
+};
 
 use bigint::uint::U256;
-use randomx::{calculate, RxState, RxAction};
+use randomx::{calculate, RxAction, RxState, RxVM};
+use crate::numa::{self, Layout};
 use crate::util::LOGGER;
 
 const MAX_HASHS: u64 = 100;
 const ALGORITHM_NAME: &str = "randomx";
+/// Reported instead in light mode, so the lower rate makes sense
+const LIGHT_ALGORITHM_NAME: &str = "randomx_light";
 
 fn timestamp() -> u64 {
 	let start = SystemTime::now();
@@ -26,6 +37,58 @@ fn timestamp() -> u64 {
 	since_the_epoch.as_millis() as u64
 }
 
+/// Value of the first `key: value` line of a /proc style file
+fn proc_value(path: &str, key: &str) -> Option<String> {
+	let content = fs::read_to_string(path).ok()?;
+	content
+		.lines()
+		.filter(|l| l.starts_with(key))
+		.filter_map(|l| l.splitn(2, ':').nth(1))
+		.map(|v| v.trim().to_owned())
+		.next()
+}
+
+/// Bytes of a /proc/meminfo entry
+fn meminfo_bytes(key: &str) -> Option<u64> {
+	proc_value("/proc/meminfo", key)
+		.and_then(|v| v.trim_end_matches("kB").trim().parse::<u64>().ok())
+		.map(|kb| kb * 1024)
+}
+
+/// CPU features RandomX makes use of, see `RxConfig`
+fn cpu_features() -> Vec<String> {
+	let mut features = vec![];
+	#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+	{
+		if is_x86_feature_detected!("aes") {
+			features.push("aes".to_owned());
+		}
+		if is_x86_feature_detected!("ssse3") {
+			features.push("ssse3".to_owned());
+		}
+		if is_x86_feature_detected!("avx2") {
+			features.push("avx2".to_owned());
+		}
+	}
+	let huge_pages = proc_value("/proc/meminfo", "HugePages_Total")
+		.and_then(|v| v.parse::<u64>().ok())
+		.unwrap_or(0);
+	if huge_pages > 0 {
+		features.push("large_pages".to_owned());
+	}
+	features
+}
+
+/// Adds the RandomX miner to the registry
+pub fn register(registry: &mut Registry) {
+	registry.register(MinerEntry {
+		name: "randomx",
+		algorithm: Algorithm::RandomX,
+		description: "RandomX CPU miner",
+		build: |config| Ok(Box::new(RxMiner::new(config)?)),
+		capabilities: RxMiner::capabilities,
+	});
+}
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 enum EpochState {
@@ -34,6 +97,8 @@ enum EpochState {
 	Loaded,
 	Running,
 	Failed(String),
+	/// its dataset was overwritten with another epoch's
+	Replaced,
 }
 
 #[derive(Debug, Clone)]
@@ -42,6 +107,10 @@ struct EpochSeed {
 	end_height: u64,
 	seed: [u8; 32],
 	state: EpochState,
+	/// dataset slot it's loaded in, or being loaded in
+	slot: Option<usize>,
+	/// when its dataset is built again, once it failed
+	retry_at: Option<Instant>,
 }
 
 impl EpochSeed {
@@ -55,16 +124,209 @@ impl EpochSeed {
 			end_height,
 			seed,
 			state: EpochState::Waiting,
+			slot: None,
+			retry_at: None,
 		}
 	}
+
+	fn covers(&self, height: u64) -> bool {
+		self.start_height < height && self.end_height >= height
+	}
+}
+
+/// Bytes of a RandomX dataset, and of the cache it's built from
+const DATASET_MEMORY: u64 = 2080 << 20;
+const CACHE_MEMORY: u64 = 256 << 20;
+/// Bytes of the scratchpad of a VM
+const VM_MEMORY: u64 = 2 << 20;
+
+/// A place a dataset is built in. With two of them, the dataset of the
+/// next epoch is built in one while the solvers hash with the other, and
+/// switching epochs is only a matter of which slot the solvers use
+struct DatasetSlot {
+	state: RwLock<RxState>,
+	/// seed of the dataset in it, none if there's none. Only changed with
+	/// `state` locked for writing
+	dataset: Mutex<Option<[u8; 32]>>,
+	/// bytes what was built in it takes, 0 until something was
+	memory: AtomicU64,
+}
+
+impl DatasetSlot {
+	/// Builds the dataset of that seed, or only the cache in light mode
+	fn build(&self, seed: &[u8; 32], threads: u64) -> Result<(), String> {
+		// waits for solvers still hashing with the slot, if there's only one
+		let mut rx = self.state.write().unwrap();
+		let mut dataset = self.dataset.lock().unwrap();
+		let memory = build_dataset(&mut *rx, &mut dataset, seed, threads)?;
+		self.memory.store(memory, Ordering::Relaxed);
+		Ok(())
+	}
+}
+
+/// What building a dataset takes of `RxState`, so it can be faked in tests
+trait DatasetBuilder {
+	fn full_mem(&self) -> bool;
+	fn init_cache(&mut self, seed: &[u8]) -> Result<RxAction, String>;
+	fn init_dataset(&mut self, threads_count: u8) -> Result<(), String>;
+	fn update_vms(&mut self);
+}
+
+impl DatasetBuilder for RxState {
+	fn full_mem(&self) -> bool {
+		self.full_mem
+	}
+
+	fn init_cache(&mut self, seed: &[u8]) -> Result<RxAction, String> {
+		RxState::init_cache(self, seed).map_err(|e| e.to_owned())
+	}
+
+	fn init_dataset(&mut self, threads_count: u8) -> Result<(), String> {
+		RxState::init_dataset(self, threads_count).map_err(|e| e.to_owned())
+	}
+
+	fn update_vms(&mut self) {
+		RxState::update_vms(self)
+	}
+}
+
+/// Builds the dataset of that seed, or only the cache in light mode, and
+/// returns the bytes they take. `dataset` is the seed of the dataset
+/// there is, kept up to date. The cache is left as it is when its seed is
+/// the same, even if building the dataset from it failed before, so the
+/// dataset is built whenever it isn't that seed's
+fn build_dataset<B: DatasetBuilder>(
+	rx: &mut B,
+	dataset: &mut Option<[u8; 32]>,
+	seed: &[u8; 32],
+	threads: u64,
+) -> Result<u64, String> {
+	let full_mem = rx.full_mem();
+	match rx.init_cache(seed) {
+		Ok(RxAction::NotChanged) => {}
+		// the dataset was built from the cache replaced, or being replaced
+		result => {
+			*dataset = None;
+			result?;
+		}
+	}
+	// light mode hashes from the cache, there's no dataset
+	if full_mem && *dataset != Some(*seed) {
+		*dataset = None;
+		rx.init_dataset(threads as u8)?;
+		*dataset = Some(*seed);
+	}
+	// VMs made for the slot before hash with the new dataset
+	rx.update_vms();
+	Ok(if full_mem {
+		DATASET_MEMORY + CACHE_MEMORY
+	} else {
+		CACHE_MEMORY
+	})
+}
+
+/// Attempts at building a dataset with the same settings, and the wait
+/// before the first retry, doubled for each one after
+const BUILD_ATTEMPTS: u32 = 3;
+const BUILD_BACKOFF: Duration = Duration::from_secs(2);
+/// Wait before building the dataset of an epoch again once it failed
+/// with every fallback
+const FAILED_RETRY: Duration = Duration::from_secs(60);
+
+/// Settings given up on to get a dataset built, in the order they're taken
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Fallback {
+	/// there may be no huge pages to be had
+	NoLargePages,
+	/// takes a fraction of the memory
+	Light,
+}
+
+impl fmt::Display for Fallback {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Fallback::NoLargePages => write!(f, "large_pages=false"),
+			Fallback::Light => write!(f, "light mode"),
+		}
+	}
+}
+
+/// How dataset builds got on, for the solvers to report
+struct Recovery {
+	large_pages: bool,
+	/// hashing from the cache only, see `RxConfig::mode`
+	light: bool,
+	/// taken so far
+	fallbacks: Vec<Fallback>,
+	/// why the last build failed, empty if none did
+	reason: String,
+	/// the last build failed even with every fallback, it's retried
+	/// after `FAILED_RETRY`
+	failed: bool,
+}
+
+impl Recovery {
+	/// The fallback to take next, none once all were
+	fn next(&self) -> Option<Fallback> {
+		if self.large_pages {
+			Some(Fallback::NoLargePages)
+		} else if !self.light {
+			Some(Fallback::Light)
+		} else {
+			None
+		}
+	}
+
+	/// Fallbacks taken so far, like "large_pages=false, light mode"
+	fn path(&self) -> String {
+		self.fallbacks
+			.iter()
+			.map(|f| f.to_string())
+			.collect::<Vec<String>>()
+			.join(", ")
+	}
+
+	/// Puts the name to report the solvers by, why the last build failed
+	/// and the fallbacks taken in their stats
+	fn report(&self, stats: &mut Stats) {
+		stats.set_plugin_name(if self.light {
+			LIGHT_ALGORITHM_NAME
+		} else {
+			ALGORITHM_NAME
+		});
+		stats.set_error_reason(&self.reason);
+		stats.fallback = self.path();
+	}
+}
+
+/// Dataset slots of each NUMA node, see `numa::Layout`, all nodes with
+/// the same slots
+type DatasetSlots = Arc<Vec<Vec<DatasetSlot>>>;
+
+/// Memory the datasets built so far take
+fn datasets_memory(slots: &[Vec<DatasetSlot>]) -> u64 {
+	slots
+		.iter()
+		.flat_map(|node| node.iter())
+		.map(|s| s.memory.load(Ordering::Relaxed))
+		.sum()
 }
 
 pub struct RxMiner {
 	/// Data shared across threads
-	pub shared_data: Arc<RwLock<JobSharedData>>,
+	pub shared_data: JobSharedDataType,
 
-	// randomx mining state
-	state: Arc<RwLock<RxState>>,
+	/// where the datasets are built, see `RxConfig::double_buffer`
+	slots: DatasetSlots,
+
+	/// NUMA nodes the datasets are on, and where the solvers run
+	layout: Layout,
+
+	/// affinity and priority of the threads
+	cpu_config: CpuConfig,
+
+	/// CPUs the threads may run on, if restricted
+	allowed: Option<Vec<usize>>,
 
 	/// Job control tx
 	control_txs: Vec<mpsc::Sender<ControlMessage>>,
@@ -75,10 +337,20 @@ pub struct RxMiner {
 	/// Solver has stopped and cleanly shutdown
 	solver_stopped_rxs: Vec<mpsc::Receiver<ControlMessage>>,
 
-	current_seed: [u8; 32],
+	/// which solver instances were paused or removed on their own
+	solver_states: SolverStates,
 
 	epochs: Arc<RwLock<Vec<EpochSeed>>>,
 
+	/// epochs loaded since the last get_loaded_epochs call
+	loaded_epochs: Arc<RwLock<Vec<(u64, u64)>>>,
+
+	/// subscriber solutions and stats are pushed to, if any
+	events_tx: Option<mpsc::Sender<MinerEvent>>,
+
+	/// fallbacks taken after builds failed, starting in the mode picked
+	recovery: Arc<Mutex<Recovery>>,
+
 	config: RxConfig,
 }
 
@@ -86,110 +358,283 @@ unsafe impl Send for RxMiner {}
 unsafe impl Sync for RxMiner {}
 
 impl RxMiner {
-	fn create_rx_state(config: &RxConfig) -> Arc<RwLock<RxState>> {
-		let mut rx_state = RxState::new();
+	/// Bytes the datasets of that many slots on each node and the VMs take
+	fn memory_needed(config: &RxConfig, nodes: usize, slots: u64) -> u64 {
+		slots * nodes as u64 * (DATASET_MEMORY + CACHE_MEMORY) + config.threads * VM_MEMORY
+	}
+
+	/// Whether to hash from the cache only, and whether to double buffer
+	/// the datasets, with that many bytes of memory available. In auto
+	/// mode, when the datasets and VMs don't fit with
+	/// `RxConfig::memory_headroom` to spare, double buffering is given up
+	/// first and full mode only when a single dataset doesn't fit either
+	fn memory_mode(config: &RxConfig, nodes: usize, available: Option<u64>) -> (bool, bool) {
+		let available = match (&config.mode, available) {
+			(RxMode::Full, _) | (RxMode::Auto, None) => return (false, config.double_buffer),
+			(RxMode::Light, _) => return (true, config.double_buffer),
+			(RxMode::Auto, Some(available)) => available.saturating_sub(config.memory_headroom << 20),
+		};
+		if available < RxMiner::memory_needed(config, nodes, 1) {
+			(true, config.double_buffer)
+		} else if available < RxMiner::memory_needed(config, nodes, 2) {
+			(false, false)
+		} else {
+			(false, config.double_buffer)
+		}
+	}
 
-		rx_state.full_mem = true;
+	fn create_slots(config: &RxConfig, light: bool, nodes: usize) -> DatasetSlots {
+		let count = if config.double_buffer { 2 } else { 1 };
+		let slots = (0..nodes)
+			.map(|_| {
+				(0..count)
+					.map(|_| {
+						let mut rx_state = RxState::new();
+						rx_state.full_mem = !light;
+						rx_state.hard_aes = config.hard_aes;
+						rx_state.large_pages = config.large_pages;
+						rx_state.jit_compiler = config.jit;
+
+						DatasetSlot {
+							state: RwLock::new(rx_state),
+							dataset: Mutex::new(None),
+							memory: AtomicU64::new(0),
+						}
+					})
+					.collect()
+			})
+			.collect();
+		Arc::new(slots)
+	}
 
-		rx_state.hard_aes = config.hard_aes;
-		rx_state.large_pages = config.large_pages;
-		rx_state.jit_compiler = config.jit;
+	/// Builds a dataset in that slot of every node, by threads pinned to
+	/// the node so the memory is allocated there
+	fn build_on_nodes(
+		slots: &DatasetSlots,
+		slot: usize,
+		seed: &[u8; 32],
+		layout: &Layout,
+		cpu_config: &CpuConfig,
+		allowed: Option<&[usize]>,
+	) -> Result<(), String> {
+		thread::scope(|scope| {
+			let builders: Vec<_> = layout
+				.nodes
+				.iter()
+				.enumerate()
+				.map(|(n, node)| {
+					let cpus = if node.cpus.is_empty() {
+						allowed
+					} else {
+						Some(&node.cpus[..])
+					};
+					scope.spawn(move || {
+						// only a matter of speed, the dataset is built anyway
+						if let Err(e) = cpu::apply(cpu_config, cpus) {
+							warn!(
+								LOGGER,
+								"Dataset builder on node {}: can't set its CPU affinity or priority: {}",
+								node.id,
+								e
+							);
+						}
+						let threads = layout.threads_on(n).max(1) as u64;
+						slots[n][slot].build(seed, threads)
+					})
+				})
+				.collect();
+			builders
+				.into_iter()
+				.map(|b| b.join().unwrap_or(Err("Dataset builder panicked".to_owned())))
+				.collect::<Result<Vec<()>, String>>()
+				.map(|_| ())
+		})
+	}
 
-		Arc::new(RwLock::new(rx_state))
+	/// Has all the slots built with the fallback from now on
+	fn fall_back(slots: &DatasetSlots, recovery: &Mutex<Recovery>, fallback: Fallback) {
+		warn!(LOGGER, "Building RandomX datasets with {} from now on", fallback);
+		for slot in slots.iter().flat_map(|node| node.iter()) {
+			let mut rx = slot.state.write().unwrap();
+			match fallback {
+				Fallback::NoLargePages => rx.large_pages = false,
+				Fallback::Light => rx.full_mem = false,
+			}
+		}
+		let mut recovery = recovery.lock().unwrap();
+		match fallback {
+			Fallback::NoLargePages => recovery.large_pages = false,
+			Fallback::Light => recovery.light = true,
+		}
+		recovery.fallbacks.push(fallback);
 	}
 
-	fn load_next_dataset(&mut self) -> Result<(), MinerError>
+	/// Starts building a dataset in the background, if none is being
+	/// built already: the one the job at that height needs, or else the
+	/// one of the next epoch, as long as there's a slot besides the one
+	/// in use. A build that fails is retried with backoff, then without
+	/// large pages and then in light mode. If it still fails the epoch is
+	/// tried again after `FAILED_RETRY`, the solvers wait meanwhile
+	fn load_next_dataset(&mut self, height: u64) -> Result<(), MinerError>
 	{
 		let epochs = self.epochs.clone();
-		let current_seed = self.current_seed.clone();
-		let threads = self.config.threads;
-		let rx_state = self.state.clone();
-		let is_loading = {
-			let epochs = epochs.read().unwrap();
-			(*epochs)
-				.iter()
-				.filter(|x| x.state == EpochState::Loading || x.state == EpochState::Loaded)
-				.count() > 0
-			||
-			(*epochs)
+		let loaded_epochs = self.loaded_epochs.clone();
+		let slots = self.slots.clone();
+		let layout = self.layout.clone();
+		let cpu_config = self.cpu_config.clone();
+		let allowed = self.allowed.clone();
+		let recovery = self.recovery.clone();
+		let solver_loop_txs = self.solver_loop_txs.clone();
+		let shared_data = self.shared_data.clone();
+		let now = Instant::now();
+
+		let (seed, slot) = {
+			let mut epochs = epochs.write().unwrap();
+			if epochs.iter().any(|x| x.state == EpochState::Loading) {
+				return Ok(());
+			}
+			let in_use = epochs
 				.iter()
-				.filter(|x| x.state == EpochState::Waiting)
-				.count() == 0
+				.find(|x| x.state == EpochState::Running && x.covers(height))
+				.and_then(|x| x.slot);
+			let next = match in_use {
+				None => epochs.iter().position(|x| {
+					let retry = match x.state {
+						EpochState::Failed(_) => x.retry_at.is_none_or(|at| at <= now),
+						_ => false,
+					};
+					x.covers(height)
+						&& (x.state == EpochState::Waiting || x.state == EpochState::Replaced || retry)
+				}),
+				Some(_) => epochs
+					.iter()
+					.position(|x| x.state == EpochState::Waiting && x.start_height >= height),
+			};
+			let slot = (0..slots[0].len()).find(|&s| Some(s) != in_use);
+			let (next, slot) = match (next, slot) {
+				(Some(next), Some(slot)) => (next, slot),
+				_ => return Ok(()),
+			};
+			for epoch in epochs.iter_mut().filter(|x| x.slot == Some(slot)) {
+				epoch.state = EpochState::Replaced;
+				epoch.slot = None;
+			}
+			let epoch = &mut epochs[next];
+			debug!(
+				LOGGER,
+				"Trying to load a new dataset for height: {} in slot {}", epoch.start_height, slot
+			);
+			epoch.state = EpochState::Loading;
+			epoch.slot = Some(slot);
+			epoch.retry_at = None;
+			(epoch.seed, slot)
 		};
-		if is_loading {
-			return Ok(());
-		}
 
+		thread::Builder::new()
+			.name("randomx_dataset".to_string())
+			.spawn(move || {
+				let mut attempt = 0;
+				let result = loop {
+					let e = match RxMiner::build_on_nodes(&slots, slot, &seed, &layout, &cpu_config, allowed.as_deref()) {
+						Ok(()) => break Ok(()),
+						Err(e) => e,
+					};
+					attempt += 1;
+					error!(LOGGER, "Building the RandomX dataset failed, attempt {}: {}", attempt, e);
+					let next = {
+						let mut recovery = recovery.lock().unwrap();
+						recovery.reason = e.clone();
+						recovery.next()
+					};
+					if attempt < BUILD_ATTEMPTS {
+						thread::sleep(BUILD_BACKOFF * (1 << (attempt - 1)));
+						continue;
+					}
+					match next {
+						Some(fallback) => {
+							RxMiner::fall_back(&slots, &recovery, fallback);
+							attempt = 0;
+						}
+						None => break Err(e),
+					}
+				};
+				recovery.lock().unwrap().failed = result.is_err();
 
-		debug!(LOGGER, "There's a new dataset waiting to be generate...");
-
-		thread::spawn(move || {
-			let mut seed = [0u8; 32];
-			let mut seed_changed = false;
-			{
 				let mut epochs = epochs.write().unwrap();
-				let mut epoch_first = (*epochs)
+				if let Some(epoch) = epochs
 					.iter_mut()
-					.filter(|x| x.state == EpochState::Waiting && x.seed != current_seed)
-					.next();
-				if let Some(ref mut epoch) = epoch_first {
-					debug!(LOGGER, "Trying to load a new dataset for height: {}", epoch.start_height);
-					seed = epoch.seed.clone();
-					seed_changed = true;
-					epoch.state = EpochState::Loading;
+					.find(|x| x.state == EpochState::Loading && x.seed == seed)
+				{
+					match result {
+						Ok(()) => {
+							loaded_epochs
+								.write()
+								.unwrap()
+								.push((epoch.start_height, epoch.end_height));
+							epoch.state = EpochState::Loaded;
+						}
+						Err(e) => {
+							error!(
+								LOGGER,
+								"Giving up on the RandomX dataset for heights {} to {} for now, retrying in {}s: {}",
+								epoch.start_height,
+								epoch.end_height,
+								FAILED_RETRY.as_secs(),
+								e
+							);
+							epoch.state = EpochState::Failed(e);
+							epoch.slot = None;
+							epoch.retry_at = Some(Instant::now() + FAILED_RETRY);
+						}
+					}
 				}
-			}
-
-			if seed_changed {
-				let mut result = EpochState::Loaded;
-				let mut rx = rx_state.write().unwrap();
-
-				if let Ok(RxAction::Changed) = rx.init_cache(&seed) {
-					if let Err(e) = rx.init_dataset(threads as u8) {
-						error!(LOGGER, "Tried to initialize a new dataset: {:?}", e);
-						result = EpochState::Failed(e.to_owned());
+				// built late after retries, solvers with nothing to hash
+				// with switch to it without waiting for the next job
+				let height = shared_data.job.load().height;
+				if !epochs.iter().any(|x| x.state == EpochState::Running && x.covers(height)) {
+					if let Some(epoch) = epochs
+						.iter_mut()
+						.find(|x| x.state == EpochState::Loaded && x.covers(height))
+					{
+						debug!(LOGGER, "Changing datasets.");
+						epoch.state = EpochState::Running;
 					}
-				} else {
-					result = EpochState::Failed("Is not possible initialize a new dataset".to_owned());
 				}
-
-				let mut epochs = epochs.write().unwrap();
-				let mut epoch_first = (*epochs)
-					.iter_mut()
-					.filter(|x| x.state == EpochState::Loading)
-					.next();
-
-				if let Some(ref mut epoch) = epoch_first {
-					epoch.state = result;
+				drop(epochs);
+				// solvers waiting for it report how it went, or start on it
+				for t in solver_loop_txs.iter() {
+					let _ = t.send(ControlMessage::SeedChanged);
 				}
-			}
-		});
+			})
+			.map_err(|e| MinerError::PluginProcessingError(format!("{}", e)))?;
 
 		Ok(())
 	}
 
+	/// Has the solvers hash with the dataset of the epoch of that height,
+	/// once it's loaded. The dataset used so far stays in its slot until
+	/// the slot is needed for another one
 	fn swap_dataset(&mut self, height: u64) -> Result<(), &'static str> {
 		let mut epochs = self.epochs.write().unwrap();
-		let mut epoch = epochs
-			.iter_mut()
-			.filter(|x| x.start_height < height && x.end_height >= height)
-			.next();
-
-		if let Some(ref mut e) = epoch {
-			match e.state.clone() {
-				EpochState::Failed(e) => {
-					panic!("{}", e);
-				},
-				EpochState::Loaded => {},
-				_ => return Ok(()),
-			}
+		let current = match epochs.iter().position(|x| x.covers(height)) {
+			Some(i) => i,
+			None => return Ok(()),
+		};
 
-			debug!(LOGGER, "Changing datasets.");
+		// a failed build is retried by load_next_dataset, the solvers wait
+		// meanwhile
+		if epochs[current].state != EpochState::Loaded {
+			return Ok(());
+		}
+
+		debug!(LOGGER, "Changing datasets.");
 
-			e.state = EpochState::Running;
-			self.current_seed = e.seed.clone();
-			let mut rx = self.state.write().unwrap();
-			rx.update_vms();
+		for epoch in epochs.iter_mut().filter(|x| x.state == EpochState::Running) {
+			epoch.state = EpochState::Loaded;
+		}
+		epochs[current].state = EpochState::Running;
+		for t in self.solver_loop_txs.iter() {
+			let _ = t.send(ControlMessage::SeedChanged);
 		}
 
 		Ok(())
@@ -197,27 +642,46 @@ impl RxMiner {
 
 	fn solver_thread(
 		instance: usize,
-		threads: u8,
-		state: Arc<RwLock<RxState>>,
+		datasets: DatasetSlots,
+		recovery: Arc<Mutex<Recovery>>,
+		layout: Layout,
+		cpu_config: CpuConfig,
+		cpus: Option<Vec<usize>>,
 		shared_data: JobSharedDataType,
 		epochs: Arc<RwLock<Vec<EpochSeed>>>,
+		mut events: EventSender,
 		control_rx: mpsc::Receiver<ControlMessage>,
 		solver_loop_rx: mpsc::Receiver<ControlMessage>,
 		solver_stopped_tx: mpsc::Sender<ControlMessage>,
 	) {
+		let (node, cpu) = layout.threads[instance];
+		let device = match cpu {
+			Some(cpu) => format!("cpu {} (node {})", cpu, layout.nodes[node].id),
+			None => "cpu".to_owned(),
+		};
+		if let Err(e) = cpu::apply(&cpu_config, cpus.as_deref()) {
+			warn!(LOGGER, "Solver {}: can't set its CPU affinity or priority: {}", instance, e);
+		}
+		let affinity = cpu::current_affinity();
+		let slots = &datasets[node];
 		{
-			let mut s = shared_data.write().unwrap();
-			s.stats[instance].set_plugin_name(ALGORITHM_NAME);
-			s.stats[instance].set_device_name("CPU");
+			let mut s = shared_data.stats[instance].lock().unwrap();
+			recovery.lock().unwrap().report(&mut s);
+			s.set_device_name(&device);
+			s.affinity = affinity.clone();
 		}
 
 		let mut iter_count = 0;
 		let mut last_solution_time = 0;
 		let mut paused = true;
-		let mut vm = None;
+		// no dataset for the job yet, waits for the next message
+		let mut waiting = false;
+		// a VM for each dataset slot, made the first time it's needed
+		let mut vms: Vec<Option<Arc<RwLock<RxVM>>>> = slots.iter().map(|_| None).collect();
+		let mut job = shared_data.job.load();
 
 		loop {
-			if let Some(message) = solver_loop_rx.try_iter().next() {
+			if let Some(message) = next_control_message(&solver_loop_rx, paused || waiting) {
 				debug!(LOGGER, "solver_thread - solver_loop_rx got msg: {:?}", message);
 				match message {
 					ControlMessage::Stop => break,
@@ -226,65 +690,85 @@ impl RxMiner {
 					_ => {}
 				}
 			}
+			waiting = false;
 
 			if paused {
-				{
-					let mut s = shared_data.write().unwrap();
-					s.stats[instance].set_plugin_name(ALGORITHM_NAME);
-					s.stats[instance].hashes_per_sec = 0;
-				}
-
-				thread::sleep(time::Duration::from_micros(100));
+				let mut s = shared_data.stats[instance].lock().unwrap();
+				recovery.lock().unwrap().report(&mut s);
+				s.hashes_per_sec = 0;
+				events.stats_now(instance, &s);
 				continue;
 			}
-			{
-				let mut s = shared_data.write().unwrap();
-				s.stats[instance].set_plugin_name(ALGORITHM_NAME);
-			}
 
-			if let None = vm {
-				let mut rx = state.write().unwrap();
+			if !shared_data.job.is_current(&job) {
+				job = shared_data.job.load();
+			}
+			let height = job.height;
+			let (slot, standby) = {
+				let epochs = epochs.read().unwrap();
+				let slot = epochs
+					.iter()
+					.find(|x| x.state == EpochState::Running && x.covers(height))
+					.and_then(|x| x.slot);
+				let standby = epochs
+					.iter()
+					.filter(|x| x.state == EpochState::Loaded)
+					.filter_map(|x| x.slot)
+					.find(|&s| vms[s].is_none());
+				(slot, standby)
+			};
 
-				if !rx.is_initialized() {
-					continue;
+			// a VM for the dataset built next, so it's ready to hash with
+			// as soon as it's switched to
+			if let Some(standby) = standby {
+				if let Ok(mut rx) = slots[standby].state.try_write() {
+					vms[standby] = rx.create_vm().ok();
 				}
-		
-				vm = Some(rx.create_vm().unwrap());
-				debug!(LOGGER, "RandomX VM initialized!");
 			}
 
-			let header_pre = { shared_data.read().unwrap().pre_nonce.clone() };
-			let header_post = { shared_data.read().unwrap().post_nonce.clone() };
-			let height = { shared_data.read().unwrap().height.clone() };
-			let epochs_state = { epochs.read().unwrap().iter().filter(|x| x.state == EpochState::Running && x.start_height < height && x.end_height >= height ).count() == 0 };
-			
-			if epochs_state {
-				{
-					let mut s = shared_data.write().unwrap();
-					s.stats[instance].hashes_per_sec = 0;
+			let slot = match slot {
+				Some(slot) => slot,
+				None => {
+					{
+						let mut s = shared_data.stats[instance].lock().unwrap();
+						let recovery = recovery.lock().unwrap();
+						recovery.report(&mut s);
+						// errored until a build of the dataset succeeds
+						s.has_errored = recovery.failed;
+						s.hashes_per_sec = 0;
+						events.stats_now(instance, &s);
+					}
+
+					// woken up by SeedChanged once swap_dataset made it current
+					waiting = true;
+					continue;
 				}
+			};
 
-				//debug!(LOGGER, "It is waiting the dataset finish the loading....");
-				thread::sleep(time::Duration::from_micros(100));
-				continue;
+			if vms[slot].is_none() {
+				match slots[slot].state.write().unwrap().create_vm() {
+					Ok(vm) => {
+						vms[slot] = Some(vm);
+						debug!(LOGGER, "RandomX VM initialized!");
+					}
+					Err(e) => {
+						error!(LOGGER, "Solver {}: can't create a RandomX VM: {}", instance, e);
+						waiting = true;
+						continue;
+					}
+				}
 			}
 
-			let job_id = { shared_data.read().unwrap().job_id.clone() };
-			let target_difficulty = { shared_data.read().unwrap().difficulty.clone() };
-			let header = util::get_next_header_data(&header_pre, &header_post);
-			let nonce = header.0;
-			let mut header = header.1;
+			let nonce = util::get_random_nonce();
+			let mut header = job.header(nonce);
 
-			let boundary = U256::max_value()
-				/ U256::from(if target_difficulty > 0 {
-					target_difficulty
-				} else {
-					1
-				});
+			let boundary = difficulty::target(job.difficulty);
 
 			let start = timestamp();
 			let results = {
-				let vm_ref = vm.as_ref().map(|x| x.write().unwrap()).unwrap();
+				// keeps the dataset from being rebuilt while hashing
+				let _dataset = slots[slot].state.read().unwrap();
+				let vm_ref = vms[slot].as_ref().map(|x| x.write().unwrap()).unwrap();
 				(0..MAX_HASHS)
 					.map(|x| calculate(&vm_ref, &mut header, nonce + x))
 					.collect::<Vec<U256>>()
@@ -292,32 +776,42 @@ impl RxMiner {
 			let end = timestamp();
 
 			iter_count += MAX_HASHS;
-			let still_valid = { height == shared_data.read().unwrap().height };
-			if still_valid {
-				let mut s = shared_data.write().unwrap();
-
+			if !shared_data.job.is_stale(&job) {
 				for (i, hash) in results.iter().enumerate().filter(|(i, &x)| x <= boundary) {
 					last_solution_time = timestamp();
-					s.solutions.push(Solution::new(
-						job_id as u64,
+					let solution = Solution::new(
+						job.job_id as u64,
 						nonce + i as u64,
 						AlgorithmParams::RandomX(hash.clone().into()),
-					));
+						instance,
+					);
+					if let Err(solution) = events.solution(solution) {
+						shared_data.solutions.lock().unwrap().push(solution);
+					}
 					break;
 				}
 
+				// the datasets are shared, they're counted once
+				let shared = if instance == 0 {
+					datasets_memory(&datasets)
+				} else {
+					0
+				};
 				let mut stats = Stats {
 					last_start_time: start,
 					last_end_time: end,
 					last_solution_time: last_solution_time,
 					iterations: iter_count as u32,
 					hashes_per_sec: (MAX_HASHS * 1000) / (end - start),
+					memory: shared + VM_MEMORY * vms.iter().filter(|v| v.is_some()).count() as u64,
+					affinity: affinity.clone(),
 					..Default::default()
 				};
 
-				stats.set_plugin_name(ALGORITHM_NAME);
-				stats.set_device_name("cpu");
-				s.stats[instance] = stats;
+				recovery.lock().unwrap().report(&mut stats);
+				stats.set_device_name(&device);
+				events.stats(instance, &stats);
+				*shared_data.stats[instance].lock().unwrap() = stats;
 			}
 		}
 
@@ -326,29 +820,112 @@ impl RxMiner {
 }
 
 impl Miner for RxMiner {
-	fn new(configs: &MinerConfig) -> RxMiner {
-		RxMiner {
-			state: RxMiner::create_rx_state(&configs.randomx_config),
+	/// A bad CPU list or NUMA layout is a configuration error, not
+	/// something to fall back from
+	fn new(configs: &MinerConfig) -> Result<RxMiner, MinerError> {
+		let config = &configs.randomx_config;
+		let allowed = cpu::allowed_cpus(&configs.cpu_config).map_err(MinerError::ParameterError)?;
+		let layout = Layout::new(config, Path::new(numa::SYSFS_ROOT), allowed.as_deref())
+			.map_err(MinerError::ParameterError)?;
+		let nodes = layout.nodes.len();
+		let available = meminfo_bytes("MemAvailable");
+		let (light, double_buffer) = RxMiner::memory_mode(config, nodes, available);
+		if light && config.mode == RxMode::Auto {
+			warn!(
+				LOGGER,
+				"Only {} MiB of memory available, RandomX needs {} MiB and {} MiB to spare to mine in full mode, mining in light mode",
+				available.unwrap_or(0) >> 20,
+				RxMiner::memory_needed(config, nodes, 1) >> 20,
+				config.memory_headroom
+			);
+		} else if config.double_buffer && !double_buffer {
+			warn!(
+				LOGGER,
+				"Only {} MiB of memory available, RandomX needs {} MiB and {} MiB to spare to double buffer the datasets, mining with a single one",
+				available.unwrap_or(0) >> 20,
+				RxMiner::memory_needed(config, nodes, 2) >> 20,
+				config.memory_headroom
+			);
+		}
+		let config = &RxConfig {
+			double_buffer: double_buffer,
+			..configs.randomx_config.clone()
+		};
+		info!(
+			LOGGER,
+			"RandomX mining in {} mode, with {} dataset(s) on each of {} NUMA node(s)",
+			if light { RxMode::Light } else { RxMode::Full },
+			if double_buffer { 2 } else { 1 },
+			nodes
+		);
+		Ok(RxMiner {
+			slots: RxMiner::create_slots(config, light, nodes),
+			layout: layout,
+			cpu_config: configs.cpu_config.clone(),
+			allowed: allowed,
 			control_txs: vec![],
 			solver_loop_txs: vec![],
 			solver_stopped_rxs: vec![],
-			config: configs.randomx_config.clone(),
-			shared_data: Arc::new(RwLock::new(JobSharedData::new(
-				configs.randomx_config.threads as usize,
-			))),
-			current_seed: [u8::max_value(); 32],
+			solver_states: SolverStates::new(0),
+			config: config.clone(),
+			shared_data: Arc::new(JobSharedData::new(config.threads as usize)),
 			epochs: Arc::new(RwLock::new(vec![])),
-		}
+			loaded_epochs: Arc::new(RwLock::new(vec![])),
+			events_tx: None,
+			recovery: Arc::new(Mutex::new(Recovery {
+				large_pages: config.large_pages,
+				light: light,
+				fallbacks: vec![],
+				reason: String::new(),
+				failed: false,
+			})),
+		})
+	}
+
+	fn capabilities(_configs: &MinerConfig) -> Result<Capabilities, MinerError> {
+		let memory = meminfo_bytes("MemTotal");
+		let cpu = DeviceInfo {
+			id: 0,
+			name: proc_value("/proc/cpuinfo", "model name").unwrap_or("CPU".to_owned()),
+			backend: Backend::Cpu,
+			memory: memory,
+			compute_units: thread::available_parallelism().ok().map(|n| n.get() as u32),
+			features: cpu_features(),
+			params: vec![],
+		};
+		let defaults = RxConfig::default();
+		Ok(Capabilities {
+			algorithms: vec![Algorithm::RandomX],
+			devices: vec![cpu],
+			params: vec![
+				ParamInfo::new("threads", defaults.threads),
+				ParamInfo::new("jit", defaults.jit),
+				ParamInfo::new("hard_aes", defaults.hard_aes),
+				ParamInfo::new("large_pages", defaults.large_pages),
+				ParamInfo::new("double_buffer", defaults.double_buffer),
+				ParamInfo::new("mode", defaults.mode),
+				ParamInfo::new("memory_headroom", defaults.memory_headroom),
+				ParamInfo::new("numa", defaults.numa),
+			],
+		})
 	}
 
 	fn start_solvers(&mut self) -> Result<(), MinerError> {
-		let _s = self.state.clone();
-		let threads = self.config.threads;
+		self.solver_states = SolverStates::new(self.config.threads as usize);
 
 		for i in 0..(self.config.threads as usize) {
-			let state = self.state.clone();
+			let slots = self.slots.clone();
+			let recovery = self.recovery.clone();
+			let layout = self.layout.clone();
+			let cpu_config = self.cpu_config.clone();
+			// a core of its own on NUMA machines, any allowed one otherwise
+			let cpus = match layout.threads[i].1 {
+				Some(cpu) => Some(vec![cpu]),
+				None => self.allowed.clone(),
+			};
 			let shared_data = self.shared_data.clone();
 			let epochs = self.epochs.clone();
+			let events = EventSender::new(self.events_tx.clone());
 
 			let (control_tx, control_rx) = mpsc::channel::<ControlMessage>();
 			let (solver_tx, solver_rx) = mpsc::channel::<ControlMessage>();
@@ -361,10 +938,14 @@ impl Miner for RxMiner {
 			thread::spawn(move || {
 				let _ = RxMiner::solver_thread(
 					i,
-					threads as u8,
-					state,
+					slots,
+					recovery,
+					layout,
+					cpu_config,
+					cpus,
 					shared_data,
 					epochs,
+					events,
 					control_rx,
 					solver_rx,
 					solver_stopped_tx,
@@ -375,17 +956,17 @@ impl Miner for RxMiner {
 		Ok(())
 	}
 
-	fn get_solutions(&self) -> Option<Vec<Solution>> {
-		let mut s = self.shared_data.write().unwrap();
-
-		let solutions = s.solutions.clone();
-		s.solutions.clear();
+	fn subscribe(&mut self, tx: mpsc::Sender<MinerEvent>) -> bool {
+		self.events_tx = Some(tx);
+		true
+	}
 
-		Some(solutions)
+	fn get_solutions(&self) -> Option<Vec<Solution>> {
+		Some(self.shared_data.take_solutions())
 	}
 
 	fn get_stats(&self) -> Result<Vec<Stats>, MinerError> {
-		Ok(self.shared_data.read().unwrap().stats.clone())
+		Ok(self.shared_data.stats())
 	}
 
 	fn notify(
@@ -397,27 +978,20 @@ impl Miner for RxMiner {
 		difficulty: u64,  /* The target difficulty, only sols greater than this difficulty will
 		                   * be returned. */
 	) -> Result<(), MinerError> {
+		let job = Job::new(job_id, height, pre_nonce, post_nonce, difficulty)?;
 		let mut paused = false;
-		{
-			let mut sd = self.shared_data.write().unwrap();
-			if height != sd.height {
-				// stop/pause any existing jobs if job is for a new
-				// height
-				self.pause_solvers();
-				paused = true;
-			}
-
-			sd.job_id = job_id;
-			sd.height = height;
-			sd.pre_nonce = pre_nonce.to_owned();
-			sd.post_nonce = post_nonce.to_owned();
-			sd.difficulty = difficulty;
+		if height != self.shared_data.job.load().height {
+			// stop/pause any existing jobs if job is for a new
+			// height
+			self.pause_solvers();
+			paused = true;
 		}
+		self.shared_data.job.publish(job);
 
 		let _ = self.swap_dataset(height);
+		self.load_next_dataset(height)?;
 
 		if paused {
-			let _ = self.load_next_dataset();
 			self.resume_solvers();
 		}
 
@@ -437,6 +1011,11 @@ impl Miner for RxMiner {
 		}
 	}
 
+	fn get_loaded_epochs(&self) -> Vec<(u64, u64)> {
+		let mut loaded = self.loaded_epochs.write().unwrap();
+		loaded.drain(..).collect()
+	}
+
 	/// #Description
 	///
 	/// Stops the current job, and signals for the loaded plugin to stop
@@ -457,6 +1036,7 @@ impl Miner for RxMiner {
 
 	/// Tells current solvers to stop and wait
 	fn pause_solvers(&self) {
+		self.solver_states.set_paused(true);
 		for t in self.control_txs.iter() {
 			let _ = t.send(ControlMessage::Pause);
 		}
@@ -468,15 +1048,44 @@ impl Miner for RxMiner {
 
 	/// Tells current solvers to stop and wait
 	fn resume_solvers(&self) {
-		for t in self.control_txs.iter() {
-			let _ = t.send(ControlMessage::Resume);
+		self.solver_states.set_paused(false);
+		for (i, t) in self.control_txs.iter().enumerate() {
+			if self.solver_states.is_enabled(i) {
+				let _ = t.send(ControlMessage::Resume);
+			}
 		}
-		for t in self.solver_loop_txs.iter() {
-			let _ = t.send(ControlMessage::Resume);
+		for (i, t) in self.solver_loop_txs.iter().enumerate() {
+			if self.solver_states.is_enabled(i) {
+				let _ = t.send(ControlMessage::Resume);
+			}
 		}
 		debug!(LOGGER, "Resume message sent");
 	}
 
+	fn pause_solver(&self, instance: usize) -> Result<(), MinerError> {
+		self.solver_states.set(instance, SolverState::Disabled)?;
+		let _ = self.control_txs[instance].send(ControlMessage::Pause);
+		let _ = self.solver_loop_txs[instance].send(ControlMessage::Pause);
+		Ok(())
+	}
+
+	fn resume_solver(&self, instance: usize) -> Result<(), MinerError> {
+		self.solver_states.set(instance, SolverState::Enabled)?;
+		// picked up by resume_solvers otherwise
+		if !self.solver_states.is_paused() {
+			let _ = self.control_txs[instance].send(ControlMessage::Resume);
+			let _ = self.solver_loop_txs[instance].send(ControlMessage::Resume);
+		}
+		Ok(())
+	}
+
+	fn remove_solver(&self, instance: usize) -> Result<(), MinerError> {
+		self.solver_states.set(instance, SolverState::Removed)?;
+		let _ = self.control_txs[instance].send(ControlMessage::Stop);
+		let _ = self.solver_loop_txs[instance].send(ControlMessage::Stop);
+		Ok(())
+	}
+
 	/// block until solvers have all exited
 	fn wait_for_solver_shutdown(&self) {
 		for r in self.solver_stopped_rxs.iter() {
@@ -492,3 +1101,117 @@ impl Miner for RxMiner {
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Keeps the cache of a seed the way `RxState` does, its dataset
+	/// builds fail as long as `failures` lasts
+	struct FakeBuilder {
+		full_mem: bool,
+		cache: Option<[u8; 32]>,
+		failures: u32,
+		datasets_built: u32,
+	}
+
+	impl DatasetBuilder for FakeBuilder {
+		fn full_mem(&self) -> bool {
+			self.full_mem
+		}
+
+		fn init_cache(&mut self, seed: &[u8]) -> Result<RxAction, String> {
+			if self.cache.as_ref().map(|c| &c[..]) == Some(seed) {
+				return Ok(RxAction::NotChanged);
+			}
+			let mut cache = [0; 32];
+			cache.copy_from_slice(seed);
+			self.cache = Some(cache);
+			Ok(RxAction::Changed)
+		}
+
+		fn init_dataset(&mut self, _threads_count: u8) -> Result<(), String> {
+			if self.failures > 0 {
+				self.failures -= 1;
+				return Err("out of memory".to_owned());
+			}
+			self.datasets_built += 1;
+			Ok(())
+		}
+
+		fn update_vms(&mut self) {}
+	}
+
+	#[test]
+	fn dataset_built_after_failure() {
+		let mut rx = FakeBuilder {
+			full_mem: true,
+			cache: None,
+			failures: 1,
+			datasets_built: 0,
+		};
+		let mut dataset = None;
+		let seed = [1; 32];
+		assert!(build_dataset(&mut rx, &mut dataset, &seed, 1).is_err());
+		assert_eq!(dataset, None);
+
+		// the cache is unchanged, the dataset is built all the same
+		assert_eq!(build_dataset(&mut rx, &mut dataset, &seed, 1), Ok(DATASET_MEMORY + CACHE_MEMORY));
+		assert_eq!((dataset, rx.datasets_built), (Some(seed), 1));
+
+		// and only once
+		assert!(build_dataset(&mut rx, &mut dataset, &seed, 1).is_ok());
+		assert_eq!(rx.datasets_built, 1);
+
+		// one from the cache of another seed is rebuilt
+		let other = [2; 32];
+		rx.failures = 1;
+		assert!(build_dataset(&mut rx, &mut dataset, &other, 1).is_err());
+		assert_eq!(dataset, None);
+
+		// light mode makes do with the cache
+		rx.full_mem = false;
+		assert_eq!(build_dataset(&mut rx, &mut dataset, &other, 1), Ok(CACHE_MEMORY));
+		assert_eq!((dataset, rx.datasets_built), (None, 1));
+		rx.full_mem = true;
+		assert!(build_dataset(&mut rx, &mut dataset, &other, 1).is_ok());
+		assert_eq!((dataset, rx.datasets_built), (Some(other), 2));
+	}
+
+	#[test]
+	fn memory_mode() {
+		let mib = |n: u64| Some(n << 20);
+		let mut config = RxConfig {
+			threads: 2,
+			double_buffer: true,
+			..RxConfig::default()
+		};
+		// 2336 MiB for a dataset and its cache, 4 for the VMs, 256 to spare
+		let single = 2336 + 4 + 256;
+		let double = 2 * 2336 + 4 + 256;
+
+		config.mode = RxMode::Full;
+		assert_eq!(RxMiner::memory_mode(&config, 1, mib(0)), (false, true));
+		config.mode = RxMode::Light;
+		assert_eq!(RxMiner::memory_mode(&config, 1, mib(100_000)), (true, true));
+
+		config.mode = RxMode::Auto;
+		assert_eq!(RxMiner::memory_mode(&config, 1, None), (false, true));
+		assert_eq!(RxMiner::memory_mode(&config, 1, mib(double)), (false, true));
+		// double buffering is given up before full mode
+		assert_eq!(RxMiner::memory_mode(&config, 1, mib(double - 1)), (false, false));
+		assert_eq!(RxMiner::memory_mode(&config, 1, mib(single)), (false, false));
+		assert_eq!(RxMiner::memory_mode(&config, 1, mib(single - 1)), (true, true));
+		// a dataset on each node
+		assert_eq!(RxMiner::memory_mode(&config, 2, mib(double)), (false, false));
+
+		config.double_buffer = false;
+		assert_eq!(RxMiner::memory_mode(&config, 1, mib(double)), (false, false));
+		assert_eq!(RxMiner::memory_mode(&config, 1, mib(single)), (false, false));
+		assert_eq!(RxMiner::memory_mode(&config, 1, mib(single - 1)), (true, false));
+
+		config.memory_headroom = 0;
+		assert_eq!(RxMiner::memory_mode(&config, 1, mib(single - 256)), (false, false));
+		assert_eq!(RxMiner::memory_mode(&config, 1, mib(single - 257)), (true, false));
+	}
+}
@@ -0,0 +1,134 @@
+// Copyright 2019 The Epic Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Where RandomX datasets live and solver threads run on NUMA machines.
+//!
+//! Reading the dataset from another socket's memory costs a lot of
+//! hashrate, so with more than one node each node gets a dataset of its
+//! own, built by threads pinned to it so its pages are allocated there,
+//! and each solver thread is pinned to a core of the node it hashes with.
+
+use std::fs;
+use std::path::Path;
+
+use core::config::RxConfig;
+use core::cpu;
+
+/// Where the kernel's sysfs is mounted
+pub const SYSFS_ROOT: &str = "/sys";
+
+/// A NUMA node and its CPUs
+#[derive(Debug, Clone, PartialEq)]
+pub struct Node {
+	pub id: usize,
+	pub cpus: Vec<usize>,
+}
+
+/// Nodes with CPUs as listed under `devices/system/node` of a sysfs
+/// root, by id. Empty if there's no such directory
+pub fn detect(root: &Path) -> Vec<Node> {
+	let entries = match fs::read_dir(root.join("devices/system/node")) {
+		Ok(entries) => entries,
+		Err(_) => return vec![],
+	};
+	let mut nodes: Vec<Node> = entries
+		.filter_map(|e| e.ok())
+		.filter_map(|e| {
+			let name = e.file_name().into_string().ok()?;
+			let id = name.strip_prefix("node")?.parse::<usize>().ok()?;
+			let list = fs::read_to_string(e.path().join("cpulist")).ok()?;
+			let cpus = cpu::parse_cpulist(&list).ok()?;
+			Some(Node { id: id, cpus: cpus })
+		})
+		// memory only nodes have nothing to run solvers on
+		.filter(|n| !n.cpus.is_empty())
+		.collect();
+	nodes.sort_by_key(|n| n.id);
+	nodes
+}
+
+/// What node each solver thread hashes on, and the core it's pinned to
+#[derive(Debug, Clone, PartialEq)]
+pub struct Layout {
+	/// a dataset is built for each
+	pub nodes: Vec<Node>,
+	/// node index and CPU of each solver thread, no CPU when not pinned
+	pub threads: Vec<(usize, Option<usize>)>,
+}
+
+impl Layout {
+	/// A single dataset and unpinned threads
+	pub fn single(threads: usize) -> Layout {
+		Layout {
+			nodes: vec![Node { id: 0, cpus: vec![] }],
+			threads: vec![(0, None); threads],
+		}
+	}
+
+	/// The layout configured, see `RxConfig::numa` and
+	/// `RxConfig::numa_nodes`, detecting nodes under that sysfs root
+	/// unless they're configured, and keeping to the allowed CPUs if
+	/// they're restricted, see `CpuConfig`. Threads are spread over the
+	/// nodes in turn, each on a core of its own as long as there are enough
+	pub fn new(config: &RxConfig, root: &Path, allowed: Option<&[usize]>) -> Result<Layout, String> {
+		let threads = config.threads as usize;
+		let is_allowed = |cpu: &usize| allowed.map_or(true, |a| a.contains(cpu));
+		let nodes = if !config.numa_nodes.is_empty() {
+			let mut nodes = vec![];
+			for (id, list) in config.numa_nodes.iter().enumerate() {
+				let cpus: Vec<usize> = cpu::parse_cpulist(list)?.into_iter().filter(is_allowed).collect();
+				if cpus.is_empty() {
+					return Err(format!("NUMA node {} has no CPUs allowed to mine on", id));
+				}
+				nodes.push(Node { id: id, cpus: cpus });
+			}
+			nodes
+		} else if config.numa {
+			detect(root)
+				.into_iter()
+				.map(|n| Node {
+					id: n.id,
+					cpus: n.cpus.into_iter().filter(is_allowed).collect(),
+				})
+				.filter(|n| !n.cpus.is_empty())
+				.collect()
+		} else {
+			vec![]
+		};
+		// a single node of the kernel's is left to the scheduler
+		if nodes.is_empty() || (nodes.len() == 1 && config.numa_nodes.is_empty()) {
+			return Ok(Layout::single(threads));
+		}
+
+		let mut used = vec![0; nodes.len()];
+		let threads = (0..threads)
+			.map(|i| {
+				let n = i % nodes.len();
+				let cpus = &nodes[n].cpus;
+				let cpu = cpus[used[n] % cpus.len()];
+				used[n] += 1;
+				(n, Some(cpu))
+			})
+			.collect();
+		Ok(Layout {
+			nodes: nodes,
+			threads: threads,
+		})
+	}
+
+	/// Solver threads hashing on that node
+	pub fn threads_on(&self, node: usize) -> usize {
+		self.threads.iter().filter(|(n, _)| *n == node).count()
+	}
+}
@@ -0,0 +1,131 @@
+// Copyright 2019 The Epic Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! NUMA layouts, read from fake sysfs trees
+
+extern crate epic_miner_core as core;
+extern crate randomx_miner;
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use core::config::RxConfig;
+use randomx_miner::numa::{detect, Layout, Node};
+
+/// A sysfs root with nodes of those CPU lists, removed when dropped
+struct FakeSysfs(PathBuf);
+
+impl FakeSysfs {
+	fn new(name: &str, cpulists: &[&str]) -> FakeSysfs {
+		let root = env::temp_dir().join(format!("epic-miner-numa-{}-{}", name, std::process::id()));
+		let nodes = root.join("devices/system/node");
+		fs::create_dir_all(nodes.join("power")).unwrap();
+		fs::write(nodes.join("possible"), format!("0-{}\n", cpulists.len() - 1)).unwrap();
+		for (i, list) in cpulists.iter().enumerate() {
+			let node = nodes.join(format!("node{}", i));
+			fs::create_dir_all(&node).unwrap();
+			fs::write(node.join("cpulist"), format!("{}\n", list)).unwrap();
+		}
+		FakeSysfs(root)
+	}
+}
+
+impl Drop for FakeSysfs {
+	fn drop(&mut self) {
+		let _ = fs::remove_dir_all(&self.0);
+	}
+}
+
+fn config(threads: u64) -> RxConfig {
+	let mut config = RxConfig::default();
+	config.threads = threads;
+	config
+}
+
+#[test]
+fn dual_socket() {
+	// the third node has memory but no CPUs
+	let sysfs = FakeSysfs::new("dual", &["0-3", "4-7", ""]);
+	assert_eq!(
+		detect(&sysfs.0),
+		vec![
+			Node { id: 0, cpus: vec![0, 1, 2, 3] },
+			Node { id: 1, cpus: vec![4, 5, 6, 7] },
+		]
+	);
+
+	let layout = Layout::new(&config(6), &sysfs.0, None).unwrap();
+	assert_eq!(layout.nodes.len(), 2);
+	assert_eq!(
+		layout.threads,
+		vec![
+			(0, Some(0)),
+			(1, Some(4)),
+			(0, Some(1)),
+			(1, Some(5)),
+			(0, Some(2)),
+			(1, Some(6)),
+		]
+	);
+	assert_eq!(layout.threads_on(0), 3);
+
+	// more threads than cores share them
+	let layout = Layout::new(&config(10), &sysfs.0, None).unwrap();
+	assert_eq!(layout.threads[8], (0, Some(0)));
+
+	// only the allowed CPUs are used, nodes without any are left out
+	let layout = Layout::new(&config(3), &sysfs.0, Some(&[1, 2, 5])).unwrap();
+	assert_eq!(layout.threads, vec![(0, Some(1)), (1, Some(5)), (0, Some(2))]);
+	let layout = Layout::new(&config(2), &sysfs.0, Some(&[4, 5])).unwrap();
+	assert_eq!(layout, Layout::single(2));
+
+	let mut off = config(6);
+	off.numa = false;
+	assert_eq!(Layout::new(&off, &sysfs.0, None).unwrap(), Layout::single(6));
+}
+
+#[test]
+fn single_node() {
+	let sysfs = FakeSysfs::new("single", &["0-7"]);
+	assert_eq!(detect(&sysfs.0).len(), 1);
+	assert_eq!(Layout::new(&config(4), &sysfs.0, None).unwrap(), Layout::single(4));
+
+	// no sysfs at all
+	let missing = sysfs.0.join("missing");
+	assert!(detect(&missing).is_empty());
+	assert_eq!(Layout::new(&config(4), &missing, None).unwrap(), Layout::single(4));
+}
+
+#[test]
+fn configured_layout() {
+	let sysfs = FakeSysfs::new("configured", &["0-3", "4-7"]);
+	let mut configured = config(3);
+	configured.numa_nodes = vec!["0,2".to_owned(), "5".to_owned()];
+	let layout = Layout::new(&configured, &sysfs.0, None).unwrap();
+	assert_eq!(layout.nodes[1], Node { id: 1, cpus: vec![5] });
+	assert_eq!(layout.threads, vec![(0, Some(0)), (1, Some(5)), (0, Some(2))]);
+
+	// a single configured node still pins
+	configured.numa_nodes = vec!["1-2".to_owned()];
+	let layout = Layout::new(&configured, &sysfs.0, None).unwrap();
+	assert_eq!(layout.threads, vec![(0, Some(1)), (0, Some(2)), (0, Some(1))]);
+
+	configured.numa_nodes = vec!["0-1".to_owned(), "2-3".to_owned()];
+	assert!(Layout::new(&configured, &sysfs.0, Some(&[0, 1])).is_err());
+	configured.numa_nodes = vec!["0-3".to_owned(), "".to_owned()];
+	assert!(Layout::new(&configured, &sysfs.0, None).is_err());
+	configured.numa_nodes = vec!["0-x".to_owned()];
+	assert!(Layout::new(&configured, &sysfs.0, None).is_err());
+}
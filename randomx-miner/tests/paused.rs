@@ -0,0 +1,78 @@
+// Copyright 2019 The Epic Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Solver threads with nothing to do have to sleep, not poll. Runs as its
+//! own test binary so the CPU time of the process is all theirs
+#![cfg(target_os = "linux")]
+
+extern crate epic_miner_core as core;
+extern crate randomx_miner;
+
+use std::fs;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use core::config::MinerConfig;
+use core::Miner;
+use randomx_miner::RxMiner;
+
+/// Clock ticks per second /proc reports CPU time in
+const TICKS_PER_SEC: f64 = 100.0;
+
+/// CPU time used by the process so far, user and system, in seconds
+fn cpu_time() -> f64 {
+	let stat = fs::read_to_string("/proc/self/stat").unwrap();
+	// the fields after the command name, starting with the state
+	let fields: Vec<&str> = stat[stat.rfind(')').unwrap() + 2..]
+		.split_whitespace()
+		.collect();
+	let utime: u64 = fields[11].parse().unwrap();
+	let stime: u64 = fields[12].parse().unwrap();
+	(utime + stime) as f64 / TICKS_PER_SEC
+}
+
+/// Share of a CPU the process used over the duration
+fn cpu_use(duration: Duration) -> f64 {
+	let start = Instant::now();
+	let before = cpu_time();
+	thread::sleep(duration);
+	(cpu_time() - before) / start.elapsed().as_secs_f64()
+}
+
+#[test]
+fn idle_solvers_sleep() {
+	let mut config = MinerConfig::default();
+	config.randomx_config.threads = 4;
+	let mut miner = RxMiner::new(&config).unwrap();
+	miner.start_solvers().unwrap();
+	// let the threads come up
+	thread::sleep(Duration::from_millis(200));
+
+	let paused = cpu_use(Duration::from_secs(2));
+	assert!(paused < 0.05, "paused solvers used {:.0}% CPU", paused * 100.0);
+
+	// resumed without a job or a dataset, there's still nothing to do
+	miner.resume_solvers();
+	thread::sleep(Duration::from_millis(200));
+	let waiting = cpu_use(Duration::from_secs(2));
+	assert!(waiting < 0.05, "solvers waiting for a dataset used {:.0}% CPU", waiting * 100.0);
+
+	miner.pause_solvers();
+	thread::sleep(Duration::from_millis(200));
+	let paused = cpu_use(Duration::from_secs(2));
+	assert!(paused < 0.05, "paused again, solvers used {:.0}% CPU", paused * 100.0);
+
+	miner.stop_solvers();
+	miner.wait_for_solver_shutdown();
+}
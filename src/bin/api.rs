@@ -0,0 +1,537 @@
+// Copyright 2019 The Epic Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Embedded HTTP API, serves the mining stats as JSON and accepts control
+//! commands which are forwarded to the mining and client controllers.
+//!
+//! Routes:
+//! * `GET /v1/status` - full miner stats
+//! * `GET /metrics` - stats in the Prometheus text format, see `metrics`
+//! * `POST /v1/pause`, `POST /v1/resume` - pause and resume mining
+//! * `POST /v1/solvers/pause`, `POST /v1/solvers/resume`,
+//!   `POST /v1/solvers/remove` - pause, resume or stop a single solver
+//!   instance, body `{"instance": 0}` with the index into `device_stats`
+//! * `POST /v1/pool` - switch stratum server, body `{"url": "host:port"}`
+//! * `POST /v1/loglevel` - change log level, body `{"level": "Debug"}`
+//! * `POST /v1/shutdown` - shut the miner down
+//! * `GET /v1/events` - WebSocket stream of miner events, see `events`
+//! * `GET /v1/pipelines` - name, algorithm and stats of every pipeline
+//!
+//! When several pipelines run, a `?pipeline=<name>` query parameter picks
+//! the one a request is about. Without it status, pool and solver requests
+//! go to the first pipeline, pause and resume to all of them.
+//!
+//! When an `api_secret` is set every request needs an
+//! `Authorization: Bearer <secret>` header. Browsers can't set headers on
+//! WebSocket connections, so a `?token=<secret>` query parameter is
+//! accepted as well. Without one only the `GET` routes are served, the
+//! miner can't be controlled.
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use serde_json;
+
+use crate::events::EventBus;
+use crate::metrics;
+use crate::pipeline::Pipeline;
+use crate::stats;
+use crate::types;
+use crate::websocket;
+use core::config::ApiConfig;
+use core::SolverState;
+use util::{self, LogLevel, LOGGER};
+
+/// Biggest request body we're willing to read
+const MAX_BODY_SIZE: usize = 64 * 1024;
+/// Connections served at once, WebSocket ones included. More are turned
+/// away
+const MAX_CONNECTIONS: usize = 32;
+
+#[derive(Deserialize)]
+struct SwitchPoolParams {
+	url: String,
+}
+
+#[derive(Deserialize)]
+struct SolverParams {
+	instance: usize,
+}
+
+#[derive(Deserialize)]
+struct LogLevelParams {
+	level: LogLevel,
+}
+
+#[derive(Serialize)]
+struct PipelineStatus<'a> {
+	name: &'a str,
+	algorithm: &'static str,
+	stats: stats::Stats,
+}
+
+struct Request {
+	method: String,
+	path: String,
+	token: Option<String>,
+	pipeline: Option<String>,
+	authorization: Option<String>,
+	websocket_key: Option<String>,
+	body: Vec<u8>,
+}
+
+struct Response {
+	code: u16,
+	content_type: &'static str,
+	body: String,
+}
+
+impl Response {
+	fn json(code: u16, body: String) -> Response {
+		Response {
+			code: code,
+			content_type: "application/json",
+			body: body,
+		}
+	}
+
+	fn ok() -> Response {
+		Response::json(200, "{\"result\":\"ok\"}".to_owned())
+	}
+
+	fn error(code: u16, message: &str) -> Response {
+		Response::json(code, serde_json::json!({ "error": message }).to_string())
+	}
+
+	fn reason(&self) -> &'static str {
+		match self.code {
+			200 => "OK",
+			400 => "Bad Request",
+			401 => "Unauthorized",
+			403 => "Forbidden",
+			404 => "Not Found",
+			405 => "Method Not Allowed",
+			503 => "Service Unavailable",
+			_ => "Internal Server Error",
+		}
+	}
+}
+
+/// API server, one thread accepting connections and one per connection,
+/// up to `MAX_CONNECTIONS`
+#[derive(Clone)]
+pub struct Server {
+	config: ApiConfig,
+	pipelines: Arc<Vec<Pipeline>>,
+	events: EventBus,
+	/// connections being served
+	connections: Arc<AtomicUsize>,
+}
+
+/// Counts a connection as served until dropped
+struct ConnectionGuard(Arc<AtomicUsize>);
+
+impl Drop for ConnectionGuard {
+	fn drop(&mut self) {
+		self.0.fetch_sub(1, Ordering::SeqCst);
+	}
+}
+
+impl Server {
+	pub fn new(config: ApiConfig, pipelines: Vec<Pipeline>, events: EventBus) -> Server {
+		Server {
+			config: config,
+			pipelines: Arc::new(pipelines),
+			events: events,
+			connections: Arc::new(AtomicUsize::new(0)),
+		}
+	}
+
+	/// Binds the listen address and starts serving requests in the background
+	pub fn start(self) -> Result<(), String> {
+		let listener = TcpListener::bind(&self.config.listen_addr).map_err(|e| {
+			format!(
+				"Can't bind API server to {}: {}",
+				self.config.listen_addr, e
+			)
+		})?;
+		if self.config.api_secret.is_none() {
+			warn!(
+				LOGGER,
+				"API server has no api_secret configured, anyone able to reach {} can read the stats, controlling the miner is disabled",
+				self.config.listen_addr
+			);
+		}
+		info!(LOGGER, "API server listening on {}", self.config.listen_addr);
+		let _ = thread::Builder::new()
+			.name("api".to_string())
+			.spawn(move || {
+				for stream in listener.incoming() {
+					match stream {
+						Ok(mut stream) => {
+							if self.connections.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+								self.connections.fetch_sub(1, Ordering::SeqCst);
+								debug!(LOGGER, "API server busy, turning a connection away");
+								write_response(&mut stream, &Response::error(503, "Too many connections"));
+								continue;
+							}
+							let guard = ConnectionGuard(self.connections.clone());
+							let server = self.clone();
+							let _ = thread::Builder::new()
+								.name("api_request".to_string())
+								.spawn(move || {
+									let _guard = guard;
+									server.handle_connection(stream)
+								});
+						}
+						Err(e) => error!(LOGGER, "API server connection failed: {}", e),
+					}
+				}
+			});
+		Ok(())
+	}
+
+	fn handle_connection(&self, mut stream: TcpStream) {
+		let _ = stream.set_read_timeout(Some(Duration::from_secs(5)));
+		let response = match read_request(&stream) {
+			Ok(req) => {
+				debug!(LOGGER, "API request: {} {}", req.method, req.path);
+				if let Err(response) = self.check_access(&req) {
+					response
+				} else if req.method == "GET" && req.path == "/v1/events" {
+					match req.websocket_key {
+						Some(key) => {
+							if let Err(e) = websocket::serve(stream, &key, self.events.subscribe()) {
+								debug!(LOGGER, "WebSocket connection failed: {}", e);
+							}
+							return;
+						}
+						None => Response::error(400, "Expected a WebSocket upgrade"),
+					}
+				} else {
+					self.route(req)
+				}
+			}
+			Err(e) => Response::error(400, &e),
+		};
+		write_response(&mut stream, &response);
+	}
+
+	/// With an api_secret the request has to come with it, without one
+	/// only stats can be read
+	fn check_access(&self, req: &Request) -> Result<(), Response> {
+		let secret = match self.config.api_secret {
+			Some(ref secret) => secret,
+			None if req.method == "GET" => return Ok(()),
+			None => {
+				return Err(Response::error(
+					403,
+					"Controlling the miner needs an api_secret configured",
+				))
+			}
+		};
+		let bearer = req
+			.authorization
+			.as_ref()
+			.and_then(|a| a.strip_prefix("Bearer "));
+		let token = req.token.as_deref();
+		if [bearer, token]
+			.iter()
+			.flatten()
+			.any(|t| secret_eq(t, secret))
+		{
+			Ok(())
+		} else {
+			Err(Response::error(401, "Missing or invalid bearer token"))
+		}
+	}
+
+	fn route(&self, req: Request) -> Response {
+		// all pipelines, or just the one asked for
+		let pipelines: Vec<&Pipeline> = match req.pipeline {
+			None => self.pipelines.iter().collect(),
+			Some(ref name) => match self.pipelines.iter().find(|p| &p.name == name) {
+				Some(p) => vec![p],
+				None => return Response::error(400, &format!("There's no pipeline {}", name)),
+			},
+		};
+		let pipeline = pipelines[0];
+		match (req.method.as_str(), req.path.as_str()) {
+			("GET", "/v1/status") => self.status(pipeline),
+			("GET", "/v1/pipelines") => self.pipelines(),
+			("GET", "/metrics") => self.metrics(),
+			("POST", "/v1/pause") => send_miner(&pipelines, || types::MinerMessage::Pause),
+			("POST", "/v1/resume") => send_miner(&pipelines, || types::MinerMessage::Resume),
+			("POST", "/v1/solvers/pause") => {
+				solver(pipeline, &req.body, types::MinerMessage::PauseSolver)
+			}
+			("POST", "/v1/solvers/resume") => {
+				solver(pipeline, &req.body, types::MinerMessage::ResumeSolver)
+			}
+			("POST", "/v1/solvers/remove") => {
+				solver(pipeline, &req.body, types::MinerMessage::RemoveSolver)
+			}
+			("POST", "/v1/pool") => match serde_json::from_slice::<SwitchPoolParams>(&req.body) {
+				Ok(params) => send_client(pipeline, types::ClientMessage::SwitchServer(params.url)),
+				Err(e) => Response::error(400, &format!("Invalid pool params: {}", e)),
+			},
+			("POST", "/v1/loglevel") => match serde_json::from_slice::<LogLevelParams>(&req.body) {
+				Ok(params) => {
+					info!(LOGGER, "Changing log level to {:?}", params.level);
+					util::set_log_level(&params.level);
+					Response::ok()
+				}
+				Err(e) => Response::error(400, &format!("Invalid log level params: {}", e)),
+			},
+			("POST", "/v1/shutdown") => {
+				info!(LOGGER, "Shutdown requested through the API");
+				let pipelines: Vec<&Pipeline> = self.pipelines.iter().collect();
+				for p in &pipelines {
+					let _ = p.client_tx.send(types::ClientMessage::Shutdown);
+				}
+				send_miner(&pipelines, || types::MinerMessage::Shutdown)
+			}
+			(_, "/v1/status") | (_, "/v1/pipelines") | (_, "/metrics") | (_, "/v1/events") | (_, "/v1/pause") | (_, "/v1/resume") | (_, "/v1/pool")
+			| (_, "/v1/solvers/pause") | (_, "/v1/solvers/resume") | (_, "/v1/solvers/remove")
+			| (_, "/v1/loglevel") | (_, "/v1/shutdown") => {
+				Response::error(405, "Method not allowed")
+			}
+			_ => Response::error(404, "Not found"),
+		}
+	}
+
+	fn status(&self, pipeline: &Pipeline) -> Response {
+		let stats = pipeline.stats.read().unwrap().clone();
+		match serde_json::to_string(&stats) {
+			Ok(body) => Response::json(200, body),
+			Err(e) => Response::error(500, &format!("Can't serialize stats: {}", e)),
+		}
+	}
+
+	fn pipelines(&self) -> Response {
+		let pipelines: Vec<PipelineStatus> = self
+			.pipelines
+			.iter()
+			.map(|p| PipelineStatus {
+				name: &p.name,
+				algorithm: metrics::algorithm_label(p.algorithm),
+				stats: p.stats.read().unwrap().clone(),
+			})
+			.collect();
+		match serde_json::to_string(&pipelines) {
+			Ok(body) => Response::json(200, body),
+			Err(e) => Response::error(500, &format!("Can't serialize stats: {}", e)),
+		}
+	}
+
+	fn metrics(&self) -> Response {
+		let snapshots: Vec<_> = self
+			.pipelines
+			.iter()
+			.map(|p| (p.name.clone(), p.algorithm, p.stats.read().unwrap().clone()))
+			.collect();
+		Response {
+			code: 200,
+			content_type: "text/plain; version=0.0.4",
+			body: metrics::render(&snapshots),
+		}
+	}
+}
+
+/// Checks the instance exists and is still around before sending the
+/// message, so typos get an error instead of a silent no-op
+fn solver(pipeline: &Pipeline, body: &[u8], message: fn(usize) -> types::MinerMessage) -> Response {
+	let instance = match serde_json::from_slice::<SolverParams>(body) {
+		Ok(params) => params.instance,
+		Err(e) => return Response::error(400, &format!("Invalid solver params: {}", e)),
+	};
+	let state = {
+		let stats = pipeline.stats.read().unwrap();
+		stats.mining_stats.device_stats.get(instance).map(|s| s.state)
+	};
+	match state {
+		None => Response::error(400, &format!("There's no solver instance {}", instance)),
+		Some(SolverState::Removed) => {
+			Response::error(400, &format!("Solver instance {} was removed", instance))
+		}
+		Some(_) => match pipeline.miner_tx.send(message(instance)) {
+			Ok(_) => Response::ok(),
+			Err(_) => Response::error(500, "Mining controller is not running"),
+		},
+	}
+}
+
+/// Compares a token with the secret, taking as long wherever they differ
+fn secret_eq(token: &str, secret: &str) -> bool {
+	let (token, secret) = (token.as_bytes(), secret.as_bytes());
+	token.len() == secret.len()
+		&& token
+			.iter()
+			.zip(secret)
+			.fold(0, |diff, (a, b)| diff | (a ^ b))
+			== 0
+}
+
+fn write_response(stream: &mut TcpStream, response: &Response) {
+	let head = format!(
+		"HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+		response.code,
+		response.reason(),
+		response.content_type,
+		response.body.len()
+	);
+	let _ = stream.write_all(head.as_bytes());
+	let _ = stream.write_all(response.body.as_bytes());
+	let _ = stream.flush();
+}
+
+fn send_miner(pipelines: &[&Pipeline], message: fn() -> types::MinerMessage) -> Response {
+	for p in pipelines {
+		if p.miner_tx.send(message()).is_err() {
+			return Response::error(500, "Mining controller is not running");
+		}
+	}
+	Response::ok()
+}
+
+fn send_client(pipeline: &Pipeline, message: types::ClientMessage) -> Response {
+	match pipeline.client_tx.send(message) {
+		Ok(_) => Response::ok(),
+		Err(_) => Response::error(500, "Client controller is not running"),
+	}
+}
+
+/// Reads a single HTTP/1.1 request, just enough of it for our purposes
+fn read_request(stream: &TcpStream) -> Result<Request, String> {
+	let mut reader = BufReader::new(stream);
+	let mut line = String::new();
+	reader
+		.read_line(&mut line)
+		.map_err(|e| format!("Can't read request: {}", e))?;
+	let mut parts = line.split_whitespace();
+	let method = parts.next().ok_or("Empty request")?.to_owned();
+	let target = parts.next().ok_or("Missing request path")?;
+	let (path, query) = match target.find('?') {
+		Some(pos) => (target[..pos].to_owned(), &target[pos + 1..]),
+		None => (target.to_owned(), ""),
+	};
+	let param = |name: &str| {
+		query
+			.split('&')
+			.find(|p| p.starts_with(name) && p[name.len()..].starts_with('='))
+			.map(|p| p[name.len() + 1..].to_owned())
+	};
+
+	let mut authorization = None;
+	let mut websocket_key = None;
+	let mut upgrade = false;
+	let mut content_length = 0;
+	loop {
+		let mut header = String::new();
+		reader
+			.read_line(&mut header)
+			.map_err(|e| format!("Can't read request headers: {}", e))?;
+		let header = header.trim_end();
+		if header.is_empty() {
+			break;
+		}
+		if let Some(pos) = header.find(':') {
+			let (name, value) = header.split_at(pos);
+			let value = value[1..].trim();
+			match name.to_lowercase().as_str() {
+				"authorization" => authorization = Some(value.to_owned()),
+				"sec-websocket-key" => websocket_key = Some(value.to_owned()),
+				"upgrade" => upgrade = value.eq_ignore_ascii_case("websocket"),
+				"content-length" => {
+					content_length = value
+						.parse::<usize>()
+						.map_err(|_| "Invalid Content-Length".to_owned())?
+				}
+				_ => {}
+			}
+		}
+	}
+	if content_length > MAX_BODY_SIZE {
+		return Err("Request body too large".to_owned());
+	}
+	let mut body = vec![0; content_length];
+	reader
+		.read_exact(&mut body)
+		.map_err(|e| format!("Can't read request body: {}", e))?;
+
+	Ok(Request {
+		method: method,
+		path: path,
+		token: param("token"),
+		pipeline: param("pipeline"),
+		authorization: authorization,
+		websocket_key: if upgrade { websocket_key } else { None },
+		body: body,
+	})
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	fn request(method: &str, authorization: Option<&str>, token: Option<&str>) -> Request {
+		Request {
+			method: method.to_owned(),
+			path: "/v1/pause".to_owned(),
+			token: token.map(|t| t.to_owned()),
+			pipeline: None,
+			authorization: authorization.map(|a| a.to_owned()),
+			websocket_key: None,
+			body: vec![],
+		}
+	}
+
+	fn server(api_secret: Option<&str>) -> Server {
+		let config = ApiConfig {
+			api_secret: api_secret.map(|s| s.to_owned()),
+			..Default::default()
+		};
+		Server::new(config, vec![], EventBus::new())
+	}
+
+	fn code(server: &Server, req: &Request) -> u16 {
+		server.check_access(req).err().map_or(200, |r| r.code)
+	}
+
+	#[test]
+	fn access() {
+		// without a secret the miner can be watched, not controlled
+		let open = server(None);
+		assert_eq!(code(&open, &request("GET", None, None)), 200);
+		assert_eq!(code(&open, &request("POST", None, None)), 403);
+		assert_eq!(code(&open, &request("POST", Some("Bearer x"), None)), 403);
+
+		let locked = server(Some("s3cret"));
+		assert_eq!(code(&locked, &request("GET", None, None)), 401);
+		assert_eq!(code(&locked, &request("POST", Some("Bearer s3cret"), None)), 200);
+		assert_eq!(code(&locked, &request("GET", None, Some("s3cret"))), 200);
+		assert_eq!(code(&locked, &request("POST", Some("s3cret"), None)), 401);
+		assert_eq!(code(&locked, &request("POST", Some("Bearer s3cre"), None)), 401);
+		assert_eq!(code(&locked, &request("POST", Some("Bearer x"), Some("s3cret"))), 200);
+
+		assert!(secret_eq("abc", "abc"));
+		assert!(!secret_eq("abd", "abc"));
+		assert!(!secret_eq("abcd", "abc"));
+		assert!(!secret_eq("", "abc"));
+	}
+}
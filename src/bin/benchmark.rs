@@ -0,0 +1,386 @@
+// Copyright 2019 The Epic Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Offline benchmark of the miners compiled in. A synthetic job is fed
+//! through `Miner::notify` like a pool job would be, and each device is
+//! measured from the end of its first unit of work (a RandomX batch, a
+//! graph, a ProgPow kernel run), so dataset initialization, plugin loading
+//! and the like count as warm-up rather than against the rate.
+
+use std::fs;
+use std::io::{self, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use core::config::MinerConfig;
+use core::errors::MinerError;
+use core::difficulty::Hash;
+use core::{Algorithm, Header, MinerEntry, Stats};
+
+/// Height of the benchmark job, inside the RandomX epoch added for it
+const BENCH_HEIGHT: u64 = 1;
+const BENCH_JOB_ID: u32 = 1;
+/// Every hash or graph meets it, the solutions are thrown away
+const BENCH_DIFFICULTY: u64 = 1;
+/// Seed of the RandomX epoch the benchmark job falls in
+const BENCH_SEED: [u8; 32] = [1; 32];
+
+/// Devices that haven't finished any work by then are reported as failed
+const WARMUP_TIMEOUT: Duration = Duration::from_secs(600);
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// The job is sent again this often, like pools do
+const NOTIFY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Pre-pow header of a made up block. The content doesn't matter as it's
+/// never submitted
+pub fn header_template(height: u64) -> String {
+	Header {
+		version: 1,
+		height: height,
+		timestamp: 1_546_300_800,
+		prev_hash: Hash::from_bytes([1; 32]),
+		prev_root: Hash::from_bytes([2; 32]),
+		output_root: Hash::from_bytes([3; 32]),
+		range_proof_root: Hash::from_bytes([4; 32]),
+		kernel_root: Hash::from_bytes([5; 32]),
+		total_kernel_offset: Hash::from_bytes([6; 32]),
+		output_mmr_size: 1_000,
+		kernel_mmr_size: 1_000,
+		total_difficulty: vec![1],
+		secondary_scaling: 1_856,
+		nonce: 0,
+	}
+	.to_hex()
+}
+
+/// Value of a `key: <n> kB` line in a /proc file, in bytes
+pub fn proc_kb(path: &str, key: &str) -> Option<u64> {
+	let contents = fs::read_to_string(path).ok()?;
+	contents
+		.lines()
+		.find(|l| l.starts_with(key) && l[key.len()..].starts_with(':'))
+		.and_then(|l| l[key.len() + 1..].trim().trim_end_matches("kB").trim().parse::<u64>().ok())
+		.map(|kb| kb * 1024)
+}
+
+/// Memory the process has resident, where it can be found
+fn resident_memory() -> Option<u64> {
+	proc_kb("/proc/self/status", "VmRSS")
+}
+
+/// Unit rates of the algorithm are reported in
+pub fn rate_unit(algorithm: Algorithm) -> &'static str {
+	match algorithm {
+		Algorithm::Cuckoo => "gps",
+		_ => "hps",
+	}
+}
+
+/// Result for a single solver instance
+#[derive(Debug, Clone, Serialize)]
+pub struct DeviceResult {
+	pub instance: usize,
+	pub device_id: u32,
+	pub device_name: String,
+	pub plugin: String,
+	/// steady state graphs or hashes per second
+	pub rate: f64,
+	/// seconds until the first unit of work finished, none if it never did
+	pub warmup_secs: Option<f64>,
+	pub error: Option<String>,
+}
+
+/// Result for a miner, or why it couldn't be benchmarked
+#[derive(Debug, Clone, Serialize)]
+pub struct MinerResult {
+	pub miner: String,
+	pub algorithm: Algorithm,
+	/// gps or hps
+	pub unit: String,
+	/// seconds until every device finished its first unit of work
+	pub warmup_secs: f64,
+	/// seconds the rates were measured over
+	pub duration_secs: f64,
+	pub total_rate: f64,
+	/// peak bytes of host memory the miner took, on top of what was in
+	/// use before, if it could be measured. Device memory isn't included
+	pub memory: Option<u64>,
+	pub devices: Vec<DeviceResult>,
+	pub error: Option<String>,
+}
+
+impl MinerResult {
+	pub fn failed(entry: &MinerEntry, error: MinerError) -> MinerResult {
+		MinerResult {
+			miner: entry.name.to_owned(),
+			algorithm: entry.algorithm,
+			unit: rate_unit(entry.algorithm).to_owned(),
+			warmup_secs: 0.0,
+			duration_secs: 0.0,
+			total_rate: 0.0,
+			memory: None,
+			devices: vec![],
+			error: Some(format!("{}", error)),
+		}
+	}
+}
+
+/// Everything a benchmark run produced, with what's needed to compare it
+/// with runs on other machines or builds
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+	pub version: String,
+	pub commit: Option<String>,
+	pub target: String,
+	pub features: String,
+	/// unix time the benchmark started
+	pub timestamp: i64,
+	pub results: Vec<MinerResult>,
+}
+
+/// Progress of a solver instance over the run
+#[derive(Clone, Default)]
+struct DeviceProgress {
+	stats: Stats,
+	/// when the iterations first and last moved, and to what
+	first: Option<(Instant, u32)>,
+	last: Option<(Instant, u32)>,
+}
+
+impl DeviceProgress {
+	fn update(&mut self, now: Instant, stats: Stats) {
+		let moved = match self.last {
+			Some((_, iterations)) => stats.iterations != iterations,
+			None => stats.iterations > 0,
+		};
+		if moved {
+			if self.first.is_none() {
+				self.first = Some((now, stats.iterations));
+			}
+			self.last = Some((now, stats.iterations));
+		}
+		self.stats = stats;
+	}
+
+	fn is_warm(&self) -> bool {
+		self.first.is_some() || self.stats.has_errored
+	}
+
+	/// Work done per second since the first unit finished, or what the
+	/// solver reported itself if it hasn't moved since
+	fn rate(&self, algorithm: Algorithm) -> f64 {
+		if let (Some((t0, i0)), Some((t1, i1))) = (self.first, self.last) {
+			let secs = (t1 - t0).as_secs() as f64 + (t1 - t0).subsec_nanos() as f64 / 1e9;
+			if secs > 0.0 && i1 > i0 {
+				return (i1 - i0) as f64 / secs;
+			}
+		}
+		match algorithm {
+			Algorithm::Cuckoo if self.stats.last_solution_time > 0 => {
+				1e9 / self.stats.last_solution_time as f64
+			}
+			Algorithm::Cuckoo => 0.0,
+			_ => self.stats.hashes_per_sec as f64,
+		}
+	}
+}
+
+fn secs(d: Duration) -> f64 {
+	d.as_secs() as f64 + d.subsec_nanos() as f64 / 1e9
+}
+
+/// Builds the miner, runs it on the benchmark job for the duration once
+/// warmed up, and shuts it down again
+pub fn run(entry: &MinerEntry, config: &MinerConfig, duration: Duration) -> MinerResult {
+	let base_memory = resident_memory();
+	let mut peak_memory = base_memory;
+	let mut miner = match (entry.build)(config) {
+		Ok(m) => m,
+		Err(e) => return MinerResult::failed(entry, e),
+	};
+	if let Err(e) = miner.start_solvers() {
+		return MinerResult::failed(entry, e);
+	}
+	let start = Instant::now();
+	let header = header_template(BENCH_HEIGHT);
+	miner.add_epoch(0, BENCH_HEIGHT + 1_000, BENCH_SEED);
+
+	let mut devices: Vec<DeviceProgress> = vec![];
+	let mut last_notify: Option<Instant> = None;
+	let mut warm_at: Option<Instant> = None;
+	let mut error = None;
+	loop {
+		let now = Instant::now();
+		if !miner.get_loaded_epochs().is_empty() {
+			// swap the dataset in right away
+			last_notify = None;
+		}
+		if last_notify.map_or(true, |t| now - t >= NOTIFY_INTERVAL) {
+			if let Err(e) = miner.notify(BENCH_JOB_ID, BENCH_HEIGHT, &header, "", BENCH_DIFFICULTY) {
+				error = Some(format!("{}", e));
+				break;
+			}
+			last_notify = Some(now);
+		}
+		let _ = miner.get_solutions();
+		peak_memory = peak_memory.max(resident_memory());
+		match miner.get_stats() {
+			Ok(stats) => {
+				devices.resize(stats.len(), DeviceProgress::default());
+				for (d, s) in devices.iter_mut().zip(stats.into_iter()) {
+					d.update(now, s);
+				}
+			}
+			Err(e) => {
+				error = Some(format!("{}", e));
+				break;
+			}
+		}
+		match warm_at {
+			Some(t) if now - t >= duration => break,
+			Some(_) => {}
+			None if !devices.is_empty() && devices.iter().all(|d| d.is_warm()) => {
+				warm_at = Some(now)
+			}
+			None if now - start >= WARMUP_TIMEOUT => {
+				error = Some(format!(
+					"Not every device finished warming up within {}s",
+					WARMUP_TIMEOUT.as_secs()
+				));
+				break;
+			}
+			None => {}
+		}
+		thread::sleep(POLL_INTERVAL);
+	}
+	miner.stop_solvers();
+	miner.wait_for_solver_shutdown();
+
+	let devices: Vec<DeviceResult> = devices
+		.iter()
+		.enumerate()
+		.map(|(i, d)| DeviceResult {
+			instance: i,
+			device_id: d.stats.device_id,
+			device_name: d.stats.get_device_name(),
+			plugin: d.stats.get_plugin_name(),
+			rate: d.rate(entry.algorithm),
+			warmup_secs: d.first.map(|(t, _)| secs(t - start)),
+			error: if d.stats.has_errored {
+				Some(d.stats.get_error_reason())
+			} else {
+				None
+			},
+		})
+		.collect();
+	MinerResult {
+		miner: entry.name.to_owned(),
+		algorithm: entry.algorithm,
+		unit: rate_unit(entry.algorithm).to_owned(),
+		warmup_secs: warm_at.map_or(0.0, |t| secs(t - start)),
+		duration_secs: warm_at.map_or(0.0, |t| secs(t.elapsed())),
+		total_rate: devices.iter().map(|d| d.rate).sum(),
+		memory: match (base_memory, peak_memory) {
+			(Some(base), Some(peak)) => Some(peak.saturating_sub(base)),
+			_ => None,
+		},
+		devices: devices,
+		error: error,
+	}
+}
+
+/// Writes the report as a table, a line per device and a total per miner
+pub fn write_table(report: &Report, out: &mut dyn Write) -> io::Result<()> {
+	writeln!(
+		out,
+		"{:<10} {:<9} {:<32} {:>16} {:>10} {:>10}",
+		"miner", "algorithm", "device", "rate", "warm-up", "memory"
+	)?;
+	for r in &report.results {
+		let algorithm = format!("{:?}", r.algorithm);
+		if let Some(ref e) = r.error {
+			if r.devices.is_empty() {
+				writeln!(out, "{:<10} {:<9} {}", r.miner, algorithm, e)?;
+				continue;
+			}
+		}
+		for d in &r.devices {
+			let device = format!("{} {} ({})", d.instance, d.device_name, d.plugin);
+			let warmup = match d.warmup_secs {
+				Some(w) => format!("{:.1}s", w),
+				None => "-".to_owned(),
+			};
+			writeln!(
+				out,
+				"{:<10} {:<9} {:<32} {:>12.2} {} {:>10}",
+				r.miner, algorithm, device, d.rate, r.unit, warmup
+			)?;
+			if let Some(ref e) = d.error {
+				writeln!(out, "{:<10} {:<9}   errored: {}", "", "", e)?;
+			}
+		}
+		let memory = match r.memory {
+			Some(m) => format!("{} MiB", m >> 20),
+			None => "-".to_owned(),
+		};
+		writeln!(
+			out,
+			"{:<10} {:<9} {:<32} {:>12.2} {} {:>9.1}s {:>10}",
+			r.miner, algorithm, "total", r.total_rate, r.unit, r.warmup_secs, memory
+		)?;
+		if let Some(ref e) = r.error {
+			writeln!(out, "{:<10} {:<9}   {}", "", "", e)?;
+		}
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn device_rate() {
+		let start = Instant::now();
+		let mut d = DeviceProgress::default();
+		let mut stats = Stats::default();
+		stats.hashes_per_sec = 500;
+		d.update(start, stats.clone());
+		assert!(!d.is_warm());
+		assert_eq!(d.rate(Algorithm::RandomX), 500.0);
+
+		// measured from the end of the first batch on
+		stats.iterations = 100;
+		d.update(start + Duration::from_secs(5), stats.clone());
+		assert!(d.is_warm());
+		stats.iterations = 1_100;
+		d.update(start + Duration::from_secs(7), stats.clone());
+		d.update(start + Duration::from_secs(8), stats.clone());
+		assert_eq!(d.rate(Algorithm::RandomX), 500.0);
+
+		let mut g = DeviceProgress::default();
+		stats.iterations = 1;
+		stats.last_solution_time = 250_000_000;
+		g.update(start, stats);
+		assert_eq!(g.rate(Algorithm::Cuckoo), 4.0);
+	}
+
+	#[test]
+	fn header() {
+		let header = header_template(BENCH_HEIGHT);
+		assert_eq!(header.len(), 238 * 2);
+		assert!(header.starts_with("00010000000000000001"));
+		assert!(header.ends_with("00000740"));
+	}
+}
@@ -21,12 +21,17 @@ use native_tls::{TlsConnector, TlsStream};
 use serde_json;
 
 use std;
+use std::collections::VecDeque;
+use std::fmt;
 use std::io::{self, BufRead, ErrorKind, Read, Write};
 use std::net::TcpStream;
 use std::sync::{mpsc, Arc, RwLock};
 use std::thread;
+use std::time::Instant;
 use time;
 
+use crate::events::{Event, EventBus};
+use crate::history::{History, ShareRecord, ShareResult};
 use crate::stats;
 use crate::types;
 use crate::util::LOGGER;
@@ -42,6 +47,31 @@ pub enum Error {
 	GeneralError(String),
 }
 
+impl fmt::Display for Error {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			Error::ConnectionError(ref message) => write!(f, "Connection failed: {}", message),
+			Error::RequestError(ref message) => write!(f, "Invalid request: {}", message),
+			Error::ResponseError(ref message) => write!(f, "Invalid response: {}", message),
+			Error::JsonError(ref message) | Error::GeneralError(ref message) => {
+				write!(f, "{}", message)
+			}
+		}
+	}
+}
+
+impl std::error::Error for Error {}
+
+impl Error {
+	/// Whether the request may succeed on another try or connection
+	pub fn is_retryable(&self) -> bool {
+		match *self {
+			Error::ConnectionError(_) | Error::ResponseError(_) | Error::JsonError(_) => true,
+			Error::RequestError(_) | Error::GeneralError(_) => false,
+		}
+	}
+}
+
 impl From<serde_json::error::Error> for Error {
 	fn from(error: serde_json::error::Error) -> Self {
 		Error::JsonError(format!("Failed to parse JSON: {:?}", error))
@@ -163,6 +193,19 @@ impl BufRead for Stream {
 	}
 }
 
+/// How many unanswered submits we keep track of
+const MAX_PENDING_SUBMITS: usize = 1000;
+
+/// A share sent to the server, waiting for its answer
+struct PendingSubmit {
+	id: String,
+	sent: Instant,
+	job_id: u64,
+	height: u64,
+	difficulty: u64,
+	device: String,
+}
+
 pub struct Controller {
 	_id: u32,
 	algorithm: Algorithm,
@@ -170,12 +213,23 @@ pub struct Controller {
 	server_login: Option<String>,
 	server_password: Option<String>,
 	server_tls_enabled: Option<bool>,
+	failover_urls: Vec<String>,
+	job_timeout: Option<i64>,
+	last_job_time: i64,
+	job_refresh_requested: bool,
 	stream: Option<Stream>,
 	rx: mpsc::Receiver<types::ClientMessage>,
 	pub tx: mpsc::Sender<types::ClientMessage>,
 	miner_tx: mpsc::Sender<types::MinerMessage>,
 	last_request_id: u32,
 	stats: Arc<RwLock<stats::Stats>>,
+	events: EventBus,
+	/// connection state last published on the event bus
+	connected: bool,
+	/// share difficulty of the current job
+	current_difficulty: u64,
+	pending_submits: VecDeque<PendingSubmit>,
+	history: Option<History>,
 }
 
 fn invlalid_error_response() -> types::RpcError {
@@ -192,10 +246,15 @@ impl Controller {
 		server_login: Option<String>,
 		server_password: Option<String>,
 		server_tls_enabled: Option<bool>,
+		failover_urls: Option<Vec<String>>,
+		job_timeout: Option<u64>,
 		miner_tx: mpsc::Sender<types::MinerMessage>,
 		stats: Arc<RwLock<stats::Stats>>,
+		events: EventBus,
 	) -> Result<Controller, Error> {
 		let (tx, rx) = mpsc::channel::<types::ClientMessage>();
+		let mut failover_urls = failover_urls.unwrap_or(vec![]);
+		failover_urls.insert(0, server_url.to_string());
 		Ok(Controller {
 			_id: 0,
 			algorithm,
@@ -203,15 +262,29 @@ impl Controller {
 			server_login: server_login,
 			server_password: server_password,
 			server_tls_enabled: server_tls_enabled,
+			failover_urls: failover_urls,
+			job_timeout: job_timeout.map(|t| t as i64),
+			last_job_time: 0,
+			job_refresh_requested: false,
 			stream: None,
 			tx: tx,
 			rx: rx,
 			miner_tx: miner_tx,
 			last_request_id: 0,
 			stats: stats,
+			events: events,
+			connected: false,
+			current_difficulty: 0,
+			pending_submits: VecDeque::new(),
+			history: None,
 		})
 	}
 
+	/// Records every answered share in the given history
+	pub fn set_history(&mut self, history: History) {
+		self.history = Some(history);
+	}
+
 	pub fn try_connect(&mut self) -> Result<(), Error> {
 		self.stream = Some(Stream::new());
 		self.stream
@@ -354,6 +427,8 @@ impl Controller {
 	}
 
 	fn send_message_submit(&mut self, height: u64, solution: Solution) -> Result<(), Error> {
+		// unique ids so the answer can be matched with the share
+		self.last_request_id += 1;
 		let params_in = types::SubmitParams {
 			height: height,
 			job_id: solution.get_id(),
@@ -375,9 +450,65 @@ impl Controller {
 				params_in.height, params_in.nonce
 			);
 		}
+		let device = {
+			let stats = self.stats.read()?;
+			match stats.mining_stats.device_stats.get(solution.get_instance()) {
+				Some(s) => format!(
+					"{} {} ({})",
+					s.get_plugin_name(),
+					s.device_id,
+					s.get_device_name()
+				),
+				None => format!("solver {}", solution.get_instance()),
+			}
+		};
+		if self.pending_submits.len() >= MAX_PENDING_SUBMITS {
+			self.pending_submits.pop_front();
+		}
+		self.pending_submits.push_back(PendingSubmit {
+			id: req.id.clone(),
+			sent: Instant::now(),
+			job_id: params_in.job_id,
+			height: params_in.height,
+			difficulty: self.current_difficulty,
+			device: device,
+		});
 		self.send_message(&req_str)
 	}
 
+	/// Books the answer to a submitted share, towards the effective rate if
+	/// it was accepted and into the history if that's enabled
+	fn record_share(&mut self, id: &str, result: ShareResult) {
+		// answers should come back in order, fall back to the oldest share
+		// if the server didn't echo our id
+		let pending = match self.pending_submits.iter().position(|p| p.id == id) {
+			Some(i) => self.pending_submits.remove(i),
+			None => self.pending_submits.pop_front(),
+		};
+		let pending = match pending {
+			Some(p) => p,
+			None => return,
+		};
+		let now = time::get_time().sec;
+		if result == ShareResult::Accepted || result == ShareResult::BlockFound {
+			let mut stats = self.stats.write().unwrap();
+			stats
+				.mining_stats
+				.add_accepted_share(self.algorithm, now, pending.difficulty);
+		}
+		if let Some(ref mut history) = self.history {
+			history.append(&ShareRecord {
+				timestamp: now,
+				job_id: pending.job_id,
+				height: pending.height,
+				difficulty: pending.difficulty,
+				result: result,
+				latency_ms: pending.sent.elapsed().as_millis() as u64,
+				device: pending.device,
+			});
+		}
+	}
+
 	fn send_miner_job(&mut self, job: types::JobTemplate) -> Result<(), Error> {
 		let miner_message = types::MinerMessage::ReceivedSeed(job.epochs);
 		self.miner_tx.send(miner_message)?;
@@ -394,6 +525,7 @@ impl Controller {
 
 			diff
 		};
+		self.current_difficulty = difficulty;
 		let algo_needed = match job.algorithm.as_str() {
 			"cuckoo" => "Cuckatoo".to_string(),
 			"randomx" => "RandomX".to_string(),
@@ -414,6 +546,73 @@ impl Controller {
 		self.miner_tx.send(miner_message).map_err(|e| e.into())
 	}
 
+	/// Resets the job watchdog, the server is still sending us work
+	fn job_received(&mut self) -> Result<(), Error> {
+		self.last_job_time = time::get_time().sec;
+		self.job_refresh_requested = false;
+		let mut stats = self.stats.write()?;
+		stats.client_stats.last_job_time = self.last_job_time;
+		Ok(())
+	}
+
+	/// Checks how long the server has been quiet. Past the job timeout a
+	/// fresh template is requested, and if that doesn't help either the
+	/// connection is dropped so the next (failover) server gets a try
+	fn check_job_age(&mut self) {
+		let timeout = match self.job_timeout {
+			Some(t) if t > 0 => t,
+			_ => return,
+		};
+		let job_age = time::get_time().sec - self.last_job_time;
+		if job_age > 2 * timeout {
+			warn!(
+				LOGGER,
+				"No new job from {} for {} seconds, reconnecting", self.server_url, job_age
+			);
+			self.stream = None;
+			self.connection_changed(false, "Server stopped sending jobs");
+			self.next_server();
+		} else if job_age > timeout && !self.job_refresh_requested {
+			warn!(
+				LOGGER,
+				"No new job from {} for {} seconds, requesting a new one",
+				self.server_url,
+				job_age
+			);
+			let _ = self.send_message_get_job_template();
+			self.job_refresh_requested = true;
+		}
+	}
+
+	/// Publishes a change of the connection state, repeats are ignored
+	fn connection_changed(&mut self, connected: bool, status: &str) {
+		if self.connected == connected {
+			return;
+		}
+		self.connected = connected;
+		self.events.publish(Event::ConnectionState {
+			server: self.server_url.clone(),
+			connected: connected,
+			status: status.to_owned(),
+		});
+	}
+
+	/// Moves on to the next configured server, if there is more than one
+	fn next_server(&mut self) {
+		if self.failover_urls.len() < 2 {
+			return;
+		}
+		let current = self
+			.failover_urls
+			.iter()
+			.position(|u| *u == self.server_url)
+			.unwrap_or(0);
+		self.server_url = self.failover_urls[(current + 1) % self.failover_urls.len()].clone();
+		warn!(LOGGER, "Failing over to stratum server {}", self.server_url);
+		let mut stats = self.stats.write().unwrap();
+		stats.client_stats.server_url = self.server_url.clone();
+	}
+
 	fn send_miner_seed(&mut self, job: types::EpochTemplate) -> Result<(), Error> {
 		let miner_message = types::MinerMessage::ReceivedSeed(job.epochs);
 		self.miner_tx.send(miner_message).map_err(|e| e.into())
@@ -432,6 +631,7 @@ impl Controller {
 				Some(params) => {
 					let job = serde_json::from_value::<types::JobTemplate>(params)?;
 					info!(LOGGER, "Got a new job: {:?}", job);
+					self.job_received()?;
 
 					let algo_needed = match job.algorithm.as_str() {
 						"cuckoo" => "cuckoo".to_string(),
@@ -493,6 +693,7 @@ impl Controller {
 			"getjobtemplate" => {
 				if let Some(result) = res.result {
 					let job: types::JobTemplate = serde_json::from_value(result)?;
+					self.job_received()?;
 					let job_diff = self.parse_difficulty(&job.block_difficulty);
 					{
 						let mut stats = self.stats.write()?;
@@ -539,30 +740,53 @@ impl Controller {
 			"submit" => {
 				if let Some(result) = res.result {
 					info!(LOGGER, "Share Accepted!!");
-					let mut stats = self.stats.write()?;
-					stats.client_stats.last_message_received =
-						format!("Last Message Received: Share Accepted!!");
-					stats.mining_stats.solution_stats.num_shares_accepted += 1;
 					let result = serde_json::to_string(&result)?;
-					if result.contains("blockfound") {
-						info!(LOGGER, "Block Found!!");
+					let block_found = result.contains("blockfound");
+					{
+						let mut stats = self.stats.write()?;
 						stats.client_stats.last_message_received =
-							format!("Last Message Received: Block Found!!");
-						stats.mining_stats.solution_stats.num_blocks_found += 1;
+							format!("Last Message Received: Share Accepted!!");
+						stats.mining_stats.solution_stats.num_shares_accepted += 1;
+						if block_found {
+							info!(LOGGER, "Block Found!!");
+							stats.client_stats.last_message_received =
+								format!("Last Message Received: Block Found!!");
+							stats.mining_stats.solution_stats.num_blocks_found += 1;
+						}
 					}
+					self.events.publish(Event::ShareAccepted {
+						block_found: block_found,
+					});
+					let result = match block_found {
+						true => ShareResult::BlockFound,
+						false => ShareResult::Accepted,
+					};
+					self.record_share(&res.id, result);
 				} else {
 					let err = res.error.unwrap_or_else(|| invlalid_error_response());
-					let mut stats = self.stats.write()?;
-					stats.client_stats.last_message_received = format!(
-						"Last Message Received: Failed to submit a solution: {:?}",
-						err.message
-					);
-					if err.message.contains("too late") {
-						stats.mining_stats.solution_stats.num_staled += 1;
-					} else {
-						stats.mining_stats.solution_stats.num_rejected += 1;
+					let stale = err.message.contains("too late");
+					{
+						let mut stats = self.stats.write()?;
+						stats.client_stats.last_message_received = format!(
+							"Last Message Received: Failed to submit a solution: {:?}",
+							err.message
+						);
+						if stale {
+							stats.mining_stats.solution_stats.num_staled += 1;
+						} else {
+							stats.mining_stats.solution_stats.num_rejected += 1;
+						}
 					}
 					error!(LOGGER, "Failed to submit a solution: {:?}", err);
+					let result = match stale {
+						true => ShareResult::Stale,
+						false => ShareResult::Rejected,
+					};
+					self.record_share(&res.id, result);
+					self.events.publish(Event::ShareRejected {
+						reason: err.message,
+						stale: stale,
+					});
 				}
 				Ok(())
 			}
@@ -590,13 +814,20 @@ impl Controller {
 				} else {
 					// This is a fatal error
 					let err = res.error.unwrap_or_else(|| invlalid_error_response());
-					let mut stats = self.stats.write()?;
-					stats.client_stats.last_message_received =
-						format!("Last Message Received: Failed to log in: {:?}", err);
-					stats.client_stats.connection_status =
-						"Connection Status: Server requires login".to_string();
-					stats.client_stats.connected = false;
+					{
+						let mut stats = self.stats.write()?;
+						stats.client_stats.last_message_received =
+							format!("Last Message Received: Failed to log in: {:?}", err);
+						stats.client_stats.connection_status =
+							"Connection Status: Server requires login".to_string();
+						stats.client_stats.connected = false;
+					}
 					error!(LOGGER, "Failed to log in: {:?}", err);
+					self.events.publish(Event::LoginFailed {
+						server: self.server_url.clone(),
+						reason: err.message,
+					});
+					self.connection_changed(false, "Server requires login");
 				}
 				Ok(())
 			}
@@ -641,6 +872,8 @@ impl Controller {
 			if let None = self.stream {
 				if !was_disconnected {
 					let _ = self.send_miner_stop();
+					// answers to these are never coming
+					self.pending_submits.clear();
 				}
 				was_disconnected = true;
 				if time::get_time().sec > next_server_retry {
@@ -649,16 +882,21 @@ impl Controller {
 							self.server_url,
 							server_retry_interval);
 						warn!(LOGGER, "{}", status);
-						let mut stats = self.stats.write().unwrap();
-						stats.client_stats.connection_status = status;
-						stats.client_stats.connected = false;
+						self.connection_changed(false, &status);
+						{
+							let mut stats = self.stats.write().unwrap();
+							stats.client_stats.connection_status = status;
+							stats.client_stats.connected = false;
+						}
 						self.stream = None;
+						self.next_server();
 					} else {
 						let status = format!(
 							"Connection Status: Connected to Epic server at {}.",
 							self.server_url
 						);
 						warn!(LOGGER, "{}", status);
+						self.connection_changed(true, &status);
 						let mut stats = self.stats.write().unwrap();
 						stats.client_stats.connection_status = status;
 					}
@@ -673,6 +911,8 @@ impl Controller {
 				if was_disconnected {
 					let _ = self.send_login();
 					let _ = self.send_message_get_job_template();
+					self.last_job_time = time::get_time().sec;
+					self.job_refresh_requested = false;
 					was_disconnected = false;
 				}
 				// read messages from server
@@ -746,6 +986,7 @@ impl Controller {
 						Err(e) => {
 							error!(LOGGER, "Error reading message: {:?}", e);
 							self.stream = None;
+							self.connection_changed(false, &format!("Connection lost: {:?}", e));
 							continue;
 						}
 					}
@@ -757,6 +998,9 @@ impl Controller {
 					let _ = self.send_message_get_status();
 					next_status_request = time::get_time().sec + status_interval;
 				}
+
+				// Make sure the server hasn't gone quiet on us
+				self.check_job_age();
 			}
 
 			// Talk to the cuckoo miner plugin
@@ -766,6 +1010,18 @@ impl Controller {
 					types::ClientMessage::FoundSolution(height, solution) => {
 						self.send_message_submit(height, solution)
 					}
+					types::ClientMessage::SwitchServer(server_url) => {
+						warn!(LOGGER, "Switching to stratum server {}", server_url);
+						self.connection_changed(false, "Switching server");
+						self.server_url = server_url;
+						{
+							let mut stats = self.stats.write().unwrap();
+							stats.client_stats.server_url = self.server_url.clone();
+						}
+						// dropping the stream reconnects to the new server
+						self.stream = None;
+						Ok(())
+					}
 					types::ClientMessage::Shutdown => {
 						//TODO: Inform server?
 						debug!(LOGGER, "Shutting down client controller");
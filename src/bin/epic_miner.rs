@@ -29,8 +29,10 @@ extern crate epic_miner_core as core;
 extern crate epic_miner_plugin as plugin;
 extern crate epic_miner_util as util;
 
+extern crate base64;
 extern crate bufstream;
 extern crate native_tls;
+extern crate sha1_smol;
 extern crate time;
 #[macro_use]
 extern crate serde_derive;
@@ -43,23 +45,37 @@ extern crate slog;
 #[cfg(feature = "tui")]
 extern crate cursive;
 
+pub mod api;
+pub mod benchmark;
 pub mod client;
+pub mod errors;
+pub mod events;
+pub mod history;
+pub mod hooks;
+pub mod metrics;
 pub mod mining;
+pub mod pipeline;
 pub mod stats;
+pub mod tune;
 pub mod types;
+pub mod websocket;
 
 #[cfg(feature = "tui")]
 pub mod tui;
 
-use clap::App;
-use config::GlobalConfig;
+use clap::{App, ArgMatches};
+use config::{ConfigError, GlobalConfig};
+use std::fs::File;
+use std::io::{self, Write};
+use std::process;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, RwLock};
+use std::sync::{mpsc, Arc, RwLock};
 use std::thread;
 
-use core::config::MinerConfig;
+use core::config::{ApiConfig, HookConfig, MinerConfig};
 use core::errors::MinerError;
-use core::{Algorithm, Miner};
+use errors::Error;
+use core::{Capabilities, MinerEntry, ParamInfo, Registry};
 use util::{init_logger, LOGGER};
 
 // include build information
@@ -97,21 +113,15 @@ fn log_build_info() {
 
 #[cfg(feature = "tui")]
 mod with_tui {
-	use core::Algorithm;
-	use stats;
+	use pipeline;
+	use util::LOGGER;
 	use std::sync::atomic::{AtomicBool, Ordering};
-	use std::sync::{mpsc, Arc, RwLock};
+	use std::sync::Arc;
 	use std::thread;
 	use tui::ui;
 	use types;
 
-	pub fn start_tui(
-		s: Arc<RwLock<stats::Stats>>,
-		client_tx: mpsc::Sender<types::ClientMessage>,
-		miner_tx: mpsc::Sender<types::MinerMessage>,
-		stop: Arc<AtomicBool>,
-		algorithm: Algorithm,
-	) {
+	pub fn start_tui(pipelines: Vec<pipeline::Pipeline>, stop: Arc<AtomicBool>) {
 		// Run the UI controller.. here for now for simplicity to access
 		// everything it might need
 		println!("Starting Epic Miner in UI mode...");
@@ -119,81 +129,127 @@ mod with_tui {
 		let _ = thread::Builder::new()
 			.name("ui".to_string())
 			.spawn(move || {
-				let mut controller = ui::Controller::new(algorithm).unwrap_or_else(|e| {
-					panic!("Error loading UI controller: {}", e);
-				});
-				controller.run(s.clone());
+				match ui::Controller::new(pipelines.clone()) {
+					Ok(mut controller) => controller.run(),
+					Err(e) => error!(LOGGER, "Error loading UI controller: {}", e),
+				}
 				// Shut down everything else on tui exit
-				let _ = client_tx.send(types::ClientMessage::Shutdown);
-				let _ = miner_tx.send(types::MinerMessage::Shutdown);
+				for p in &pipelines {
+					let _ = p.client_tx.send(types::ClientMessage::Shutdown);
+					let _ = p.miner_tx.send(types::MinerMessage::Shutdown);
+				}
 				stop.store(true, Ordering::Relaxed);
 			});
 	}
 }
 
-fn start_miner<T>(mut miner: T, algorithm: Algorithm, mining_config: &MinerConfig)
-where
-	T: Miner + 'static,
-{
+/// Miners compiled into this build, and the features that bring in the
+/// ones that aren't
+fn miner_registry() -> Registry {
+	let mut registry = Registry::new();
+	randomx::register(&mut registry);
+	#[cfg(any(feature = "cpu", feature = "cuda"))]
+	cuckoo::register(&mut registry);
+	#[cfg(not(any(feature = "cpu", feature = "cuda")))]
+	registry.register_missing("cuckoo", core::Algorithm::Cuckoo, &["cpu", "cuda"]);
+	#[cfg(any(feature = "opencl", feature = "cuda"))]
+	progpow::register(&mut registry);
+	#[cfg(not(any(feature = "opencl", feature = "cuda")))]
+	registry.register_missing("progpow", core::Algorithm::ProgPow, &["opencl", "cuda"]);
+	registry
+}
+
+/// Builds and starts the miner and stratum client of a pipeline, returns
+/// its handle and the flags set once its controllers have stopped. The
+/// error its mining controller fails with, if it does, goes to `failed`
+fn start_pipeline(
+	name: &str,
+	entry: &MinerEntry,
+	mining_config: &MinerConfig,
+	events: events::EventBus,
+	failed: mpsc::Sender<Error>,
+) -> Result<(pipeline::Pipeline, Arc<AtomicBool>, Arc<AtomicBool>), Error> {
+	let algorithm = entry.algorithm;
+	let mut miner = (entry.build)(mining_config).map_err(|e| {
+		Error::Miner(
+			format!("Can't load the {} miner", metrics::algorithm_label(algorithm)),
+			e,
+		)
+	})?;
 	let stats = Arc::new(RwLock::new(stats::Stats::default()));
 
-	let mut mc =
-		mining::Controller::new(mining_config.clone(), stats.clone()).unwrap_or_else(|e| {
-			panic!("Error loading mining controller: {}", e);
-		});
+	let mut mc = mining::Controller::new(mining_config.clone(), stats.clone(), events.clone())
+		.map_err(|e| Error::General(format!("Can't load the mining controller: {}", e)))?;
 
-	let cc = client::Controller::new(
+	let mut cc = client::Controller::new(
 		algorithm.clone(),
 		&mining_config.stratum_server_addr,
 		mining_config.stratum_server_login.clone(),
 		mining_config.stratum_server_password.clone(),
 		mining_config.stratum_server_tls_enabled.clone(),
+		mining_config.stratum_server_failover_addrs.clone(),
+		mining_config.stratum_job_timeout.clone(),
 		mc.tx.clone(),
 		stats.clone(),
+		events,
 	)
-	.unwrap_or_else(|e| {
-		panic!("Error loading stratum client controller: {:?}", e);
-	});
-
-	let tui_stopped = Arc::new(AtomicBool::new(false));
-	let miner_stopped = Arc::new(AtomicBool::new(false));
-	let client_stopped = Arc::new(AtomicBool::new(false));
+	.map_err(|e| Error::Client("Can't load the stratum client".to_owned(), e))?;
 
-	if let Err(e) = miner.start_solvers() {
-		println!("Error starting plugins. Please check logs for further info.");
-		println!("Error details:");
-		println!("{:?}", e);
-		println!("Exiting");
-		return;
+	if let Some(ref path) = mining_config.share_history_file {
+		match history::load(path) {
+			Ok(records) => {
+				let mut stats = stats.write().unwrap();
+				history::restore_counters(&records, &mut stats.mining_stats.solution_stats);
+				history::restore_effective_rate(&records, algorithm, &mut stats.mining_stats);
+				info!(
+					LOGGER,
+					"Restored {} shares from the share history at {}",
+					records.len(),
+					path
+				);
+			}
+			Err(e) => error!(LOGGER, "{}", e),
+		}
+		match history::History::open(path) {
+			Ok(h) => cc.set_history(h),
+			Err(e) => error!(LOGGER, "{}", e),
+		}
 	}
 
-	if mining_config.run_tui {
-		#[cfg(feature = "tui")]
-		with_tui::start_tui(
-			stats.clone(),
-			cc.tx.clone(),
-			mc.tx.clone(),
-			tui_stopped.clone(),
-			mining_config.algorithm.clone().unwrap(),
-		);
+	let miner_stopped = Arc::new(AtomicBool::new(false));
+	let client_stopped = Arc::new(AtomicBool::new(false));
 
-		#[cfg(not(feature = "tui"))]
-		warn!(LOGGER, "Epic-miner was built with TUI support disabled!");
-	} else {
-		tui_stopped.store(true, Ordering::Relaxed);
-	}
+	mc.subscribe(miner.as_mut());
+	miner.start_solvers().map_err(|e| {
+		Error::Miner(
+			format!(
+				"Can't start the {} solvers, please check the logs",
+				metrics::algorithm_label(algorithm)
+			),
+			e,
+		)
+	})?;
+	info!(LOGGER, "Started pipeline {} mining {:?}", name, algorithm);
 
 	mc.set_client_tx(cc.tx.clone());
+	let handle = pipeline::Pipeline {
+		name: name.to_owned(),
+		algorithm: algorithm,
+		stats: stats,
+		client_tx: cc.tx.clone(),
+		miner_tx: mc.tx.clone(),
+	};
 
 	let miner_stopped_internal = miner_stopped.clone();
 	let _ = thread::Builder::new()
 		.name("mining_controller".to_string())
 		.spawn(move || {
 			if let Err(e) = mc.run(miner) {
-				error!(
-					LOGGER,
-					"Error loading plugins. Please check logs for further info: {:?}", e
-				);
+				error!(LOGGER, "The {} mining controller failed: {}", metrics::algorithm_label(algorithm), e);
+				let _ = failed.send(Error::Miner(
+					format!("The {} mining controller failed", metrics::algorithm_label(algorithm)),
+					e,
+				));
 				return;
 			}
 			miner_stopped_internal.store(true, Ordering::Relaxed);
@@ -207,50 +263,357 @@ where
 			client_stopped_internal.store(true, Ordering::Relaxed);
 		});
 
+	Ok((handle, miner_stopped, client_stopped))
+}
+
+fn start_miner(
+	pipelines: Vec<(String, &MinerEntry, MinerConfig)>,
+	mining_config: &MinerConfig,
+	api_config: &ApiConfig,
+	hook_config: &HookConfig,
+) -> Result<(), Error> {
+	let events = events::EventBus::new();
+	hooks::start(hook_config.clone(), &events);
+
+	// events are only tagged when there's more than one pipeline to tell apart
+	let tagged = pipelines.len() > 1;
+	let mut handles = vec![];
+	let (failed_tx, failed_rx) = mpsc::channel();
+	let mut stopped = vec![];
+	for (name, entry, config) in pipelines {
+		let events = if tagged {
+			events.for_pipeline(&name)
+		} else {
+			events.clone()
+		};
+		match start_pipeline(&name, entry, &config, events, failed_tx.clone()) {
+			Ok((handle, miner_stopped, client_stopped)) => {
+				handles.push(handle);
+				stopped.push(miner_stopped);
+				stopped.push(client_stopped);
+			}
+			Err(e) => {
+				if tagged {
+					error!(LOGGER, "Pipeline {} failed to start, shutting down the others", name);
+				}
+				for h in &handles {
+					let _ = h.client_tx.send(types::ClientMessage::Shutdown);
+					let _ = h.miner_tx.send(types::MinerMessage::Shutdown);
+				}
+				return Err(e);
+			}
+		}
+	}
+
+	let tui_stopped = Arc::new(AtomicBool::new(false));
+	if mining_config.run_tui {
+		#[cfg(feature = "tui")]
+		with_tui::start_tui(handles.clone(), tui_stopped.clone());
+
+		#[cfg(not(feature = "tui"))]
+		warn!(LOGGER, "Epic-miner was built with TUI support disabled!");
+	} else {
+		tui_stopped.store(true, Ordering::Relaxed);
+	}
+	stopped.push(tui_stopped);
+
+	if api_config.enabled {
+		let api_server = api::Server::new(api_config.clone(), handles.clone(), events.clone());
+		if let Err(e) = api_server.start() {
+			error!(LOGGER, "{}", e);
+		}
+	}
+
 	loop {
-		if miner_stopped.load(Ordering::Relaxed)
-			&& client_stopped.load(Ordering::Relaxed)
-			&& tui_stopped.load(Ordering::Relaxed)
-		{
+		if let Ok(e) = failed_rx.try_recv() {
+			for h in &handles {
+				let _ = h.client_tx.send(types::ClientMessage::Shutdown);
+				let _ = h.miner_tx.send(types::MinerMessage::Shutdown);
+			}
+			return Err(e);
+		}
+		if stopped.iter().all(|s| s.load(Ordering::Relaxed)) {
 			thread::sleep(std::time::Duration::from_millis(100));
 			break;
 		}
 		thread::sleep(std::time::Duration::from_millis(100));
 	}
+	Ok(())
 }
 
-fn main() {
-	let yml = load_yaml!("epic_miner.yml");
-	let args = App::from_yaml(yml).get_matches();
+/// `shares` subcommand, prints the share history counted by result or
+/// exports it as CSV. That of all pipelines merged, unless one is given
+fn show_shares(mining_config: &MinerConfig, args: &ArgMatches) -> Result<(), Error> {
+	let pipelines = mining_config.pipelines();
+	if let Some(name) = args.value_of("pipeline") {
+		if !pipelines.iter().any(|(n, _)| n == name) {
+			return Err(Error::Usage(format!("No pipeline named {}", name)));
+		}
+	}
+	let paths: Vec<String> = pipelines
+		.into_iter()
+		.filter(|(name, _)| args.value_of("pipeline").map_or(true, |n| n == name))
+		.filter_map(|(_, config)| config.share_history_file)
+		.collect();
+	if paths.is_empty() {
+		println!("No share_history_file is configured");
+		return Ok(());
+	}
+	let hours = args
+		.value_of("hours")
+		.unwrap_or("24")
+		.parse::<i64>()
+		.map_err(|e| Error::Usage(format!("Invalid number of hours: {}", e)))?;
+	let since = time::get_time().sec - hours * 3600;
+	let mut records = vec![];
+	for path in &paths {
+		records.extend(history::load(path).map_err(Error::General)?);
+	}
+	records.sort_by_key(|r| r.timestamp);
+
+	if let Some(csv_path) = args.value_of("csv") {
+		let result = match csv_path {
+			"-" => history::write_csv(&records, since, &mut io::stdout()),
+			p => File::create(p).and_then(|mut f| history::write_csv(&records, since, &mut f)),
+		};
+		return result.map_err(|e| Error::Io(format!("Can't write the CSV to {}", csv_path), e));
+	}
+
+	let counts = history::count_by_result(&records, since);
+	println!("Shares in the last {}h, from {}:", hours, paths.join(", "));
+	let mut total = 0;
+	for result in &[
+		history::ShareResult::Accepted,
+		history::ShareResult::BlockFound,
+		history::ShareResult::Rejected,
+		history::ShareResult::Stale,
+	] {
+		let count = counts.get(result).cloned().unwrap_or(0);
+		total += count;
+		println!("  {:<12} {}", result.as_str(), count);
+	}
+	println!("  {:<12} {}", "total", total);
+	Ok(())
+}
+
+/// `list-devices` subcommand, prints what each miner compiled into this
+/// build can do on this machine
+fn list_devices(registry: &Registry, mining_config: &MinerConfig) {
+	for entry in registry.entries() {
+		print_capabilities(entry.description, (entry.capabilities)(mining_config));
+	}
+	for e in registry.missing() {
+		println!("{}", e);
+		println!();
+	}
+}
+
+/// `benchmark` subcommand, runs every miner compiled in (or the one asked
+/// for) on synthetic jobs and prints the rates as a table and/or JSON
+fn run_benchmark(
+	registry: &Registry,
+	mining_config: &MinerConfig,
+	args: &ArgMatches,
+) -> Result<(), Error> {
+	let duration = args
+		.value_of("duration")
+		.unwrap_or("60")
+		.parse::<u64>()
+		.map_err(|e| Error::Usage(format!("Invalid duration: {}", e)))?;
+	let entries: Vec<_> = match args.value_of("miner") {
+		Some(name) => vec![registry
+			.get(name)
+			.map_err(|e| Error::Miner(format!("Can't benchmark {}", name), e))?],
+		None => registry.entries().iter().collect(),
+	};
+	let json_path = args.value_of("json");
+
+	let mut report = benchmark::Report {
+		version: built_info::PKG_VERSION.to_owned(),
+		commit: built_info::GIT_VERSION.map(|v| v.to_owned()),
+		target: built_info::TARGET.to_owned(),
+		features: built_info::FEATURES_STR.to_owned(),
+		timestamp: time::get_time().sec,
+		results: vec![],
+	};
+	for entry in entries {
+		// a missing plugin or device would only show up as a panic later on
+		if let Err(e) = (entry.capabilities)(mining_config) {
+			report.results.push(benchmark::MinerResult::failed(entry, e));
+			continue;
+		}
+		eprintln!(
+			"Benchmarking the {} for {}s after warming up...",
+			entry.description, duration
+		);
+		report.results.push(benchmark::run(
+			entry,
+			mining_config,
+			std::time::Duration::from_secs(duration),
+		));
+	}
+
+	if json_path != Some("-") {
+		let _ = benchmark::write_table(&report, &mut io::stdout());
+	}
+	if let Some(path) = json_path {
+		let json = serde_json::to_string_pretty(&report)
+			.map_err(|e| Error::General(format!("Can't serialize the report: {}", e)))?;
+		let result = match path {
+			"-" => writeln!(io::stdout(), "{}", json),
+			p => File::create(p).and_then(|mut f| writeln!(f, "{}", json)),
+		};
+		result.map_err(|e| Error::Io(format!("Can't write the JSON report to {}", path), e))?;
+	}
+	Ok(())
+}
+
+/// `tune` subcommand, benchmarks the miner over a range of parameters and
+/// prints the mining configuration with the best ones found
+fn run_tune(registry: &Registry, mining_config: &MinerConfig, args: &ArgMatches) -> Result<(), Error> {
+	let duration = args
+		.value_of("duration")
+		.unwrap_or("20")
+		.parse::<u64>()
+		.map_err(|e| Error::Usage(format!("Invalid duration: {}", e)))?;
+	let budget = match args.value_of("memory") {
+		Some(m) => Some(
+			m.parse::<u64>()
+				.map_err(|e| Error::Usage(format!("Invalid memory budget: {}", e)))?
+				<< 20,
+		),
+		None => benchmark::proc_kb("/proc/meminfo", "MemAvailable"),
+	};
+	let entry = match args.value_of("miner") {
+		Some(name) => registry.get(name),
+		None => match mining_config.algorithm {
+			Some(algorithm) => registry.for_algorithm(algorithm),
+			None => return Err(Error::InvalidConfig("No mining algorithm configured".to_owned())),
+		},
+	}
+	.map_err(|e| Error::Miner("Can't tune the miner".to_owned(), e))?;
+	let state_path = args.value_of("state").unwrap_or("epic-miner-tune.jsonl");
+	let mut state = tune::State::open(state_path).map_err(Error::General)?;
+	if !state.rounds().is_empty() {
+		eprintln!(
+			"Resuming with the {} rounds already done in {}",
+			state.rounds().len(),
+			state_path
+		);
+	}
+	eprintln!(
+		"Tuning the {}, {}s per round{}",
+		entry.description,
+		duration,
+		budget.map_or(String::new(), |b| format!(", within {} MiB", b >> 20))
+	);
+
+	let tuned = tune::run(
+		entry,
+		mining_config,
+		std::time::Duration::from_secs(duration),
+		budget,
+		&mut state,
+	)
+	.map_err(Error::General)?;
+	let tuned = config::mining_section(&tuned).map_err(Error::Config)?;
+	let result = match args.value_of("output") {
+		Some(p) => File::create(p).and_then(|mut f| write!(f, "{}", tuned)),
+		None => write!(io::stdout(), "{}", tuned),
+	};
+	result.map_err(|e| Error::Io("Can't write the tuned configuration".to_owned(), e))
+}
+
+fn print_capabilities(miner: &str, capabilities: Result<Capabilities, MinerError>) {
+	let params_str = |params: &[ParamInfo]| {
+		params
+			.iter()
+			.map(|p| format!("{}={}", p.name, p.default))
+			.collect::<Vec<_>>()
+			.join(", ")
+	};
+	println!("{}:", miner);
+	match capabilities {
+		Ok(c) => {
+			let algorithms: Vec<String> = c.algorithms.iter().map(|a| format!("{:?}", a)).collect();
+			println!("  algorithms: {}", algorithms.join(", "));
+			if !c.params.is_empty() {
+				println!("  parameters: {}", params_str(&c.params));
+			}
+			for d in c.devices {
+				let mut details = vec![format!("{:?}", d.backend)];
+				if let Some(memory) = d.memory {
+					details.push(format!("{:.1} GiB", memory as f64 / (1u64 << 30) as f64));
+				}
+				if let Some(units) = d.compute_units {
+					details.push(format!("{} compute units", units));
+				}
+				if !d.features.is_empty() {
+					details.push(d.features.join(" "));
+				}
+				println!("  device {}: {} ({})", d.id, d.name, details.join(", "));
+				if !d.params.is_empty() {
+					println!("    parameters: {}", params_str(&d.params));
+				}
+			}
+		}
+		Err(e) => println!("  unavailable: {}", e),
+	}
+	println!();
+}
+
+/// Runs what the command line asks for, the miner itself if no subcommand
+fn run(args: &ArgMatches) -> Result<(), Error> {
 	if let ("new_config", _) = args.subcommand() {
-		GlobalConfig::default()
+		return GlobalConfig::default()
 			.copy_config_file()
-			.unwrap_or_else(|e| panic!("Error: {}", e));
-		return ();
+			.map_err(Error::Config);
 	}
 	// Init configuration
 	let mut global_config =
-		GlobalConfig::new(args.value_of("config_file_path")).unwrap_or_else(|e| {
-			panic!("Error parsing config file: {}", e);
-		});
-	println!(
-		"Starting Epic-Miner from config file at: {}",
-		global_config
-			.config_file_path
-			.expect("Couldn't find the configuration file")
-			.to_str()
-			.expect("The path to the configuration file is not a valid utf-8 string")
-	);
+		GlobalConfig::new(args.value_of("config_file_path")).map_err(Error::Config)?;
+	let members = match global_config.members.take() {
+		Some(m) => m,
+		None => return Err(Error::Config(ConfigError::FileNotFoundError())),
+	};
+	let registry = miner_registry();
+	if let ("list-devices", _) = args.subcommand() {
+		list_devices(&registry, &members.mining);
+		return Ok(());
+	}
+	if let ("benchmark", Some(benchmark_args)) = args.subcommand() {
+		return run_benchmark(&registry, &members.mining, benchmark_args);
+	}
+	if let ("tune", Some(tune_args)) = args.subcommand() {
+		return run_tune(&registry, &members.mining, tune_args);
+	}
+	if let ("shares", Some(shares_args)) = args.subcommand() {
+		return show_shares(&members.mining, shares_args);
+	}
+	let mut pipelines = vec![];
+	for (name, config) in members.mining.pipelines() {
+		let entry = match config.algorithm {
+			Some(algorithm) => registry
+				.for_algorithm(algorithm)
+				.map_err(|e| Error::Miner(format!("Can't mine in pipeline {}", name), e))?,
+			None => return Err(Error::InvalidConfig("No mining algorithm configured".to_owned())),
+		};
+		pipelines.push((name, entry, config));
+	}
+	let configs: Vec<_> = pipelines
+		.iter()
+		.map(|(name, _, config)| (name.clone(), config.clone()))
+		.collect();
+	let available = benchmark::proc_kb("/proc/meminfo", "MemAvailable");
+	pipeline::check(&configs, tune::cpu_count(), available)?;
+	if let Some(ref path) = global_config.config_file_path {
+		println!("Starting Epic-Miner from config file at: {}", path.display());
+	}
 	// Init logging
-	let mut log_conf = global_config
-		.members
-		.as_mut()
-		.unwrap()
-		.logging
-		.clone()
-		.unwrap();
-
-	let mining_config = global_config.members.as_mut().unwrap().mining.clone();
+	let mut log_conf = members.logging.clone().unwrap_or_default();
+	let mining_config = members.mining.clone();
+	let api_config = members.api.clone().unwrap_or(ApiConfig::default());
+	let hook_config = members.hooks.clone().unwrap_or(HookConfig::default());
 
 	if cfg!(feature = "tui") && mining_config.run_tui {
 		log_conf.log_to_stdout = false;
@@ -265,30 +628,17 @@ fn main() {
 	// so we can exit pre-tui if something is obviously wrong
 	debug!(LOGGER, "Starting solvers");
 
-	match mining_config.algorithm.clone().unwrap() {
-		Algorithm::RandomX => start_miner(
-			randomx::RxMiner::new(&mining_config),
-			mining_config.algorithm.clone().unwrap(),
-			&mining_config,
-		), //randomx::RxMiner::new(&mining_config),
-		Algorithm::Cuckoo => start_miner(
-			cuckoo::CuckooMiner::new(&mining_config),
-			mining_config.algorithm.clone().unwrap(),
-			&mining_config,
-		),
-		#[cfg(feature = "opencl")]
-		Algorithm::ProgPow => start_miner(
-			progpow::PpMiner::new(&mining_config),
-			mining_config.algorithm.clone().unwrap(),
-			&mining_config,
-		),
-		#[cfg(feature = "cuda")]
-		Algorithm::ProgPow => start_miner(
-			progpow::PpMiner::new(&mining_config),
-			mining_config.algorithm.clone().unwrap(),
-			&mining_config,
-		),
-		#[allow(unreachable_patterns)]
-		_ => panic!("This algorithm is not supported in this build!"),
+	start_miner(pipelines, &mining_config, &api_config, &hook_config)
+}
+
+fn main() {
+	let yml = load_yaml!("epic_miner.yml");
+	let args = App::from_yaml(yml).get_matches();
+	if let Err(e) = run(&args) {
+		eprintln!("Error: {}", e.chain());
+		if e.is_retryable() {
+			eprintln!("This may be temporary, running again may succeed");
+		}
+		process::exit(e.exit_code());
 	}
 }
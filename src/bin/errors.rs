@@ -0,0 +1,166 @@
+// Copyright 2019 The Epic Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Errors the miner ends up on, wrapping the ones of the crates below it,
+//! and the process exit codes they map to.
+//!
+//! Exit codes follow sysexits(3) where one fits:
+//! * 1 - any other error
+//! * 64 - invalid command line arguments
+//! * 69 - a miner, plugin or device isn't available
+//! * 71 - the pipelines don't fit the CPUs, memory or GPUs
+//! * 74 - reading or writing a file failed
+//! * 75 - a temporary failure, running again may succeed
+//! * 78 - the configuration is invalid
+
+use std::{error, fmt, io};
+
+use crate::client;
+use config::ConfigError;
+use core::errors::MinerError;
+
+pub const EXIT_GENERAL: i32 = 1;
+pub const EXIT_USAGE: i32 = 64;
+pub const EXIT_UNAVAILABLE: i32 = 69;
+pub const EXIT_RESOURCES: i32 = 71;
+pub const EXIT_IO: i32 = 74;
+pub const EXIT_RETRYABLE: i32 = 75;
+pub const EXIT_CONFIG: i32 = 78;
+
+#[derive(Debug)]
+pub enum Error {
+	/// Invalid command line arguments
+	Usage(String),
+	/// The configuration file can't be loaded or written
+	Config(ConfigError),
+	/// The mining settings don't make sense
+	InvalidConfig(String),
+	/// A miner failed, with what it was doing at the time
+	Miner(String, MinerError),
+	/// The stratum client failed, with what it was doing at the time
+	Client(String, client::Error),
+	/// The pipelines don't fit the machine
+	Resources(String),
+	/// Reading or writing a file failed, with the path or what it was for
+	Io(String, io::Error),
+	General(String),
+}
+
+impl fmt::Display for Error {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			Error::Usage(ref message)
+			| Error::InvalidConfig(ref message)
+			| Error::Resources(ref message)
+			| Error::General(ref message) => write!(f, "{}", message),
+			Error::Config(_) => write!(f, "Configuration error"),
+			Error::Miner(ref context, _)
+			| Error::Client(ref context, _)
+			| Error::Io(ref context, _) => write!(f, "{}", context),
+		}
+	}
+}
+
+impl error::Error for Error {
+	fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+		match *self {
+			Error::Config(ref e) => Some(e),
+			Error::Miner(_, ref e) => Some(e),
+			Error::Client(_, ref e) => Some(e),
+			Error::Io(_, ref e) => Some(e),
+			Error::Usage(_)
+			| Error::InvalidConfig(_)
+			| Error::Resources(_)
+			| Error::General(_) => None,
+		}
+	}
+}
+
+impl Error {
+	/// Whether running again, unchanged, may succeed
+	pub fn is_retryable(&self) -> bool {
+		match *self {
+			Error::Miner(_, ref e) => e.is_retryable(),
+			Error::Client(_, ref e) => e.is_retryable(),
+			Error::Io(_, ref e) => match e.kind() {
+				io::ErrorKind::Interrupted | io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => true,
+				_ => false,
+			},
+			_ => false,
+		}
+	}
+
+	/// Code the process exits with on this error
+	pub fn exit_code(&self) -> i32 {
+		if self.is_retryable() {
+			return EXIT_RETRYABLE;
+		}
+		match *self {
+			Error::Usage(_) => EXIT_USAGE,
+			Error::Config(_) | Error::InvalidConfig(_) => EXIT_CONFIG,
+			Error::Miner(_, _) => EXIT_UNAVAILABLE,
+			Error::Resources(_) => EXIT_RESOURCES,
+			Error::Io(_, _) => EXIT_IO,
+			Error::Client(_, _) | Error::General(_) => EXIT_GENERAL,
+		}
+	}
+
+	/// The error and everything that led to it, as a single line
+	pub fn chain(&self) -> String {
+		let mut message = format!("{}", self);
+		let mut source = error::Error::source(self);
+		while let Some(e) = source {
+			message.push_str(&format!(": {}", e));
+			source = e.source();
+		}
+		message
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn exit_codes() {
+		let missing = Error::Miner(
+			"Can't start the miner".to_owned(),
+			MinerError::PluginNotFoundError("No plugin cuckatoo_cpu".to_owned()),
+		);
+		assert_eq!(missing.exit_code(), EXIT_UNAVAILABLE);
+		assert_eq!(missing.chain(), "Can't start the miner: No plugin cuckatoo_cpu");
+		let stats = Error::Miner(
+			"Can't start the miner".to_owned(),
+			MinerError::StatsError("busy".to_owned()),
+		);
+		assert!(stats.is_retryable());
+		assert_eq!(stats.exit_code(), EXIT_RETRYABLE);
+		let io = Error::Io(
+			"Can't write x.csv".to_owned(),
+			io::Error::new(io::ErrorKind::PermissionDenied, "denied"),
+		);
+		assert_eq!(io.exit_code(), EXIT_IO);
+		let plugin = Error::Miner(
+			"Can't load the cuckoo miner".to_owned(),
+			MinerError::from(io::Error::new(io::ErrorKind::NotFound, "No such file or directory")),
+		);
+		assert_eq!(plugin.exit_code(), EXIT_UNAVAILABLE);
+		assert_eq!(
+			plugin.chain(),
+			"Can't load the cuckoo miner: Error loading plugin: No such file or directory"
+		);
+		assert_eq!(Error::Config(ConfigError::FileNotFoundError()).exit_code(), EXIT_CONFIG);
+		assert_eq!(Error::Usage("Invalid duration".to_owned()).exit_code(), EXIT_USAGE);
+	}
+}
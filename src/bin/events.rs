@@ -0,0 +1,199 @@
+// Copyright 2019 The Epic Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Internal event bus. The mining and client controllers publish what
+//! happens into it, subscribers (such as the API's WebSocket stream) get
+//! every event as a JSON message. When several pipelines run, events carry
+//! the name of the one they come from in a `pipeline` field.
+//!
+//! Publishing never blocks: every subscriber has a bounded queue and a
+//! subscriber that lets it fill up is dropped, so a slow consumer can't
+//! hold up mining. The miner's own subscribers, such as hooks, must see
+//! every event for as long as it runs, their queues are unbounded.
+
+use std::sync::mpsc::{self, TrySendError};
+use std::sync::{Arc, Mutex};
+
+use serde_json;
+use time;
+use util::LOGGER;
+
+/// How many events a subscriber may fall behind before it's dropped
+const SUBSCRIBER_QUEUE_SIZE: usize = 256;
+
+/// Events published by the miner, serialized with an `event` field
+/// holding the snake_case variant name
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+	/// A new job was received from the stratum server
+	NewJob {
+		height: u64,
+		job_id: u64,
+		difficulty: u64,
+	},
+	/// A solver found a share, it's being submitted
+	ShareFound { height: u64, job_id: u64, nonce: u64 },
+	/// The server accepted a submitted share
+	ShareAccepted { block_found: bool },
+	/// The server rejected a submitted share
+	ShareRejected { reason: String, stale: bool },
+	/// A device reported an error
+	DeviceError {
+		plugin: String,
+		device_id: u32,
+		device_name: String,
+		reason: String,
+	},
+	/// The connection to the stratum server went up or down
+	ConnectionState {
+		server: String,
+		connected: bool,
+		status: String,
+	},
+	/// The stratum server refused our login
+	LoginFailed { server: String, reason: String },
+	/// The dataset for a mining epoch finished loading
+	EpochLoaded { start_height: u64, end_height: u64 },
+}
+
+#[derive(Serialize)]
+struct EventMessage<'a> {
+	timestamp: i64,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pipeline: Option<&'a str>,
+	#[serde(flatten)]
+	event: &'a Event,
+}
+
+enum Subscriber {
+	/// dropped once it falls too far behind
+	Bounded(mpsc::SyncSender<String>),
+	/// one of the miner's own, never dropped for being slow
+	Unbounded(mpsc::Sender<String>),
+}
+
+impl Subscriber {
+	fn send(&self, message: String) -> Result<(), TrySendError<String>> {
+		match self {
+			Subscriber::Bounded(tx) => tx.try_send(message),
+			Subscriber::Unbounded(tx) => tx
+				.send(message)
+				.map_err(|e| TrySendError::Disconnected(e.0)),
+		}
+	}
+}
+
+/// Cheap to clone handle to the event bus
+#[derive(Clone)]
+pub struct EventBus {
+	subscribers: Arc<Mutex<Vec<Subscriber>>>,
+	/// pipeline the events published through this handle are tagged with
+	pipeline: Option<Arc<String>>,
+}
+
+impl EventBus {
+	pub fn new() -> EventBus {
+		EventBus {
+			subscribers: Arc::new(Mutex::new(vec![])),
+			pipeline: None,
+		}
+	}
+
+	/// Handle to the same bus that tags what's published through it with
+	/// a `pipeline` field
+	pub fn for_pipeline(&self, name: &str) -> EventBus {
+		EventBus {
+			subscribers: self.subscribers.clone(),
+			pipeline: Some(Arc::new(name.to_owned())),
+		}
+	}
+
+	/// Registers a new subscriber, the receiver gets every event published
+	/// from now on as a JSON string. It's disconnected if it falls too far
+	/// behind.
+	pub fn subscribe(&self) -> mpsc::Receiver<String> {
+		let (tx, rx) = mpsc::sync_channel(SUBSCRIBER_QUEUE_SIZE);
+		self.subscribers.lock().unwrap().push(Subscriber::Bounded(tx));
+		rx
+	}
+
+	/// Registers one of the miner's own subscribers, which gets every
+	/// event however far behind it falls, until it drops the receiver
+	pub fn subscribe_internal(&self) -> mpsc::Receiver<String> {
+		let (tx, rx) = mpsc::channel();
+		self.subscribers.lock().unwrap().push(Subscriber::Unbounded(tx));
+		rx
+	}
+
+	/// Sends the event to all subscribers without waiting on any of them
+	pub fn publish(&self, event: Event) {
+		let mut subscribers = self.subscribers.lock().unwrap();
+		if subscribers.is_empty() {
+			return;
+		}
+		let message = EventMessage {
+			timestamp: time::get_time().sec,
+			pipeline: self.pipeline.as_ref().map(|p| p.as_str()),
+			event: &event,
+		};
+		let message = match serde_json::to_string(&message) {
+			Ok(m) => m,
+			Err(e) => {
+				error!(LOGGER, "Can't serialize event {:?}: {}", event, e);
+				return;
+			}
+		};
+		subscribers.retain(|s| match s.send(message.clone()) {
+			Ok(_) => true,
+			Err(TrySendError::Full(_)) => {
+				warn!(LOGGER, "Event subscriber is too slow, dropping it");
+				false
+			}
+			Err(TrySendError::Disconnected(_)) => false,
+		});
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn slow_subscribers() {
+		let bus = EventBus::new();
+		let external = bus.subscribe();
+		let internal = bus.subscribe_internal();
+		let published = SUBSCRIBER_QUEUE_SIZE + 10;
+		for height in 0..published {
+			bus.for_pipeline("cpu").publish(Event::NewJob {
+				height: height as u64,
+				job_id: 1,
+				difficulty: 1,
+			});
+		}
+
+		// the external one was dropped once its queue was full
+		assert_eq!(external.try_iter().count(), SUBSCRIBER_QUEUE_SIZE);
+		assert_eq!(external.try_recv(), Err(mpsc::TryRecvError::Disconnected));
+		let events: Vec<String> = internal.try_iter().collect();
+		assert_eq!(events.len(), published);
+		assert!(events[published - 1].contains("\"pipeline\":\"cpu\""));
+		assert_eq!(bus.subscribers.lock().unwrap().len(), 1);
+
+		drop(internal);
+		bus.publish(Event::ShareAccepted { block_found: false });
+		assert!(bus.subscribers.lock().unwrap().is_empty());
+	}
+}
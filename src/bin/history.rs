@@ -0,0 +1,314 @@
+// Copyright 2019 The Epic Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Share history, every share the pool answers is appended to a local
+//! file as one JSON object per line. The file is only ever appended to, a
+//! line cut short by a crash is skipped when reading it back.
+//!
+//! The history is used to restore the solution counters at startup, and
+//! can be queried and exported with the `shares` subcommand.
+
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use serde_json;
+use time;
+
+use crate::stats::{MiningStats, SolutionStats, EFFECTIVE_RATE_WINDOWS};
+use core::Algorithm;
+use util::LOGGER;
+
+/// What the pool made of a submitted share
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShareResult {
+	Accepted,
+	/// accepted, and it also solved a block
+	BlockFound,
+	Rejected,
+	Stale,
+}
+
+impl ShareResult {
+	pub fn as_str(&self) -> &'static str {
+		match *self {
+			ShareResult::Accepted => "accepted",
+			ShareResult::BlockFound => "block_found",
+			ShareResult::Rejected => "rejected",
+			ShareResult::Stale => "stale",
+		}
+	}
+}
+
+/// A single answered share
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ShareRecord {
+	/// when the pool answered (seconds since epoch)
+	pub timestamp: i64,
+	pub job_id: u64,
+	pub height: u64,
+	/// share difficulty the job asked for
+	pub difficulty: u64,
+	pub result: ShareResult,
+	/// milliseconds between submitting the share and the answer
+	pub latency_ms: u64,
+	/// device that found the share, as plugin, device id and name
+	pub device: String,
+}
+
+/// Append-only share history file
+pub struct History {
+	path: PathBuf,
+	file: File,
+}
+
+impl History {
+	/// Opens the history file for appending, creating it if needed. A last
+	/// line cut short, say by a crash, is ended so records go on the next
+	pub fn open<P: AsRef<Path>>(path: P) -> Result<History, String> {
+		let path = path.as_ref().to_path_buf();
+		let mut file = OpenOptions::new()
+			.create(true)
+			.read(true)
+			.append(true)
+			.open(&path)
+			.map_err(|e| format!("Can't open share history {}: {}", path.display(), e))?;
+		let mut last = [b'\n'];
+		let ended = match file.seek(SeekFrom::End(-1)) {
+			Ok(_) => file.read_exact(&mut last).map(|_| last[0] == b'\n'),
+			// empty
+			Err(_) => Ok(true),
+		};
+		match ended {
+			Ok(true) => {}
+			Ok(false) => {
+				writeln!(file).map_err(|e| format!("Can't write to share history {}: {}", path.display(), e))?
+			}
+			Err(e) => return Err(format!("Can't read share history {}: {}", path.display(), e)),
+		}
+		Ok(History {
+			path: path,
+			file: file,
+		})
+	}
+
+	/// Appends a record, written out straight away
+	pub fn append(&mut self, record: &ShareRecord) {
+		let line = match serde_json::to_string(record) {
+			Ok(l) => l,
+			Err(e) => {
+				error!(LOGGER, "Can't serialize share record {:?}: {}", record, e);
+				return;
+			}
+		};
+		if let Err(e) = writeln!(self.file, "{}", line).and_then(|_| self.file.flush()) {
+			error!(
+				LOGGER,
+				"Can't write to share history {}: {}",
+				self.path.display(),
+				e
+			);
+		}
+	}
+}
+
+/// Reads all records from the history file, a missing file is an empty
+/// history
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Vec<ShareRecord>, String> {
+	let path = path.as_ref();
+	let file = match File::open(path) {
+		Ok(f) => f,
+		Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+		Err(e) => {
+			return Err(format!(
+				"Can't open share history {}: {}",
+				path.display(),
+				e
+			))
+		}
+	};
+	let mut records = vec![];
+	for (i, line) in BufReader::new(file).lines().enumerate() {
+		let line = line.map_err(|e| format!("Can't read share history {}: {}", path.display(), e))?;
+		if line.trim().is_empty() {
+			continue;
+		}
+		match serde_json::from_str::<ShareRecord>(&line) {
+			Ok(r) => records.push(r),
+			Err(e) => warn!(
+				LOGGER,
+				"Skipping bad line {} in share history {}: {}",
+				i + 1,
+				path.display(),
+				e
+			),
+		}
+	}
+	Ok(records)
+}
+
+/// Rebuilds the cumulative solution counters from the history. Solutions
+/// the pool never answered aren't in the history, so the found counter
+/// can end up lower than it was before the restart.
+pub fn restore_counters(records: &[ShareRecord], stats: &mut SolutionStats) {
+	for r in records {
+		stats.num_solutions_found += 1;
+		match r.result {
+			ShareResult::Accepted => stats.num_shares_accepted += 1,
+			ShareResult::BlockFound => {
+				stats.num_shares_accepted += 1;
+				stats.num_blocks_found += 1;
+			}
+			ShareResult::Rejected => stats.num_rejected += 1,
+			ShareResult::Stale => stats.num_staled += 1,
+		}
+	}
+}
+
+/// Feeds the accepted shares still inside the effective rate windows back
+/// into the stats. The history doesn't record the algorithm, all shares
+/// are taken to be for the one being mined.
+pub fn restore_effective_rate(records: &[ShareRecord], algorithm: Algorithm, stats: &mut MiningStats) {
+	let longest = EFFECTIVE_RATE_WINDOWS[EFFECTIVE_RATE_WINDOWS.len() - 1].1;
+	let since = time::get_time().sec - longest;
+	for r in records.iter().filter(|r| r.timestamp >= since) {
+		if r.result == ShareResult::Accepted || r.result == ShareResult::BlockFound {
+			stats.add_accepted_share(algorithm, r.timestamp, r.difficulty);
+		}
+	}
+}
+
+/// Number of shares per result answered at or after `since`
+pub fn count_by_result(records: &[ShareRecord], since: i64) -> BTreeMap<ShareResult, u64> {
+	let mut counts = BTreeMap::new();
+	for r in records.iter().filter(|r| r.timestamp >= since) {
+		*counts.entry(r.result).or_insert(0) += 1;
+	}
+	counts
+}
+
+fn csv_field(value: &str) -> String {
+	if value.contains(',') || value.contains('"') || value.contains('\n') {
+		format!("\"{}\"", value.replace('"', "\"\""))
+	} else {
+		value.to_owned()
+	}
+}
+
+/// Writes the records answered at or after `since` as CSV, with a header
+pub fn write_csv<W: Write>(records: &[ShareRecord], since: i64, out: &mut W) -> std::io::Result<()> {
+	writeln!(out, "timestamp,job_id,height,difficulty,result,latency_ms,device")?;
+	for r in records.iter().filter(|r| r.timestamp >= since) {
+		writeln!(
+			out,
+			"{},{},{},{},{},{},{}",
+			r.timestamp,
+			r.job_id,
+			r.height,
+			r.difficulty,
+			r.result.as_str(),
+			r.latency_ms,
+			csv_field(&r.device)
+		)?;
+	}
+	out.flush()
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use std::env;
+	use std::fs;
+
+	fn record(timestamp: i64, result: ShareResult, device: &str) -> ShareRecord {
+		ShareRecord {
+			timestamp: timestamp,
+			job_id: timestamp as u64,
+			height: 100,
+			difficulty: 8,
+			result: result,
+			latency_ms: 40,
+			device: device.to_owned(),
+		}
+	}
+
+	#[test]
+	fn reload() {
+		let path = env::temp_dir().join(format!("epic-miner-history-{}.jsonl", std::process::id()));
+		let _ = fs::remove_file(&path);
+		assert!(load(&path).unwrap().is_empty());
+
+		let mut history = History::open(&path).unwrap();
+		history.append(&record(10, ShareResult::Accepted, "randomx 0 CPU"));
+		history.append(&record(20, ShareResult::BlockFound, "randomx 0 CPU"));
+		history.append(&record(30, ShareResult::Rejected, "randomx 0 CPU"));
+		drop(history);
+
+		// a crash in the middle of a write
+		let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+		write!(file, "{{\"timestamp\":40,\"job_id\"").unwrap();
+		drop(file);
+		let records = load(&path).unwrap();
+		assert_eq!(records.len(), 3);
+
+		// records go after the cut short line
+		let mut history = History::open(&path).unwrap();
+		history.append(&record(50, ShareResult::Stale, "randomx 0 CPU"));
+		history.append(&record(60, ShareResult::Accepted, "randomx 0 CPU"));
+		drop(history);
+		let records = load(&path).unwrap();
+		fs::remove_file(&path).unwrap();
+		let timestamps: Vec<i64> = records.iter().map(|r| r.timestamp).collect();
+		assert_eq!(timestamps, vec![10, 20, 30, 50, 60]);
+
+		let mut stats = SolutionStats::default();
+		restore_counters(&records, &mut stats);
+		assert_eq!(stats.num_solutions_found, 5);
+		assert_eq!(stats.num_shares_accepted, 3);
+		assert_eq!(stats.num_blocks_found, 1);
+		assert_eq!(stats.num_rejected, 1);
+		assert_eq!(stats.num_staled, 1);
+
+		let counts = count_by_result(&records, 20);
+		assert_eq!(counts.get(&ShareResult::Accepted), Some(&1));
+		assert_eq!(counts.get(&ShareResult::BlockFound), Some(&1));
+		assert_eq!(counts.get(&ShareResult::Rejected), Some(&1));
+		assert_eq!(counts.get(&ShareResult::Stale), Some(&1));
+	}
+
+	#[test]
+	fn csv() {
+		let records = vec![
+			record(10, ShareResult::Accepted, "cuckoo 0 GeForce, \"Ti\""),
+			record(20, ShareResult::BlockFound, "randomx 0 CPU"),
+			record(30, ShareResult::Stale, "randomx 1 CPU, 2nd"),
+		];
+		let mut out = vec![];
+		write_csv(&records, 20, &mut out).unwrap();
+		assert_eq!(
+			String::from_utf8(out).unwrap(),
+			"timestamp,job_id,height,difficulty,result,latency_ms,device\n\
+			 20,20,100,8,block_found,40,randomx 0 CPU\n\
+			 30,30,100,8,stale,40,\"randomx 1 CPU, 2nd\"\n"
+		);
+
+		let mut out = vec![];
+		write_csv(&records[..1], 0, &mut out).unwrap();
+		assert!(String::from_utf8(out)
+			.unwrap()
+			.ends_with("\n10,10,100,8,accepted,40,\"cuckoo 0 GeForce, \"\"Ti\"\"\"\n"));
+	}
+}
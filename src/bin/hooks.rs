@@ -0,0 +1,355 @@
+// Copyright 2019 The Epic Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Event hooks, POST a JSON payload to a webhook and/or run a local
+//! command when something notable happens:
+//!
+//! * `block_found` - the pool reported a share solved a block
+//! * `device_error` - a device reported an error
+//! * `disconnected` - the connection to the stratum server went down
+//! * `login_failed` - the stratum server refused our login
+//!
+//! Hooks listen on the event bus from their own thread and every firing
+//! runs on a thread of its own, so a slow webhook or command never holds
+//! up mining. Each event fires at most once per `min_interval`, firings in
+//! between are dropped and their number is sent along with the next one
+//! as `suppressed`.
+
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::process::Command;
+use std::thread;
+use std::time::Duration;
+
+use native_tls::TlsConnector;
+use serde_json::{self, Value};
+use time;
+
+use crate::events::EventBus;
+use core::config::HookConfig;
+use util::LOGGER;
+
+/// How long a webhook may take before it's given up on
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Name of the hook event for a bus event, if it's one hooks care about
+fn hook_event(event: &Value) -> Option<&'static str> {
+	match event["event"].as_str() {
+		Some("share_accepted") if event["block_found"] == Value::Bool(true) => Some("block_found"),
+		Some("device_error") => Some("device_error"),
+		Some("connection_state") if event["connected"] == Value::Bool(false) => {
+			Some("disconnected")
+		}
+		Some("login_failed") => Some("login_failed"),
+		_ => None,
+	}
+}
+
+/// Lets each hook event fire at most once per interval, counting the
+/// firings dropped in between
+struct RateLimiter {
+	/// seconds
+	min_interval: i64,
+	/// last firing time and number of dropped firings per event
+	last_fired: HashMap<&'static str, (i64, u64)>,
+}
+
+impl RateLimiter {
+	fn new(min_interval: u64) -> RateLimiter {
+		RateLimiter {
+			min_interval: min_interval as i64,
+			last_fired: HashMap::new(),
+		}
+	}
+
+	/// Whether the event fires at that time, with the number of firings
+	/// dropped since the last one if it does
+	fn fire(&mut self, name: &'static str, now: i64) -> Option<u64> {
+		let entry = self.last_fired.entry(name).or_insert((0, 0));
+		if entry.0 > 0 && now - entry.0 < self.min_interval {
+			entry.1 += 1;
+			return None;
+		}
+		let suppressed = entry.1;
+		*entry = (now, 0);
+		Some(suppressed)
+	}
+}
+
+/// Starts listening for events, does nothing if neither a webhook nor a
+/// command is configured
+pub fn start(config: HookConfig, events: &EventBus) {
+	if config.webhook_url.is_none() && config.command.is_none() {
+		return;
+	}
+	let rx = events.subscribe_internal();
+	let _ = thread::Builder::new()
+		.name("hooks".to_string())
+		.spawn(move || {
+			let mut limiter = RateLimiter::new(config.min_interval);
+			for message in rx.iter() {
+				let mut event: Value = match serde_json::from_str(&message) {
+					Ok(v) => v,
+					Err(_) => continue,
+				};
+				let name = match hook_event(&event) {
+					Some(n) if config.events.iter().any(|e| e == n) => n,
+					_ => continue,
+				};
+				let suppressed = match limiter.fire(name, time::get_time().sec) {
+					Some(suppressed) => suppressed,
+					None => {
+						debug!(LOGGER, "Hook {} rate limited", name);
+						continue;
+					}
+				};
+				event["hook"] = Value::from(name);
+				event["suppressed"] = Value::from(suppressed);
+				fire(&config, name, event);
+			}
+		});
+}
+
+fn fire(config: &HookConfig, name: &'static str, payload: Value) {
+	info!(LOGGER, "Firing {} hook", name);
+	if let Some(ref url) = config.webhook_url {
+		let url = url.clone();
+		let body = payload.to_string();
+		let _ = thread::Builder::new()
+			.name("hook_webhook".to_string())
+			.spawn(move || match post_json(&url, &body) {
+				Ok(status) if (200..300).contains(&status) => {}
+				Ok(status) => warn!(LOGGER, "Webhook {} answered with status {}", url, status),
+				Err(e) => warn!(LOGGER, "Webhook {} failed: {}", url, e),
+			});
+	}
+	if let Some(ref command) = config.command {
+		let mut cmd = Command::new(command);
+		if let Value::Object(ref fields) = payload {
+			for (key, value) in fields {
+				let value = match *value {
+					Value::String(ref s) => s.clone(),
+					ref v => v.to_string(),
+				};
+				cmd.env(format!("EPIC_MINER_{}", key.to_uppercase()), value);
+			}
+		}
+		// set last, the payload has an "event" field of its own
+		cmd.env("EPIC_MINER_EVENT", name)
+			.env("EPIC_MINER_PAYLOAD", payload.to_string());
+		let command = command.clone();
+		let _ = thread::Builder::new()
+			.name("hook_command".to_string())
+			.spawn(move || match cmd.spawn().and_then(|mut c| c.wait()) {
+				Ok(status) if status.success() => {}
+				Ok(status) => warn!(LOGGER, "Hook command {} exited with {}", command, status),
+				Err(e) => warn!(LOGGER, "Can't run hook command {}: {}", command, e),
+			});
+	}
+}
+
+/// The parts of a webhook URL needed to POST to it
+#[derive(Debug, PartialEq)]
+struct WebhookUrl<'a> {
+	tls: bool,
+	/// without the brackets of an IPv6 address
+	host: &'a str,
+	port: u16,
+	/// host and port as in the URL, for the Host header
+	authority: &'a str,
+	path: &'a str,
+}
+
+/// Splits an http or https URL, the port defaulting to the scheme's
+fn parse_url(url: &str) -> Result<WebhookUrl<'_>, String> {
+	let (tls, rest) = if let Some(rest) = url.strip_prefix("https://") {
+		(true, rest)
+	} else if let Some(rest) = url.strip_prefix("http://") {
+		(false, rest)
+	} else {
+		return Err("Only http and https URLs are supported".to_owned());
+	};
+	let (authority, path) = match rest.find('/') {
+		Some(i) => (&rest[..i], &rest[i..]),
+		None => (rest, "/"),
+	};
+	// an IPv6 address is in brackets, its colons aren't the port's
+	let (host, port) = match authority.strip_prefix('[') {
+		Some(bracketed) => {
+			let end = bracketed
+				.find(']')
+				.ok_or(format!("Invalid host in {}", url))?;
+			match &bracketed[end + 1..] {
+				"" => (&bracketed[..end], None),
+				port => match port.strip_prefix(':') {
+					Some(port) => (&bracketed[..end], Some(port)),
+					None => return Err(format!("Invalid host in {}", url)),
+				},
+			}
+		}
+		None => match authority.rfind(':') {
+			Some(i) => (&authority[..i], Some(&authority[i + 1..])),
+			None => (authority, None),
+		},
+	};
+	if host.is_empty() {
+		return Err(format!("Missing host in {}", url));
+	}
+	let port = match port {
+		Some(port) => port
+			.parse::<u16>()
+			.map_err(|_| format!("Invalid port in {}", url))?,
+		None if tls => 443,
+		None => 80,
+	};
+	Ok(WebhookUrl {
+		tls,
+		host,
+		port,
+		authority,
+		path,
+	})
+}
+
+/// POSTs a JSON body to an http or https URL, returning the status code
+fn post_json(url: &str, body: &str) -> Result<u16, String> {
+	let WebhookUrl {
+		tls,
+		host,
+		port,
+		authority,
+		path,
+	} = parse_url(url)?;
+	let addr = (host, port)
+		.to_socket_addrs()
+		.map_err(|e| format!("Can't resolve {}: {}", authority, e))?
+		.next()
+		.ok_or(format!("Can't resolve {}", authority))?;
+
+	let stream = TcpStream::connect_timeout(&addr, WEBHOOK_TIMEOUT).map_err(|e| e.to_string())?;
+	stream
+		.set_read_timeout(Some(WEBHOOK_TIMEOUT))
+		.and_then(|_| stream.set_write_timeout(Some(WEBHOOK_TIMEOUT)))
+		.map_err(|e| e.to_string())?;
+	let request = format!(
+		"POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+		path,
+		authority,
+		body.len(),
+		body
+	);
+	let mut response = vec![];
+	if tls {
+		let connector = TlsConnector::new().map_err(|e| e.to_string())?;
+		let mut stream = connector.connect(host, stream).map_err(|e| e.to_string())?;
+		stream
+			.write_all(request.as_bytes())
+			.map_err(|e| e.to_string())?;
+		let _ = stream.read_to_end(&mut response);
+	} else {
+		let mut stream = stream;
+		stream
+			.write_all(request.as_bytes())
+			.map_err(|e| e.to_string())?;
+		let _ = stream.read_to_end(&mut response);
+	}
+
+	// status line is "HTTP/1.1 200 OK"
+	String::from_utf8_lossy(&response)
+		.split_whitespace()
+		.nth(1)
+		.and_then(|s| s.parse::<u16>().ok())
+		.ok_or("Invalid response".to_owned())
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use std::net::TcpListener;
+
+	#[test]
+	fn hook_events() {
+		let event = |json: &str| hook_event(&serde_json::from_str(json).unwrap());
+		assert_eq!(event(r#"{"event":"share_accepted","block_found":true}"#), Some("block_found"));
+		assert_eq!(event(r#"{"event":"share_accepted","block_found":false}"#), None);
+		assert_eq!(event(r#"{"event":"device_error","reason":"x"}"#), Some("device_error"));
+		assert_eq!(event(r#"{"event":"connection_state","connected":false}"#), Some("disconnected"));
+		assert_eq!(event(r#"{"event":"connection_state","connected":true}"#), None);
+		assert_eq!(event(r#"{"event":"login_failed","reason":"x"}"#), Some("login_failed"));
+		assert_eq!(event(r#"{"event":"new_job","height":1}"#), None);
+		assert_eq!(event(r#"{}"#), None);
+	}
+
+	#[test]
+	fn rate_limit() {
+		let mut limiter = RateLimiter::new(60);
+		assert_eq!(limiter.fire("block_found", 1000), Some(0));
+		assert_eq!(limiter.fire("block_found", 1010), None);
+		assert_eq!(limiter.fire("block_found", 1059), None);
+		// other events are limited on their own
+		assert_eq!(limiter.fire("device_error", 1059), Some(0));
+		// the dropped ones are counted with the next firing
+		assert_eq!(limiter.fire("block_found", 1060), Some(2));
+		assert_eq!(limiter.fire("block_found", 1200), Some(0));
+
+		let mut unlimited = RateLimiter::new(0);
+		assert_eq!(unlimited.fire("block_found", 1000), Some(0));
+		assert_eq!(unlimited.fire("block_found", 1000), Some(0));
+	}
+
+	#[test]
+	fn urls() {
+		let url = |tls, host, port, authority, path| WebhookUrl {
+			tls,
+			host,
+			port,
+			authority,
+			path,
+		};
+		assert_eq!(
+			parse_url("http://example.com/hook?x=1"),
+			Ok(url(false, "example.com", 80, "example.com", "/hook?x=1"))
+		);
+		assert_eq!(
+			parse_url("https://example.com:8443"),
+			Ok(url(true, "example.com", 8443, "example.com:8443", "/"))
+		);
+		assert_eq!(parse_url("http://[::1]:8080/hook"), Ok(url(false, "::1", 8080, "[::1]:8080", "/hook")));
+		assert_eq!(parse_url("https://[fe80::1]/"), Ok(url(true, "fe80::1", 443, "[fe80::1]", "/")));
+		assert!(parse_url("ftp://example.com/").is_err());
+		assert!(parse_url("http://example.com:http/").is_err());
+		assert!(parse_url("http://[::1/").is_err());
+		assert!(parse_url("http://[::1]x/").is_err());
+		assert!(parse_url("http:///hook").is_err());
+	}
+
+	#[test]
+	fn webhook() {
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let port = listener.local_addr().unwrap().port();
+		let server = thread::spawn(move || {
+			let (mut stream, _) = listener.accept().unwrap();
+			let mut request = vec![0; 4096];
+			let len = stream.read(&mut request).unwrap();
+			stream.write_all(b"HTTP/1.1 204 No Content\r\n\r\n").unwrap();
+			String::from_utf8_lossy(&request[..len]).into_owned()
+		});
+		let url = format!("http://127.0.0.1:{}/hook", port);
+		assert_eq!(post_json(&url, "{}"), Ok(204));
+		let request = server.join().unwrap();
+		assert!(request.starts_with("POST /hook HTTP/1.1\r\n"));
+		assert!(request.contains(&format!("Host: 127.0.0.1:{}\r\n", port)));
+	}
+}
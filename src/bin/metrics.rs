@@ -0,0 +1,461 @@
+// Copyright 2019 The Epic Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Renders the miner stats in the Prometheus text exposition format,
+//! served by the API at `GET /metrics`.
+//!
+//! Metric names and labels are part of the public interface, dashboards
+//! and alerts are built on top of them, so don't rename them. New metrics
+//! can be added freely.
+//!
+//! Solutions (counters):
+//! * `epic_miner_solutions_found_total`
+//! * `epic_miner_shares_accepted_total`
+//! * `epic_miner_shares_rejected_total`
+//! * `epic_miner_shares_stale_total`
+//! * `epic_miner_blocks_found_total`
+//!
+//! Mining (gauges, label `algorithm`):
+//! * `epic_miner_combined_rate` - graphs per second for cuckoo, hashes per
+//!   second otherwise, averaged like the TUI does
+//! * `epic_miner_block_height`
+//! * `epic_miner_target_difficulty`
+//!
+//! Effective rate (gauge, labels `algorithm` and `window`, one of `5m`,
+//! `1h` and `24h`):
+//! * `epic_miner_effective_rate` - rate credited by accepted shares, same
+//!   unit as the combined rate
+//!
+//! Devices (labels `solver`, `plugin`, `device_id`, `device_name`,
+//! `algorithm`, where `solver` is the solver instance index, needed since
+//! several instances can run on the same device):
+//! * `epic_miner_device_rate` - gauge, same unit as the combined rate
+//! * `epic_miner_device_iterations_total` - counter
+//! * `epic_miner_device_errored` - gauge, 1 when the device has errored
+//! * `epic_miner_device_enabled` - gauge, 0 when the solver instance was
+//!   paused or removed on its own
+//! * `epic_miner_device_restarts_total` - counter, times the watchdog
+//!   brought the solver instance back up
+//! * `epic_miner_device_memory_bytes` - gauge, memory the solver instance
+//!   holds, 0 when unknown
+//!
+//! Stratum client (label `server`):
+//! * `epic_miner_stratum_connected` - gauge, 1 when connected
+//! * `epic_miner_last_job_timestamp_seconds` - gauge, 0 before the first job
+//!
+//! When several pipelines run, every sample gets a `pipeline` label with
+//! the name of the one it's about.
+
+use std::fmt::Write;
+
+use time;
+
+use crate::stats::{self, EFFECTIVE_RATE_WINDOWS};
+use core::{Algorithm, SolverState, Stats as CrStats};
+
+/// Label value used for the `algorithm` label
+pub fn algorithm_label(algorithm: Algorithm) -> &'static str {
+	match algorithm {
+		Algorithm::Cuckoo => "cuckoo",
+		Algorithm::RandomX => "randomx",
+		Algorithm::ProgPow => "progpow",
+	}
+}
+
+/// Escapes a label value as required by the exposition format
+fn escape(value: &str) -> String {
+	value
+		.replace('\\', "\\\\")
+		.replace('"', "\\\"")
+		.replace('\n', "\\n")
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+	let _ = writeln!(out, "# HELP {} {}", name, help);
+	let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn device_rate(algorithm: Algorithm, s: &CrStats) -> f64 {
+	if s.state != SolverState::Enabled {
+		return 0.0;
+	}
+	match algorithm {
+		Algorithm::Cuckoo => {
+			let secs = s.last_solution_time as f64 / 1000000000.0;
+			let gps = 1.0 / secs;
+			if gps.is_finite() {
+				gps
+			} else {
+				0.0
+			}
+		}
+		_ => s.hashes_per_sec as f64,
+	}
+}
+
+/// Label set in braces, the pipeline's first if there is one
+fn labels(pipeline: Option<&str>, rest: &str) -> String {
+	match (pipeline, rest.is_empty()) {
+		(None, true) => String::new(),
+		(None, false) => format!("{{{}}}", rest),
+		(Some(p), true) => format!("{{pipeline=\"{}\"}}", escape(p)),
+		(Some(p), false) => format!("{{pipeline=\"{}\",{}}}", escape(p), rest),
+	}
+}
+
+/// Renders all metrics for the given stats snapshots, taken of each
+/// pipeline as its name, algorithm and stats
+pub fn render(pipelines: &[(String, Algorithm, stats::Stats)]) -> String {
+	let mut out = String::new();
+	// a single pipeline renders as it always did, without the label
+	let pipeline = |name: &str| {
+		if pipelines.len() > 1 {
+			Some(name.to_owned())
+		} else {
+			None
+		}
+	};
+
+	let counters: [(&str, &str, fn(&stats::Stats) -> u32); 5] = [
+		(
+			"epic_miner_solutions_found_total",
+			"Solutions found by the solvers",
+			|s| s.mining_stats.solution_stats.num_solutions_found,
+		),
+		(
+			"epic_miner_shares_accepted_total",
+			"Shares accepted by the pool",
+			|s| s.mining_stats.solution_stats.num_shares_accepted,
+		),
+		(
+			"epic_miner_shares_rejected_total",
+			"Shares rejected by the pool",
+			|s| s.mining_stats.solution_stats.num_rejected,
+		),
+		(
+			"epic_miner_shares_stale_total",
+			"Shares reported stale by the pool",
+			|s| s.mining_stats.solution_stats.num_staled,
+		),
+		(
+			"epic_miner_blocks_found_total",
+			"Blocks found",
+			|s| s.mining_stats.solution_stats.num_blocks_found,
+		),
+	];
+	for (name, help, value) in counters.iter() {
+		header(&mut out, name, "counter", help);
+		for (p, _, stats) in pipelines {
+			let _ = writeln!(
+				out,
+				"{}{} {}",
+				name,
+				labels(pipeline(p).as_deref(), ""),
+				value(stats)
+			);
+		}
+	}
+
+	let gauges: [(&str, &str, fn(&stats::Stats) -> String); 3] = [
+		(
+			"epic_miner_combined_rate",
+			"Combined rate of all devices, graphs per second for cuckoo, hashes per second otherwise",
+			|s| s.mining_stats.combined_gps().to_string(),
+		),
+		(
+			"epic_miner_block_height",
+			"Height of the block being mined",
+			|s| s.mining_stats.block_height.to_string(),
+		),
+		(
+			"epic_miner_target_difficulty",
+			"Share difficulty currently requested by the pool",
+			|s| s.mining_stats.target_difficulty.to_string(),
+		),
+	];
+	for (name, help, value) in gauges.iter() {
+		header(&mut out, name, "gauge", help);
+		for (p, algorithm, stats) in pipelines {
+			let _ = writeln!(
+				out,
+				"{}{} {}",
+				name,
+				labels(
+					pipeline(p).as_deref(),
+					&format!("algorithm=\"{}\"", algorithm_label(*algorithm))
+				),
+				value(stats)
+			);
+		}
+	}
+
+	header(
+		&mut out,
+		"epic_miner_effective_rate",
+		"gauge",
+		"Rate credited by the difficulty of accepted shares, same unit as epic_miner_combined_rate",
+	);
+	let now = time::get_time().sec;
+	for (p, _, stats) in pipelines {
+		for a in stats.mining_stats.accepted_difficulty() {
+			for &(label, window) in EFFECTIVE_RATE_WINDOWS.iter() {
+				let _ = writeln!(
+					out,
+					"epic_miner_effective_rate{} {}",
+					labels(
+						pipeline(p).as_deref(),
+						&format!(
+							"algorithm=\"{}\",window=\"{}\"",
+							algorithm_label(a.algorithm),
+							label
+						)
+					),
+					a.rate(now, window)
+				);
+			}
+		}
+	}
+
+	let devices: Vec<(Algorithm, &CrStats, String)> = pipelines
+		.iter()
+		.flat_map(|(p, algorithm, stats)| {
+			let pipeline = pipeline(p);
+			stats
+				.mining_stats
+				.device_stats
+				.iter()
+				.enumerate()
+				.map(move |(i, s)| {
+					let rest = format!(
+						"solver=\"{}\",plugin=\"{}\",device_id=\"{}\",device_name=\"{}\",algorithm=\"{}\"",
+						i,
+						escape(&s.get_plugin_name()),
+						s.device_id,
+						escape(&s.get_device_name()),
+						algorithm_label(*algorithm)
+					);
+					(*algorithm, s, labels(pipeline.as_deref(), &rest))
+				})
+		})
+		.collect();
+	let device_metrics: [(&str, &str, &str, fn(Algorithm, &CrStats) -> String); 6] = [
+		(
+			"epic_miner_device_rate",
+			"gauge",
+			"Rate of a single device, same unit as epic_miner_combined_rate",
+			|a, s| device_rate(a, s).to_string(),
+		),
+		(
+			"epic_miner_device_iterations_total",
+			"counter",
+			"Solver iterations run on a single device",
+			|_, s| s.iterations.to_string(),
+		),
+		(
+			"epic_miner_device_errored",
+			"gauge",
+			"1 if the device has errored, 0 otherwise",
+			|_, s| (s.has_errored as u8).to_string(),
+		),
+		(
+			"epic_miner_device_enabled",
+			"gauge",
+			"0 if the solver instance was paused or removed at runtime, 1 otherwise",
+			|_, s| ((s.state == SolverState::Enabled) as u8).to_string(),
+		),
+		(
+			"epic_miner_device_restarts_total",
+			"counter",
+			"Times the solver instance was restarted after erroring or stalling",
+			|_, s| s.restarts.to_string(),
+		),
+		(
+			"epic_miner_device_memory_bytes",
+			"gauge",
+			"Memory held by the solver instance, 0 if unknown",
+			|_, s| s.memory.to_string(),
+		),
+	];
+	for (name, kind, help, value) in device_metrics.iter() {
+		header(&mut out, name, kind, help);
+		for (algorithm, s, l) in &devices {
+			let _ = writeln!(out, "{}{} {}", name, l, value(*algorithm, s));
+		}
+	}
+
+	let client_metrics: [(&str, &str, fn(&stats::Stats) -> i64); 2] = [
+		(
+			"epic_miner_stratum_connected",
+			"1 if connected to the stratum server, 0 otherwise",
+			|s| s.client_stats.connected as i64,
+		),
+		(
+			"epic_miner_last_job_timestamp_seconds",
+			"Unix time the last job was received, 0 if none was received yet",
+			|s| s.client_stats.last_job_time,
+		),
+	];
+	for (name, help, value) in client_metrics.iter() {
+		header(&mut out, name, "gauge", help);
+		for (p, _, stats) in pipelines {
+			let _ = writeln!(
+				out,
+				"{}{} {}",
+				name,
+				labels(
+					pipeline(p).as_deref(),
+					&format!("server=\"{}\"", escape(&stats.client_stats.server_url))
+				),
+				value(stats)
+			);
+		}
+	}
+
+	out
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	/// Stats of a cuckoo pipeline with one device mining and another
+	/// paused after it errored
+	fn snapshot() -> stats::Stats {
+		let mut stats = stats::Stats::default();
+		stats.client_stats.server_url = "pool.example.com:3416".to_owned();
+		stats.client_stats.connected = true;
+		stats.client_stats.last_job_time = 1_560_000_000;
+		let mining = &mut stats.mining_stats;
+		mining.block_height = 1234;
+		mining.target_difficulty = 4096;
+		mining.add_combined_gps(3.0);
+		mining.add_combined_gps(5.0);
+		mining.solution_stats = stats::SolutionStats {
+			num_solutions_found: 9,
+			num_shares_accepted: 6,
+			num_rejected: 1,
+			num_staled: 2,
+			num_blocks_found: 1,
+		};
+
+		let mut gpu = CrStats::default();
+		gpu.set_plugin_name("cuckaroo_cuda_29");
+		gpu.set_device_name("GeForce \"Ti\"");
+		gpu.last_solution_time = 250_000_000;
+		gpu.iterations = 42;
+		gpu.restarts = 2;
+		gpu.memory = 1 << 30;
+		let mut paused = CrStats::default();
+		paused.device_id = 1;
+		paused.set_plugin_name("cuckaroo_cuda_29");
+		paused.set_device_name("GeForce");
+		paused.last_solution_time = 500_000_000;
+		paused.has_errored = true;
+		paused.state = SolverState::Disabled;
+		mining.device_stats = vec![gpu, paused];
+		stats
+	}
+
+	#[test]
+	fn golden() {
+		let out = render(&[("default".to_owned(), Algorithm::Cuckoo, snapshot())]);
+		assert_eq!(out, GOLDEN);
+	}
+
+	#[test]
+	fn pipelines() {
+		let mut randomx = snapshot();
+		randomx.client_stats.connected = false;
+		let mut cpu = CrStats::default();
+		cpu.set_plugin_name("randomx");
+		cpu.set_device_name("CPU");
+		cpu.hashes_per_sec = 1500;
+		randomx.mining_stats.device_stats = vec![cpu];
+		let out = render(&[
+			("gpu".to_owned(), Algorithm::Cuckoo, snapshot()),
+			("cpu".to_owned(), Algorithm::RandomX, randomx),
+		]);
+		for line in &[
+			"epic_miner_shares_accepted_total{pipeline=\"gpu\"} 6",
+			"epic_miner_shares_accepted_total{pipeline=\"cpu\"} 6",
+			"epic_miner_block_height{pipeline=\"cpu\",algorithm=\"randomx\"} 1234",
+			"epic_miner_device_rate{pipeline=\"gpu\",solver=\"1\",plugin=\"cuckaroo_cuda_29\",device_id=\"1\",device_name=\"GeForce\",algorithm=\"cuckoo\"} 0",
+			"epic_miner_device_rate{pipeline=\"cpu\",solver=\"0\",plugin=\"randomx\",device_id=\"0\",device_name=\"CPU\",algorithm=\"randomx\"} 1500",
+			"epic_miner_stratum_connected{pipeline=\"cpu\",server=\"pool.example.com:3416\"} 0",
+		] {
+			assert!(out.lines().any(|l| l == *line), "{} missing", line);
+		}
+		// the headers once for all pipelines
+		assert_eq!(out.matches("# TYPE epic_miner_device_rate ").count(), 1);
+	}
+
+	/// What `snapshot` renders as, changes to it break dashboards
+	const GOLDEN: &str = r#"# HELP epic_miner_solutions_found_total Solutions found by the solvers
+# TYPE epic_miner_solutions_found_total counter
+epic_miner_solutions_found_total 9
+# HELP epic_miner_shares_accepted_total Shares accepted by the pool
+# TYPE epic_miner_shares_accepted_total counter
+epic_miner_shares_accepted_total 6
+# HELP epic_miner_shares_rejected_total Shares rejected by the pool
+# TYPE epic_miner_shares_rejected_total counter
+epic_miner_shares_rejected_total 1
+# HELP epic_miner_shares_stale_total Shares reported stale by the pool
+# TYPE epic_miner_shares_stale_total counter
+epic_miner_shares_stale_total 2
+# HELP epic_miner_blocks_found_total Blocks found
+# TYPE epic_miner_blocks_found_total counter
+epic_miner_blocks_found_total 1
+# HELP epic_miner_combined_rate Combined rate of all devices, graphs per second for cuckoo, hashes per second otherwise
+# TYPE epic_miner_combined_rate gauge
+epic_miner_combined_rate{algorithm="cuckoo"} 4
+# HELP epic_miner_block_height Height of the block being mined
+# TYPE epic_miner_block_height gauge
+epic_miner_block_height{algorithm="cuckoo"} 1234
+# HELP epic_miner_target_difficulty Share difficulty currently requested by the pool
+# TYPE epic_miner_target_difficulty gauge
+epic_miner_target_difficulty{algorithm="cuckoo"} 4096
+# HELP epic_miner_effective_rate Rate credited by the difficulty of accepted shares, same unit as epic_miner_combined_rate
+# TYPE epic_miner_effective_rate gauge
+# HELP epic_miner_device_rate Rate of a single device, same unit as epic_miner_combined_rate
+# TYPE epic_miner_device_rate gauge
+epic_miner_device_rate{solver="0",plugin="cuckaroo_cuda_29",device_id="0",device_name="GeForce \"Ti\"",algorithm="cuckoo"} 4
+epic_miner_device_rate{solver="1",plugin="cuckaroo_cuda_29",device_id="1",device_name="GeForce",algorithm="cuckoo"} 0
+# HELP epic_miner_device_iterations_total Solver iterations run on a single device
+# TYPE epic_miner_device_iterations_total counter
+epic_miner_device_iterations_total{solver="0",plugin="cuckaroo_cuda_29",device_id="0",device_name="GeForce \"Ti\"",algorithm="cuckoo"} 42
+epic_miner_device_iterations_total{solver="1",plugin="cuckaroo_cuda_29",device_id="1",device_name="GeForce",algorithm="cuckoo"} 0
+# HELP epic_miner_device_errored 1 if the device has errored, 0 otherwise
+# TYPE epic_miner_device_errored gauge
+epic_miner_device_errored{solver="0",plugin="cuckaroo_cuda_29",device_id="0",device_name="GeForce \"Ti\"",algorithm="cuckoo"} 0
+epic_miner_device_errored{solver="1",plugin="cuckaroo_cuda_29",device_id="1",device_name="GeForce",algorithm="cuckoo"} 1
+# HELP epic_miner_device_enabled 0 if the solver instance was paused or removed at runtime, 1 otherwise
+# TYPE epic_miner_device_enabled gauge
+epic_miner_device_enabled{solver="0",plugin="cuckaroo_cuda_29",device_id="0",device_name="GeForce \"Ti\"",algorithm="cuckoo"} 1
+epic_miner_device_enabled{solver="1",plugin="cuckaroo_cuda_29",device_id="1",device_name="GeForce",algorithm="cuckoo"} 0
+# HELP epic_miner_device_restarts_total Times the solver instance was restarted after erroring or stalling
+# TYPE epic_miner_device_restarts_total counter
+epic_miner_device_restarts_total{solver="0",plugin="cuckaroo_cuda_29",device_id="0",device_name="GeForce \"Ti\"",algorithm="cuckoo"} 2
+epic_miner_device_restarts_total{solver="1",plugin="cuckaroo_cuda_29",device_id="1",device_name="GeForce",algorithm="cuckoo"} 0
+# HELP epic_miner_device_memory_bytes Memory held by the solver instance, 0 if unknown
+# TYPE epic_miner_device_memory_bytes gauge
+epic_miner_device_memory_bytes{solver="0",plugin="cuckaroo_cuda_29",device_id="0",device_name="GeForce \"Ti\"",algorithm="cuckoo"} 1073741824
+epic_miner_device_memory_bytes{solver="1",plugin="cuckaroo_cuda_29",device_id="1",device_name="GeForce",algorithm="cuckoo"} 0
+# HELP epic_miner_stratum_connected 1 if connected to the stratum server, 0 otherwise
+# TYPE epic_miner_stratum_connected gauge
+epic_miner_stratum_connected{server="pool.example.com:3416"} 1
+# HELP epic_miner_last_job_timestamp_seconds Unix time the last job was received, 0 if none was received yet
+# TYPE epic_miner_last_job_timestamp_seconds gauge
+epic_miner_last_job_timestamp_seconds{server="pool.example.com:3416"} 1560000000
+"#;
+}
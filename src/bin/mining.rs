@@ -15,14 +15,16 @@
 /// Plugin controller, listens for messages sent from the stratum
 /// server, controls plugins and responds appropriately
 use std::sync::{mpsc, Arc, RwLock};
-use std::{self, thread};
+use std::thread;
+use std::time::Duration;
 use time;
 use util::LOGGER;
 use {config, crate::stats, crate::types};
+use crate::events::{Event, EventBus};
 
 use core::config::MinerConfig;
 use core::errors::MinerError;
-use core::{Algorithm, AlgorithmParams, Miner, Stats};
+use core::{Algorithm, AlgorithmParams, Header, Miner, MinerEvent, Solution, SolverState, Stats};
 
 pub struct Controller {
 	_config: MinerConfig,
@@ -32,14 +34,26 @@ pub struct Controller {
 	current_height: u64,
 	current_job_id: u64,
 	current_target_diff: u64,
+	current_pre_pow: String,
 	current_seed: [u8; 32],
+	paused: bool,
 	stats: Arc<RwLock<stats::Stats>>,
+	events: EventBus,
+	/// which solvers were errored at the last stats output
+	errored: Vec<bool>,
+	/// whether the miner pushes solutions and stats instead of being polled
+	event_driven: bool,
+	/// latest stats pushed by each solver
+	device_stats: Vec<Stats>,
+	/// solver instances paused or removed on their own
+	solver_states: Vec<SolverState>,
 }
 
 impl Controller {
 	pub fn new(
 		config: MinerConfig,
 		stats: Arc<RwLock<stats::Stats>>,
+		events: EventBus,
 	) -> Result<Controller, String> {
 		{
 			let mut stats_w = stats.write().unwrap();
@@ -54,8 +68,15 @@ impl Controller {
 			current_height: 0,
 			current_job_id: 0,
 			current_target_diff: 0,
+			current_pre_pow: String::new(),
 			current_seed: [0; 32],
+			paused: false,
 			stats: stats,
+			events: events,
+			errored: vec![],
+			event_driven: false,
+			device_stats: vec![],
+			solver_states: vec![],
 		})
 	}
 
@@ -63,73 +84,267 @@ impl Controller {
 		self.client_tx = Some(client_tx);
 	}
 
+	/// Subscribes to the solutions and stats the miner pushes, which then
+	/// arrive as `MinerMessage::MinerEvent`. Miners that don't support it
+	/// are polled instead. Has to be called before the solvers are started.
+	pub fn subscribe(&mut self, miner: &mut dyn Miner) {
+		let (event_tx, event_rx) = mpsc::channel::<MinerEvent>();
+		if !miner.subscribe(event_tx) {
+			debug!(LOGGER, "Miner doesn't push events, polling it for solutions");
+			return;
+		}
+		self.event_driven = true;
+		let tx = self.tx.clone();
+		let _ = thread::Builder::new()
+			.name("miner_events".to_string())
+			.spawn(move || {
+				for event in event_rx {
+					if tx.send(types::MinerMessage::MinerEvent(event)).is_err() {
+						break;
+					}
+				}
+			});
+	}
+
 	/// Run the mining controller, solvers in miner should already be going
-	pub fn run<T>(&mut self, mut miner: T) -> Result<(), MinerError>
-	where
-		T: Miner,
-	{
+	pub fn run(&mut self, mut miner: Box<dyn Miner>) -> Result<(), MinerError> {
 		// how often to output stats
 		let stat_output_interval = 2;
 		let mut next_stat_output = time::get_time().sec + stat_output_interval;
+		// solutions of a subscribed miner come in as messages, so there's
+		// no need to wake up more often than the stats are due
+		let wait = if self.event_driven {
+			Duration::from_secs(1)
+		} else {
+			Duration::from_millis(100)
+		};
 
 		loop {
-			while let Some(message) = self.rx.try_iter().next() {
-				debug!(LOGGER, "Miner received message: {:?}", message);
-				let result = match message {
-					types::MinerMessage::ReceivedJob(height, job_id, diff, pre_pow) => {
-						self.current_height = height;
-						self.current_job_id = job_id;
-						self.current_target_diff = diff;
-						miner.notify(
-							self.current_job_id as u32,
-							self.current_height,
-							&pre_pow,
-							"",
-							diff,
-						)
-					}
-					types::MinerMessage::ReceivedSeed(epochs) => {
-						for (start_height, end_height, seed) in epochs {
-							miner.add_epoch(start_height, end_height, seed);
+			let mut next_message = self.rx.recv_timeout(wait).ok();
+			while let Some(message) = next_message {
+				if !self.handle_message(miner.as_mut(), message) {
+					return Ok(());
+				}
+				next_message = self.rx.try_recv().ok();
+			}
+
+			if time::get_time().sec > next_stat_output {
+				let mut stats = if self.event_driven {
+					self.device_stats.clone()
+				} else {
+					match miner.get_stats() {
+						Ok(stats) => stats,
+						Err(e) => {
+							miner.stop_solvers();
+							miner.wait_for_solver_shutdown();
+							return Err(e);
 						}
-						Ok(())
 					}
-					types::MinerMessage::StopJob => {
-						debug!(LOGGER, "Stopping jobs");
+				};
+				self.apply_solver_states(&mut stats);
+				self.publish_device_errors(&stats);
+				self.output_job_stats(stats);
+				for (start_height, end_height) in miner.get_loaded_epochs() {
+					self.events.publish(Event::EpochLoaded {
+						start_height: start_height,
+						end_height: end_height,
+					});
+				}
+				next_stat_output = time::get_time().sec + stat_output_interval;
+			}
+
+			if !self.event_driven {
+				if let Some(solutions) = miner.get_solutions() {
+					self.found_solutions(solutions);
+				}
+			}
+		}
+	}
+
+	/// Handles a single message, returns false once the controller
+	/// should shut down
+	fn handle_message(&mut self, miner: &mut dyn Miner, message: types::MinerMessage) -> bool {
+		match message {
+			types::MinerMessage::MinerEvent(MinerEvent::Stats(_, _)) => {}
+			_ => debug!(LOGGER, "Miner received message: {:?}", message),
+		}
+		let result = match message {
+			types::MinerMessage::ReceivedJob(height, job_id, diff, pre_pow) => {
+				let header = match Header::from_hex(&pre_pow) {
+					Ok(header) => header,
+					Err(e) => {
+						error!(
+							LOGGER,
+							"Rejecting job {} at height {}, stopping the solvers: {}", job_id, height, e
+						);
+						// the pool replaced the job before, shares for it
+						// would only be rejected. The next job starts the
+						// solvers again
+						self.current_pre_pow = String::new();
+						self.stats.write().unwrap().mining_stats.job_header = String::new();
 						miner.pause_solvers();
-						Ok(())
-					}
-					types::MinerMessage::Shutdown => {
-						debug!(LOGGER, "Stopping jobs and Shutting down mining controller");
-						miner.stop_solvers();
-						miner.wait_for_solver_shutdown();
-						return Ok(());
+						return true;
 					}
 				};
-				if let Err(e) = result {
-					error!(LOGGER, "Mining Controller Error {:?}", e);
+				info!(LOGGER, "New job {}: {}", job_id, header);
+				// no job since the last one was rejected
+				let stopped = self.current_pre_pow.is_empty();
+				self.stats.write().unwrap().mining_stats.job_header = header.to_string();
+				self.current_height = height;
+				self.current_job_id = job_id;
+				self.current_target_diff = diff;
+				self.current_pre_pow = pre_pow;
+				self.events.publish(Event::NewJob {
+					height: height,
+					job_id: job_id,
+					difficulty: diff,
+				});
+				if self.paused {
+					// picked up again on resume
+					Ok(())
+				} else {
+					let result = miner.notify(
+						self.current_job_id as u32,
+						self.current_height,
+						&self.current_pre_pow,
+						"",
+						diff,
+					);
+					if stopped {
+						miner.resume_solvers();
+					}
+					result
+				}
+			}
+			types::MinerMessage::ReceivedSeed(epochs) => {
+				for (start_height, end_height, seed) in epochs {
+					miner.add_epoch(start_height, end_height, seed);
+				}
+				Ok(())
+			}
+			types::MinerMessage::StopJob => {
+				debug!(LOGGER, "Stopping jobs");
+				miner.pause_solvers();
+				Ok(())
+			}
+			types::MinerMessage::Pause => {
+				info!(LOGGER, "Pausing mining");
+				self.paused = true;
+				miner.pause_solvers();
+				Ok(())
+			}
+			types::MinerMessage::Resume => {
+				info!(LOGGER, "Resuming mining");
+				self.paused = false;
+				if self.current_pre_pow.is_empty() {
+					// nothing to work on yet, the next job starts the solvers
+					Ok(())
+				} else {
+					let result = miner.notify(
+						self.current_job_id as u32,
+						self.current_height,
+						&self.current_pre_pow,
+						"",
+						self.current_target_diff,
+					);
+					miner.resume_solvers();
+					result
+				}
+			}
+			types::MinerMessage::PauseSolver(instance) => {
+				info!(LOGGER, "Pausing solver instance {}", instance);
+				self.set_solver_state(instance, miner.pause_solver(instance), SolverState::Disabled)
+			}
+			types::MinerMessage::ResumeSolver(instance) => {
+				info!(LOGGER, "Resuming solver instance {}", instance);
+				self.set_solver_state(instance, miner.resume_solver(instance), SolverState::Enabled)
+			}
+			types::MinerMessage::RemoveSolver(instance) => {
+				info!(LOGGER, "Removing solver instance {}", instance);
+				self.set_solver_state(instance, miner.remove_solver(instance), SolverState::Removed)
+			}
+			types::MinerMessage::Shutdown => {
+				debug!(LOGGER, "Stopping jobs and Shutting down mining controller");
+				miner.stop_solvers();
+				miner.wait_for_solver_shutdown();
+				return false;
+			}
+			types::MinerMessage::MinerEvent(MinerEvent::Solution(solution)) => {
+				self.found_solutions(vec![solution]);
+				Ok(())
+			}
+			types::MinerMessage::MinerEvent(MinerEvent::Stats(instance, stats)) => {
+				if instance >= self.device_stats.len() {
+					self.device_stats.resize(instance + 1, Stats::default());
 				}
+				self.device_stats[instance] = *stats;
+				Ok(())
 			}
+		};
+		if let Err(e) = result {
+			error!(LOGGER, "Mining Controller Error {:?}", e);
+		}
+		true
+	}
 
-			if time::get_time().sec > next_stat_output {
-				self.output_job_stats(miner.get_stats().unwrap());
-				next_stat_output = time::get_time().sec + stat_output_interval;
+	/// Records the state of a solver instance once the miner agreed to it
+	fn set_solver_state(
+		&mut self,
+		instance: usize,
+		result: Result<(), MinerError>,
+		state: SolverState,
+	) -> Result<(), MinerError> {
+		result?;
+		if instance >= self.solver_states.len() {
+			self.solver_states.resize(instance + 1, SolverState::Enabled);
+		}
+		self.solver_states[instance] = state;
+		Ok(())
+	}
+
+	/// Marks the stats of paused and removed solvers, whatever they last
+	/// reported they aren't mining
+	fn apply_solver_states(&self, stats: &mut [Stats]) {
+		for (s, state) in stats.iter_mut().zip(self.solver_states.iter()) {
+			s.state = *state;
+			if *state != SolverState::Enabled {
+				s.hashes_per_sec = 0;
 			}
+		}
+	}
 
-			let solutions = miner.get_solutions();
-			if let Some(ss) = solutions {
-				let len = ss.len();
-				for i in ss {
-					let _ = self
-						.client_tx
-						.as_mut()
-						.unwrap()
-						.send(types::ClientMessage::FoundSolution(self.current_height, i));
-				}
-				let mut s_stats = self.stats.write().unwrap();
-				s_stats.mining_stats.solution_stats.num_solutions_found += len as u32;
+	/// Forwards solutions to the stratum client
+	fn found_solutions(&mut self, solutions: Vec<Solution>) {
+		let len = solutions.len();
+		for i in solutions {
+			self.events.publish(Event::ShareFound {
+				height: self.current_height,
+				job_id: i.get_id(),
+				nonce: i.get_nonce(),
+			});
+			let _ = self
+				.client_tx
+				.as_mut()
+				.unwrap()
+				.send(types::ClientMessage::FoundSolution(self.current_height, i));
+		}
+		let mut s_stats = self.stats.write().unwrap();
+		s_stats.mining_stats.solution_stats.num_solutions_found += len as u32;
+	}
+
+	/// Publishes an event for every solver that errored since the last call
+	fn publish_device_errors(&mut self, stats: &[Stats]) {
+		self.errored.resize(stats.len(), false);
+		for (s, errored) in stats.iter().zip(self.errored.iter_mut()) {
+			if s.has_errored && !*errored {
+				self.events.publish(Event::DeviceError {
+					plugin: s.get_plugin_name(),
+					device_id: s.device_id,
+					device_name: s.get_device_name(),
+					reason: s.get_error_reason(),
+				});
 			}
-			thread::sleep(std::time::Duration::from_millis(100));
+			*errored = s.has_errored;
 		}
 	}
 
@@ -139,9 +354,11 @@ impl Controller {
 		for s in stats.clone() {
 			let last_solution_time_secs = s.last_solution_time as f64 / 1000000000.0;
 			let last_hashes_per_sec = 1.0 / last_solution_time_secs;
-			let status = match s.has_errored {
-				false => "OK",
-				_ => "ERRORED",
+			let status = match (s.has_errored, s.state) {
+				(true, _) => "ERRORED",
+				(false, SolverState::Enabled) => "OK",
+				(false, SolverState::Disabled) => "DISABLED",
+				(false, SolverState::Removed) => "REMOVED",
 			};
 			if !s.has_errored {
 				debug!(
@@ -158,7 +375,7 @@ impl Controller {
 					last_hashes_per_sec,
 					s.iterations
 				);
-				if last_hashes_per_sec.is_finite() {
+				if last_hashes_per_sec.is_finite() && s.state == SolverState::Enabled {
 					sps_total += last_hashes_per_sec;
 				}
 			} else {
@@ -211,3 +428,80 @@ impl Controller {
 		}
 	}
 }
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use core::header::{self, MIN_PRE_POW_SIZE};
+
+	/// Records what it's told to do, has no stats to give
+	struct RecordingMiner(Arc<RwLock<Vec<String>>>);
+
+	impl Miner for RecordingMiner {
+		fn new(_configs: &MinerConfig) -> Result<Self, MinerError> {
+			Ok(RecordingMiner(Arc::new(RwLock::new(vec![]))))
+		}
+		fn notify(&mut self, job_id: u32, _: u64, _: &str, _: &str, _: u64) -> Result<(), MinerError> {
+			self.0.write().unwrap().push(format!("notify {}", job_id));
+			Ok(())
+		}
+		fn start_solvers(&mut self) -> Result<(), MinerError> {
+			Ok(())
+		}
+		fn get_stats(&self) -> Result<Vec<Stats>, MinerError> {
+			Err(MinerError::StatsError("No stats".to_owned()))
+		}
+		fn get_solutions(&self) -> Option<Vec<Solution>> {
+			None
+		}
+		fn stop_solvers(&self) {
+			self.0.write().unwrap().push("stop".to_owned());
+		}
+		fn pause_solvers(&self) {
+			self.0.write().unwrap().push("pause".to_owned());
+		}
+		fn resume_solvers(&self) {
+			self.0.write().unwrap().push("resume".to_owned());
+		}
+		fn wait_for_solver_shutdown(&self) {}
+	}
+
+	#[test]
+	fn malformed_pre_pow() {
+		let stats = Arc::new(RwLock::new(stats::Stats::default()));
+		let mut controller = Controller::new(MinerConfig::default(), stats.clone(), EventBus::new()).unwrap();
+		let calls = Arc::new(RwLock::new(vec![]));
+		let mut miner = RecordingMiner(calls.clone());
+		let pre_pow = header::to_hex(&vec![0; MIN_PRE_POW_SIZE]);
+		let job = |job_id: u64, pre_pow: &str| types::MinerMessage::ReceivedJob(10, job_id, 1, pre_pow.to_owned());
+
+		assert!(controller.handle_message(&mut miner, job(1, &pre_pow)));
+		calls.write().unwrap().clear();
+
+		// the solvers stop instead of mining the job replaced
+		assert!(controller.handle_message(&mut miner, job(2, "00")));
+		assert_eq!(*calls.read().unwrap(), vec!["pause"]);
+		assert_eq!(stats.read().unwrap().mining_stats.job_header, "");
+		// and stay stopped when resumed without a job
+		assert!(controller.handle_message(&mut miner, types::MinerMessage::Resume));
+		assert!(controller.handle_message(&mut miner, job(3, "zz")));
+		assert_eq!(*calls.read().unwrap(), vec!["pause", "pause"]);
+
+		// the next good job starts them again
+		assert!(controller.handle_message(&mut miner, job(4, &pre_pow)));
+		assert_eq!(*calls.read().unwrap(), vec!["pause", "pause", "notify 4", "resume"]);
+	}
+
+	#[test]
+	fn stats_error() {
+		let stats = Arc::new(RwLock::new(stats::Stats::default()));
+		let mut controller = Controller::new(MinerConfig::default(), stats, EventBus::new()).unwrap();
+		let calls = Arc::new(RwLock::new(vec![]));
+		// the solvers are stopped and the error returned
+		match controller.run(Box::new(RecordingMiner(calls.clone()))) {
+			Err(MinerError::StatsError(e)) => assert_eq!(e, "No stats"),
+			_ => panic!("expected a stats error"),
+		}
+		assert_eq!(*calls.read().unwrap(), vec!["stop"]);
+	}
+}
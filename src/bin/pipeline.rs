@@ -0,0 +1,279 @@
+// Copyright 2019 The Epic Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Mining pipelines, each an algorithm mined with a miner, devices and
+//! stratum client of its own. Several of them can run in one process, what
+//! each takes of the CPUs, memory and GPUs is checked before any is started
+//! so they don't starve each other.
+
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::{mpsc, Arc, RwLock};
+
+use crate::errors::Error;
+use crate::stats;
+use crate::types;
+use core::config::{MinerConfig, RxMode};
+use core::cpu;
+use core::Algorithm;
+use randomx::numa::{self, Layout};
+
+/// Host memory a RandomX dataset and its cache take, in MiB, twice that
+/// with `RxConfig::double_buffer`
+const RANDOMX_MEMORY: u64 = 2336;
+/// Host memory a RandomX cache takes, all light mode needs, in MiB
+const RANDOMX_LIGHT_MEMORY: u64 = 256;
+/// Host memory each RandomX thread takes on top, in MiB
+const RANDOMX_THREAD_MEMORY: u64 = 2;
+/// `GpuConfig::driver` values, as the plugins pick theirs by name
+const DRIVER_CUDA: u8 = 1;
+const DRIVER_OCL: u8 = 2;
+
+/// A running pipeline, what the API and TUI need to reach it
+#[derive(Clone)]
+pub struct Pipeline {
+	pub name: String,
+	pub algorithm: Algorithm,
+	pub stats: Arc<RwLock<stats::Stats>>,
+	pub client_tx: mpsc::Sender<types::ClientMessage>,
+	pub miner_tx: mpsc::Sender<types::MinerMessage>,
+}
+
+/// What a pipeline takes of the machine
+#[derive(Debug, PartialEq)]
+pub struct Reservation {
+	/// CPU threads, one to feed each GPU included
+	pub threads: u32,
+	/// host memory in MiB, as far as it's known up front
+	pub memory: u64,
+	/// GPUs used, as driver and device index
+	pub gpus: Vec<(u8, u32)>,
+}
+
+/// What the pipeline with that configuration takes of the machine
+pub fn reservation(config: &MinerConfig) -> Reservation {
+	let mut reservation = Reservation {
+		threads: 0,
+		memory: 0,
+		gpus: vec![],
+	};
+	match config.algorithm {
+		Some(Algorithm::RandomX) => {
+			let threads = config.randomx_config.threads as u32;
+			reservation.threads = threads;
+			let slots = if config.randomx_config.double_buffer { 2 } else { 1 };
+			// a dataset on each NUMA node
+			let allowed = cpu::allowed_cpus(&config.cpu_config).unwrap_or(None);
+			let nodes = Layout::new(&config.randomx_config, Path::new(numa::SYSFS_ROOT), allowed.as_deref())
+				.map_or(1, |l| l.nodes.len() as u64);
+			let datasets = slots * nodes;
+			// auto mode mines in full mode whenever the memory is there,
+			// so the other pipelines have to leave it that
+			let memory = match config.randomx_config.mode {
+				RxMode::Full | RxMode::Auto => RANDOMX_MEMORY,
+				RxMode::Light => RANDOMX_LIGHT_MEMORY,
+			};
+			reservation.memory = memory * datasets + RANDOMX_THREAD_MEMORY * threads as u64;
+		}
+		Some(Algorithm::Cuckoo) => {
+			for plugin in &config.miner_plugin_config {
+				let param = |name: &str| {
+					plugin
+						.parameters
+						.as_ref()
+						.and_then(|p| p.get(name))
+						.cloned()
+				};
+				let driver = if plugin.plugin_name.contains("cuda") {
+					Some(DRIVER_CUDA)
+				} else if plugin.plugin_name.contains("ocl") {
+					Some(DRIVER_OCL)
+				} else {
+					None
+				};
+				match driver {
+					Some(driver) => {
+						reservation.threads += 1;
+						reservation.gpus.push((driver, param("device").unwrap_or(0)));
+					}
+					None => reservation.threads += param("nthreads").unwrap_or(1),
+				}
+			}
+		}
+		Some(Algorithm::ProgPow) => {
+			for gpu in &config.gpu_config {
+				reservation.threads += 1;
+				reservation.gpus.push((gpu.driver, gpu.device));
+			}
+		}
+		None => {}
+	}
+	reservation
+}
+
+/// Checks the pipelines have distinct names, don't share a GPU and
+/// together fit the CPUs and the memory available (in bytes, if known).
+/// A single pipeline is left to take what it's configured to.
+pub fn check(
+	pipelines: &[(String, MinerConfig)],
+	cpus: u32,
+	memory: Option<u64>,
+) -> Result<(), Error> {
+	let mut names = HashSet::new();
+	for (name, _) in pipelines {
+		if name.is_empty() {
+			return Err(Error::InvalidConfig("A pipeline has an empty name".to_owned()));
+		}
+		if !names.insert(name) {
+			return Err(Error::InvalidConfig(format!(
+				"There's more than one pipeline named {}",
+				name
+			)));
+		}
+	}
+	if pipelines.len() < 2 {
+		return Ok(());
+	}
+
+	let reservations: Vec<Reservation> = pipelines.iter().map(|(_, c)| reservation(c)).collect();
+	let mut gpus = HashSet::new();
+	for ((name, _), r) in pipelines.iter().zip(reservations.iter()) {
+		for gpu in &r.gpus {
+			if !gpus.insert(gpu) {
+				return Err(Error::Resources(format!(
+					"Pipeline {} uses GPU {} (driver {}), which another pipeline uses already",
+					name, gpu.1, gpu.0
+				)));
+			}
+		}
+	}
+
+	let threads: u32 = reservations.iter().map(|r| r.threads).sum();
+	let total: u64 = reservations.iter().map(|r| r.memory).sum();
+	let memory = memory.map(|m| m >> 20);
+	if threads > cpus || memory.map_or(false, |m| total > m) {
+		let breakdown: Vec<String> = pipelines
+			.iter()
+			.zip(reservations.iter())
+			.map(|((name, _), r)| format!("{}: {} threads, {} MiB", name, r.threads, r.memory))
+			.collect();
+		return Err(Error::Resources(format!(
+			"The pipelines need {} threads and {} MiB, but there are {} CPUs and {} available ({})",
+			threads,
+			total,
+			cpus,
+			memory.map_or("unknown memory".to_owned(), |m| format!("{} MiB", m)),
+			breakdown.join(", ")
+		)));
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use core::config::{EpicMinerPluginConfig, GpuConfig, PipelineConfig};
+	use std::collections::HashMap;
+
+	fn pipeline(name: &str, algorithm: Algorithm) -> PipelineConfig {
+		PipelineConfig {
+			name: name.to_owned(),
+			algorithm: algorithm,
+			stratum_server_addr: None,
+			stratum_server_login: None,
+			stratum_server_password: None,
+			stratum_server_tls_enabled: None,
+			stratum_server_failover_addrs: None,
+			share_history_file: None,
+			randomx_config: None,
+			miner_plugin_config: None,
+			gpu_config: None,
+			cpu_config: None,
+		}
+	}
+
+	#[test]
+	fn reservations() {
+		let mut config = MinerConfig::default();
+		config.randomx_config.threads = 6;
+		config.randomx_config.double_buffer = false;
+		config.randomx_config.mode = RxMode::Full;
+		config.randomx_config.numa = false;
+		config.stratum_server_login = Some("main".to_owned());
+		config.gpu_config = vec![GpuConfig { device: 0, driver: 2 }];
+		let mut params = HashMap::new();
+		params.insert("nthreads".to_owned(), 2);
+		config.miner_plugin_config = vec![
+			EpicMinerPluginConfig {
+				plugin_name: "cuckaroo_cpu_compat_19".to_owned(),
+				parameters: Some(params),
+			},
+			EpicMinerPluginConfig {
+				plugin_name: "cuckaroo_cuda_29".to_owned(),
+				parameters: None,
+			},
+		];
+		assert_eq!(config.pipelines().len(), 1);
+
+		let mut progpow = pipeline("gpu", Algorithm::ProgPow);
+		progpow.stratum_server_login = Some("gpu".to_owned());
+		config.pipelines = vec![pipeline("cpu", Algorithm::RandomX), progpow];
+		let pipelines = config.pipelines();
+		assert_eq!(pipelines[0].1.stratum_server_login, Some("main".to_owned()));
+		assert_eq!(pipelines[1].1.stratum_server_login, Some("gpu".to_owned()));
+		assert!(pipelines.iter().all(|(_, c)| c.pipelines.is_empty()));
+		assert_eq!(
+			reservation(&pipelines[0].1),
+			Reservation {
+				threads: 6,
+				memory: 2348,
+				gpus: vec![],
+			}
+		);
+		let mut double_buffer = pipelines[0].1.clone();
+		double_buffer.randomx_config.double_buffer = true;
+		assert_eq!(reservation(&double_buffer).memory, 4684);
+		double_buffer.randomx_config.mode = RxMode::Auto;
+		assert_eq!(reservation(&double_buffer).memory, 4684);
+		double_buffer.randomx_config.mode = RxMode::Light;
+		assert_eq!(reservation(&double_buffer).memory, 524);
+		double_buffer.randomx_config.numa_nodes = vec!["0-2".to_owned(), "3-5".to_owned()];
+		assert_eq!(reservation(&double_buffer).memory, 1036);
+		assert_eq!(reservation(&pipelines[1].1).gpus, vec![(2, 0)]);
+		let mut cuckoo = pipelines[1].1.clone();
+		cuckoo.algorithm = Some(Algorithm::Cuckoo);
+		assert_eq!(
+			reservation(&cuckoo),
+			Reservation {
+				threads: 3,
+				memory: 0,
+				gpus: vec![(1, 0)],
+			}
+		);
+
+		assert!(check(&pipelines, 8, Some(4 << 30)).is_ok());
+		assert!(check(&pipelines, 6, None).is_err());
+		assert!(check(&pipelines, 8, Some(2 << 30)).is_err());
+		// a single pipeline is never held back
+		assert!(check(&pipelines[..1], 1, Some(0)).is_ok());
+
+		let mut shared = pipelines.clone();
+		shared[0].1.algorithm = Some(Algorithm::ProgPow);
+		shared[0].0 = "gpu2".to_owned();
+		assert!(check(&shared, 8, None).is_err());
+		shared[0].0 = "gpu".to_owned();
+		let err = check(&shared, 8, None).unwrap_err();
+		assert!(format!("{}", err).contains("more than one"));
+	}
+}
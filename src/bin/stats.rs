@@ -15,12 +15,118 @@
 //! Miner stats collection types, to be used by tests, logging or GUI/TUI
 //! to collect information about mining status
 
+use std::collections::VecDeque;
+
+use serde::ser::{SerializeMap, SerializeSeq, Serializer};
+use time;
+
+use core::difficulty::expected_work;
+use core::Algorithm;
 use core::Stats as CrStats;
 /// Struct to return relevant information about the mining process
 /// back to interested callers (such as the TUI)
 use plugin;
 
+/// Windows the effective rate is computed over, label and seconds
+pub const EFFECTIVE_RATE_WINDOWS: [(&str, i64); 3] =
+	[("5m", 5 * 60), ("1h", 60 * 60), ("24h", 24 * 60 * 60)];
+
+/// Difficulty of the shares accepted for one algorithm, summed per minute
+/// over the longest window. Difficulty is the expected number of hashes
+/// (or cuckoo proofs) it takes to find a share, so the sum over a window
+/// gives the rate the pool actually credits us for.
 #[derive(Clone)]
+pub struct AcceptedDifficulty {
+	pub algorithm: Algorithm,
+	/// when tracking started (seconds since epoch)
+	since: i64,
+	/// (minute, summed difficulty), oldest first
+	buckets: VecDeque<(i64, u64)>,
+}
+
+impl AcceptedDifficulty {
+	fn new(algorithm: Algorithm, since: i64) -> AcceptedDifficulty {
+		AcceptedDifficulty {
+			algorithm: algorithm,
+			since: since,
+			buckets: VecDeque::new(),
+		}
+	}
+
+	fn add(&mut self, timestamp: i64, difficulty: u64) {
+		let minute = timestamp / 60;
+		match self.buckets.back_mut() {
+			// shares come in roughly in order, a late one goes to the
+			// current minute
+			Some(b) if b.0 >= minute => b.1 += difficulty,
+			_ => self.buckets.push_back((minute, difficulty)),
+		}
+		let longest = EFFECTIVE_RATE_WINDOWS[EFFECTIVE_RATE_WINDOWS.len() - 1].1;
+		while let Some(&(m, _)) = self.buckets.front() {
+			if (m + 1) * 60 > timestamp - longest {
+				break;
+			}
+			self.buckets.pop_front();
+		}
+	}
+
+	/// Effective rate over the last `window` seconds, or since tracking
+	/// started if that's more recent. Graphs per second for cuckoo, hashes
+	/// per second otherwise.
+	pub fn rate(&self, now: i64, window: i64) -> f64 {
+		let start = now - window;
+		let difficulty: u64 = self
+			.buckets
+			.iter()
+			.filter(|b| b.0 * 60 >= start)
+			.map(|b| b.1)
+			.sum();
+		let elapsed = (now - self.since.max(start)).max(1) as f64;
+		expected_work(self.algorithm, difficulty) / elapsed
+	}
+}
+
+/// Serializes the accepted difficulty as the effective rates it gives, a
+/// list of `{"algorithm": .., "5m": .., "1h": .., "24h": ..}`
+fn serialize_effective_rates<S>(accepted: &Vec<AcceptedDifficulty>, serializer: S) -> Result<S::Ok, S::Error>
+where
+	S: Serializer,
+{
+	let now = time::get_time().sec;
+	let mut seq = serializer.serialize_seq(Some(accepted.len()))?;
+	for a in accepted {
+		let rates: Vec<(&str, f64)> = EFFECTIVE_RATE_WINDOWS
+			.iter()
+			.map(|&(label, window)| (label, a.rate(now, window)))
+			.collect();
+		seq.serialize_element(&EffectiveRates {
+			algorithm: a.algorithm,
+			rates: rates,
+		})?;
+	}
+	seq.end()
+}
+
+struct EffectiveRates<'a> {
+	algorithm: Algorithm,
+	rates: Vec<(&'a str, f64)>,
+}
+
+impl<'a> serde::Serialize for EffectiveRates<'a> {
+	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+	where
+		S: Serializer,
+	{
+		let mut map = serializer.serialize_map(Some(self.rates.len() + 1))?;
+		map.serialize_entry("algorithm", &self.algorithm)?;
+		for &(label, rate) in &self.rates {
+			map.serialize_entry(label, &rate)?;
+		}
+		map.end()
+	}
+}
+
+#[derive(Clone, Serialize)]
 pub struct SolutionStats {
 	/// total solutions found
 	pub num_solutions_found: u32,
@@ -46,7 +152,7 @@ impl Default for SolutionStats {
 	}
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize)]
 pub struct MiningStats {
 	/// combined graphs per second
 	combined_gps: Vec<f64>,
@@ -54,10 +160,21 @@ pub struct MiningStats {
 	pub block_height: u64,
 	/// current target for share difficulty we're working on
 	pub target_difficulty: u64,
+	/// fields of the header of the current job
+	pub job_header: String,
 	/// solution statistics
 	pub solution_stats: SolutionStats,
 	/// Individual device status from Cuckoo-Miner
 	pub device_stats: Vec<CrStats>,
+	/// difficulty of accepted shares, per algorithm
+	#[serde(
+		rename = "effective_rates",
+		serialize_with = "serialize_effective_rates"
+	)]
+	accepted_difficulty: Vec<AcceptedDifficulty>,
+	/// when mining started (seconds since epoch)
+	#[serde(skip)]
+	started: i64,
 }
 
 impl Default for MiningStats {
@@ -66,8 +183,11 @@ impl Default for MiningStats {
 			combined_gps: vec![],
 			block_height: 0,
 			target_difficulty: 0,
+			job_header: String::new(),
 			solution_stats: SolutionStats::default(),
 			device_stats: vec![],
+			accepted_difficulty: vec![],
+			started: time::get_time().sec,
 		}
 	}
 }
@@ -78,6 +198,30 @@ impl MiningStats {
 		self.combined_gps.truncate(50);
 	}
 
+	/// Counts an accepted share towards the effective rate
+	pub fn add_accepted_share(&mut self, algorithm: Algorithm, timestamp: i64, difficulty: u64) {
+		let i = match self
+			.accepted_difficulty
+			.iter()
+			.position(|a| a.algorithm == algorithm)
+		{
+			Some(i) => i,
+			None => {
+				// shares restored from the history can predate this run
+				let since = self.started.min(timestamp);
+				self.accepted_difficulty
+					.push(AcceptedDifficulty::new(algorithm, since));
+				self.accepted_difficulty.len() - 1
+			}
+		};
+		self.accepted_difficulty[i].add(timestamp, difficulty);
+	}
+
+	/// Accepted share difficulty for every algorithm mined so far
+	pub fn accepted_difficulty(&self) -> &[AcceptedDifficulty] {
+		&self.accepted_difficulty
+	}
+
 	pub fn combined_gps(&self) -> f64 {
 		if self.combined_gps.is_empty() {
 			0.0
@@ -88,7 +232,7 @@ impl MiningStats {
 	}
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize)]
 pub struct ClientStats {
 	/// Server we're connected to
 	pub server_url: String,
@@ -106,7 +250,8 @@ pub struct ClientStats {
 	pub algorithm_needed: String,
 	/// The current network difficulty
 	pub current_network_difficulty: String,
-	
+	/// When the last job was received from the server (seconds since epoch)
+	pub last_job_time: i64,
 }
 
 impl Default for ClientStats {
@@ -120,11 +265,12 @@ impl Default for ClientStats {
 			my_algorithm: "".to_string(),
 			algorithm_needed: "".to_string(),
 			current_network_difficulty: "".to_string(),
+			last_job_time: 0,
 		}
 	}
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize)]
 pub struct Stats {
 	/// Client/networking stats
 	pub client_stats: ClientStats,
@@ -140,3 +286,29 @@ impl Default for Stats {
 		}
 	}
 }
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn effective_rate_windows() {
+		let now = 1_000_000 * 60;
+		let mut a = AcceptedDifficulty::new(Algorithm::RandomX, now - 2 * 60 * 60);
+		// 600 difficulty an hour and a half ago, 300 just now
+		a.add(now - 90 * 60, 600);
+		a.add(now - 30, 300);
+		assert_eq!(a.rate(now, 5 * 60), 1.0);
+		assert_eq!(a.rate(now, 60 * 60), 300.0 / 3600.0);
+		// only two hours of tracking for the 24h window
+		assert_eq!(a.rate(now, 24 * 60 * 60), 900.0 / 7200.0);
+
+		// shares older than the longest window are dropped
+		a.add(now + 24 * 60 * 60, 0);
+		assert_eq!(a.buckets.len(), 1);
+
+		let mut c = AcceptedDifficulty::new(Algorithm::Cuckoo, now - 60 * 60);
+		c.add(now - 10, 3600);
+		assert_eq!(c.rate(now, 60 * 60), 42.0);
+	}
+}
@@ -26,8 +26,9 @@ use cursive::Cursive;
 use tui::constants::*;
 use tui::types::*;
 
-use core::{Algorithm, Stats};
+use core::{Algorithm, SolverState, Stats};
 use stats;
+use time;
 use tui::table::{TableView, TableViewItem};
 
 #[derive(Copy, Clone, PartialEq, Eq, Hash)]
@@ -65,9 +66,11 @@ impl TableViewItem<MiningDeviceColumn> for Stats {
 			MiningDeviceColumn::DeviceId => format!("{}", self.device_id).to_owned(),
 			MiningDeviceColumn::DeviceName => self.get_device_name(),
 			MiningDeviceColumn::EdgeBits => format!("{}", self.edge_bits).to_owned(),
-			MiningDeviceColumn::ErrorStatus => match self.has_errored {
-				false => String::from("OK"),
-				_ => String::from("Errored"),
+			MiningDeviceColumn::ErrorStatus => match (self.has_errored, self.state) {
+				(true, _) => String::from("Errored"),
+				(false, SolverState::Enabled) => String::from("OK"),
+				(false, SolverState::Disabled) => String::from("Disabled"),
+				(false, SolverState::Removed) => String::from("Removed"),
 			},
 			MiningDeviceColumn::LastGraphTime => {
 				String::from(format!("{}s", last_solution_time_secs))
@@ -108,7 +111,17 @@ impl TableViewItem<MiningDeviceColumn> for Stats {
 pub struct TUIMiningView;
 
 impl TUIMiningView {
-	pub fn create_algorithm(algorithm: Algorithm) -> Box<View> {
+	/// Solver instance of the selected row in the devices table
+	pub fn selected_instance(c: &mut Cursive) -> Option<usize> {
+		c.call_on_id(
+			TABLE_MINING_STATUS,
+			|t: &mut TableView<Stats, MiningDeviceColumn>| t.item(),
+		)
+		.and_then(|i| i)
+	}
+
+	/// Mining view for the algorithm, with the title on top if given
+	pub fn create_algorithm(algorithm: Algorithm, title: Option<String>) -> Box<View> {
 		let table_view = {
 			let table = TableView::<Stats, MiningDeviceColumn>::new()
 				.column(MiningDeviceColumn::Plugin, "Plugin", |c| {
@@ -139,8 +152,12 @@ impl TUIMiningView {
 			}
 		};
 
+		let mut status_view = LinearLayout::new(Orientation::Vertical);
+		if let Some(title) = title {
+			status_view.add_child(TextView::new(title));
+		}
 		let status_view =
-			LinearLayout::new(Orientation::Vertical)
+			status_view
 				.child(LinearLayout::new(Orientation::Horizontal).child(
 					TextView::new("Connection Status: Starting...").with_id("mining_server_status"),
 				))
@@ -160,6 +177,18 @@ impl TUIMiningView {
 					LinearLayout::new(Orientation::Horizontal)
 						.child(TextView::new("  ").with_id("network_info")),
 				)
+				.child(
+					LinearLayout::new(Orientation::Horizontal)
+						.child(TextView::new("  ").with_id("mining_rate")),
+				)
+				.child(
+					LinearLayout::new(Orientation::Horizontal)
+						.child(TextView::new("Current Job Age:  ").with_id("current_job_age")),
+				)
+				.child(
+					LinearLayout::new(Orientation::Horizontal)
+						.child(TextView::new("Current Job Header:  ").with_id("current_job_header")),
+				)
 				.child(
 					LinearLayout::new(Orientation::Horizontal)
 						.child(TextView::new("  ").with_id("mining_statistics")),
@@ -176,7 +205,7 @@ impl TUIMiningView {
 			.child(status_view)
 			.child(BoxView::with_full_screen(
 				Dialog::around(table_view.with_id(TABLE_MINING_STATUS).min_size((50, 20)))
-					.title("Mining Devices"),
+					.title("Mining Devices (p: pause, r: resume, x: remove the selected one)"),
 			))
 			.with_id("mining_device_view");
 
@@ -235,6 +264,18 @@ impl TUIStatusListener for TUIMiningView {
 					LinearLayout::new(Orientation::Horizontal)
 						.child(TextView::new("  ").with_id("network_info")),
 				)
+				.child(
+					LinearLayout::new(Orientation::Horizontal)
+						.child(TextView::new("  ").with_id("mining_rate")),
+				)
+				.child(
+					LinearLayout::new(Orientation::Horizontal)
+						.child(TextView::new("Current Job Age:  ").with_id("current_job_age")),
+				)
+				.child(
+					LinearLayout::new(Orientation::Horizontal)
+						.child(TextView::new("Current Job Header:  ").with_id("current_job_header")),
+				)
 				.child(
 					LinearLayout::new(Orientation::Horizontal)
 						.child(TextView::new("  ").with_id("mining_statistics")),
@@ -251,7 +292,7 @@ impl TUIStatusListener for TUIMiningView {
 			.child(status_view)
 			.child(BoxView::with_full_screen(
 				Dialog::around(table_view.with_id(TABLE_MINING_STATUS).min_size((50, 20)))
-					.title("Mining Devices"),
+					.title("Mining Devices (p: pause, r: resume, x: remove the selected one)"),
 			))
 			.with_id("mining_device_view");
 
@@ -311,12 +352,42 @@ impl TUIStatusListener for TUIMiningView {
 		c.call_on_id("network_info", |t: &mut TextView| {
 			t.set_content(basic_network_info);
 		});
+		c.call_on_id("current_job_header", |t: &mut TextView| {
+			t.set_content(format!("Current Job Header:  {}", mining_stats.job_header));
+		});
+		let now = time::get_time().sec;
+		let mut mining_rate = format!("Reported Rate: {:.2}", mining_stats.combined_gps());
+		for a in mining_stats.accepted_difficulty() {
+			let rates: Vec<String> = stats::EFFECTIVE_RATE_WINDOWS
+				.iter()
+				.map(|&(label, window)| format!("{} {:.2}", label, a.rate(now, window)))
+				.collect();
+			mining_rate.push_str(&format!(
+				" - Effective {:?}: {}",
+				a.algorithm,
+				rates.join(", ")
+			));
+		}
+		c.call_on_id("mining_rate", |t: &mut TextView| {
+			t.set_content(mining_rate);
+		});
 		c.call_on_id("current_algo_needed", |t: &mut TextView| {
 			t.set_content(format!("Network Needed Algorithm: {}", client_stats.algorithm_needed));
 		});
 		c.call_on_id("current_network_diff", |t: &mut TextView| {
 			t.set_content(format!("Current Network Difficulty: {}", client_stats.current_network_difficulty));
 		});
+		let job_age = if client_stats.last_job_time > 0 {
+			format!(
+				"Current Job Age: {}s",
+				time::get_time().sec - client_stats.last_job_time
+			)
+		} else {
+			"Current Job Age: No job received yet".to_string()
+		};
+		c.call_on_id("current_job_age", |t: &mut TextView| {
+			t.set_content(job_age);
+		});
 		c.call_on_id("last_message_sent", |t: &mut TextView| {
 			t.set_content(client_stats.last_message_sent.clone());
 		});
@@ -341,7 +412,12 @@ impl TUIStatusListener for TUIMiningView {
 		let _ = c.call_on_id(
 			TABLE_MINING_STATUS,
 			|t: &mut TableView<Stats, MiningDeviceColumn>| {
+				// keep the selection, the device controls act on it
+				let selected = t.item();
 				t.set_items(mining_stats.device_stats);
+				if let Some(i) = selected {
+					t.set_selected_item(i);
+				}
 			},
 		);
 	}
@@ -15,7 +15,8 @@
 //! Basic TUI to better output the overall system status and status
 //! of various subsystems
 
-use std::sync::{mpsc, Arc, RwLock};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
 use std::{self, thread};
 use time;
 
@@ -33,9 +34,8 @@ use tui::constants::*;
 use tui::types::*;
 use tui::{menu, mining, version};
 
-use core::Algorithm;
-
-use stats;
+use pipeline::Pipeline;
+use types;
 
 use built_info;
 
@@ -45,6 +45,21 @@ pub struct UI {
 	ui_rx: mpsc::Receiver<UIMessage>,
 	ui_tx: mpsc::Sender<UIMessage>,
 	controller_tx: mpsc::Sender<ControllerMessage>,
+	/// index of the pipeline shown in the mining view
+	current: Arc<AtomicUsize>,
+}
+
+/// Title of the mining view, only needed to tell several pipelines apart
+fn pipeline_title(pipelines: &[Pipeline], current: usize) -> Option<String> {
+	if pipelines.len() < 2 {
+		return None;
+	}
+	Some(format!(
+		"Pipeline {} ({}/{}, n: next)",
+		pipelines[current].name,
+		current + 1,
+		pipelines.len()
+	))
 }
 
 fn modify_theme(theme: &mut Theme) {
@@ -61,17 +76,21 @@ fn modify_theme(theme: &mut Theme) {
 
 impl UI {
 	/// Create a new UI
-	pub fn new(algorithm: Algorithm, controller_tx: mpsc::Sender<ControllerMessage>) -> UI {
+	pub fn new(pipelines: Vec<Pipeline>, controller_tx: mpsc::Sender<ControllerMessage>) -> UI {
 		let (ui_tx, ui_rx) = mpsc::channel::<UIMessage>();
 		let mut epic_ui = UI {
 			cursive: Cursive::default(),
 			ui_tx: ui_tx,
 			ui_rx: ui_rx,
 			controller_tx: controller_tx,
+			current: Arc::new(AtomicUsize::new(0)),
 		};
 
 		// Create UI objects, etc
-		let mining_view = mining::TUIMiningView::create_algorithm(algorithm);
+		let mining_view = mining::TUIMiningView::create_algorithm(
+			pipelines[0].algorithm,
+			pipeline_title(&pipelines, 0),
+		);
 		let version_view = version::TUIVersionView::create();
 
 		let main_menu = menu::create();
@@ -108,6 +127,41 @@ impl UI {
 				.send(ControllerMessage::Shutdown)
 				.unwrap();
 		});
+		// Per device controls, acting on the row selected in the mining view
+		let device_controls: [(char, fn(usize) -> types::MinerMessage); 3] = [
+			('p', types::MinerMessage::PauseSolver),
+			('r', types::MinerMessage::ResumeSolver),
+			('x', types::MinerMessage::RemoveSolver),
+		];
+		for &(key, message) in device_controls.iter() {
+			let pipelines = pipelines.clone();
+			let current = epic_ui.current.clone();
+			epic_ui.cursive.add_global_callback(key, move |c| {
+				if let Some(instance) = mining::TUIMiningView::selected_instance(c) {
+					let pipeline = &pipelines[current.load(Ordering::Relaxed)];
+					let _ = pipeline.miner_tx.send(message(instance));
+				}
+			});
+		}
+		// Switch the mining view to the next pipeline, rebuilt as the
+		// columns depend on the algorithm
+		if pipelines.len() > 1 {
+			let current = epic_ui.current.clone();
+			epic_ui.cursive.add_global_callback('n', move |c| {
+				let next = (current.load(Ordering::Relaxed) + 1) % pipelines.len();
+				current.store(next, Ordering::Relaxed);
+				let view = mining::TUIMiningView::create_algorithm(
+					pipelines[next].algorithm,
+					pipeline_title(&pipelines, next),
+				);
+				c.call_on_id(ROOT_STACK, |sv: &mut StackView| {
+					if let Some(pos) = sv.find_layer_from_id(VIEW_MINING) {
+						sv.remove_layer(pos);
+					}
+					sv.add_fullscreen_layer(view);
+				});
+			});
+		}
 		epic_ui.cursive.set_fps(4);
 		epic_ui
 	}
@@ -145,6 +199,7 @@ impl UI {
 pub struct Controller {
 	rx: mpsc::Receiver<ControllerMessage>,
 	ui: UI,
+	pipelines: Vec<Pipeline>,
 }
 
 /// Controller Message
@@ -155,15 +210,16 @@ pub enum ControllerMessage {
 
 impl Controller {
 	/// Create a new controller
-	pub fn new(algo: Algorithm) -> Result<Controller, String> {
+	pub fn new(pipelines: Vec<Pipeline>) -> Result<Controller, String> {
 		let (tx, rx) = mpsc::channel::<ControllerMessage>();
 		Ok(Controller {
 			rx: rx,
-			ui: UI::new(algo, tx.clone()),
+			ui: UI::new(pipelines.clone(), tx.clone()),
+			pipelines: pipelines,
 		})
 	}
 	/// Run the controller
-	pub fn run(&mut self, stats: Arc<RwLock<stats::Stats>>) {
+	pub fn run(&mut self) {
 		let stat_update_interval = 1;
 		let mut next_stat_update = time::get_time().sec + stat_update_interval;
 		while self.ui.step() {
@@ -176,9 +232,12 @@ impl Controller {
 				}
 			}
 			if time::get_time().sec > next_stat_update {
+				let stats = self.pipelines[self.ui.current.load(Ordering::Relaxed)]
+					.stats
+					.clone();
 				self.ui
 					.ui_tx
-					.send(UIMessage::UpdateStatus(stats.clone()))
+					.send(UIMessage::UpdateStatus(stats))
 					.unwrap();
 				next_stat_update = time::get_time().sec + stat_update_interval;
 			}
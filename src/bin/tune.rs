@@ -0,0 +1,363 @@
+// Copyright 2019 The Epic Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Auto-tuning of the RandomX thread count and the Cuckoo plugin
+//! parameters. Short benchmark rounds are run over a grid of values, and
+//! the fastest combination whose memory use fits the budget is picked.
+//!
+//! Cuckoo plugins are tuned one after the other, each on its own, and
+//! what the chosen parameters of one take is deducted from the budget left
+//! for the next. Every finished round is appended to a state file as a
+//! line of JSON, tuning again skips the rounds already found there.
+
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+use serde_json;
+
+use crate::benchmark;
+use core::config::MinerConfig;
+use core::{Algorithm, MinerEntry};
+
+/// A finished benchmark round
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Round {
+	/// what was tuned, randomx or the name of the plugin
+	pub target: String,
+	/// parameter values tried
+	pub params: BTreeMap<String, u32>,
+	/// graphs or hashes per second over all devices
+	pub rate: f64,
+	/// bytes of host memory taken at the peak, if known
+	pub memory: Option<u64>,
+	pub error: Option<String>,
+}
+
+impl Round {
+	/// Parameters as name=value pairs
+	pub fn params_str(&self) -> String {
+		self.params
+			.iter()
+			.map(|(k, v)| format!("{}={}", k, v))
+			.collect::<Vec<_>>()
+			.join(", ")
+	}
+}
+
+/// Something to tune and the parameter combinations to try on it
+struct Target {
+	name: String,
+	/// miner configuration the combination is applied to
+	config: MinerConfig,
+	/// index of the plugin in `miner_plugin_config`, none for RandomX
+	plugin: Option<usize>,
+	combinations: Vec<BTreeMap<String, u32>>,
+}
+
+/// Rounds finished so far, kept in an append-only file
+pub struct State {
+	path: PathBuf,
+	rounds: Vec<Round>,
+}
+
+impl State {
+	/// Reads the rounds of an earlier tune, a missing file is a fresh one
+	pub fn open<P: AsRef<Path>>(path: P) -> Result<State, String> {
+		let path = path.as_ref().to_path_buf();
+		let mut rounds = vec![];
+		match File::open(&path) {
+			Ok(f) => {
+				for line in BufReader::new(f).lines() {
+					let line = line
+						.map_err(|e| format!("Can't read tuning state {}: {}", path.display(), e))?;
+					// a line cut short by an interruption is simply run again
+					if let Ok(r) = serde_json::from_str::<Round>(&line) {
+						rounds.push(r);
+					}
+				}
+			}
+			Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => {}
+			Err(e) => return Err(format!("Can't open tuning state {}: {}", path.display(), e)),
+		}
+		Ok(State {
+			path: path,
+			rounds: rounds,
+		})
+	}
+
+	pub fn rounds(&self) -> &[Round] {
+		&self.rounds
+	}
+
+	fn find(&self, target: &str, params: &BTreeMap<String, u32>) -> Option<&Round> {
+		self.rounds
+			.iter()
+			.find(|r| r.target == target && &r.params == params)
+	}
+
+	fn add(&mut self, round: Round) -> Result<(), String> {
+		let line = serde_json::to_string(&round).map_err(|e| format!("{}", e))?;
+		OpenOptions::new()
+			.create(true)
+			.append(true)
+			.open(&self.path)
+			.and_then(|mut f| writeln!(f, "{}", line))
+			.map_err(|e| format!("Can't write tuning state {}: {}", self.path.display(), e))?;
+		self.rounds.push(round);
+		Ok(())
+	}
+}
+
+/// Thread counts worth trying on a machine with that many CPUs
+pub fn thread_candidates(cpus: u32) -> Vec<u32> {
+	let cpus = cpus.max(1);
+	let mut candidates = vec![1, cpus / 4, cpus / 2, cpus * 3 / 4, cpus - 1, cpus];
+	let mut p = 2;
+	while p < cpus {
+		candidates.push(p);
+		p *= 2;
+	}
+	candidates.retain(|&c| c > 0);
+	candidates.sort();
+	candidates.dedup();
+	candidates
+}
+
+/// Values around the default, kept even as the GPU trimmers want
+fn around(default: u32) -> Vec<u32> {
+	let mut values: Vec<u32> = vec![default * 3 / 4, default, default * 5 / 4]
+		.into_iter()
+		.map(|v| v & !1)
+		.filter(|&v| v > 0)
+		.collect();
+	values.dedup();
+	if values.is_empty() {
+		values.push(default);
+	}
+	values
+}
+
+/// Every combination of the parameter values
+fn grid(params: &[(&str, Vec<u32>)]) -> Vec<BTreeMap<String, u32>> {
+	let mut combinations = vec![BTreeMap::new()];
+	for &(name, ref values) in params {
+		let mut next = vec![];
+		for c in &combinations {
+			for v in values {
+				let mut c = c.clone();
+				c.insert(name.to_owned(), *v);
+				next.push(c);
+			}
+		}
+		combinations = next;
+	}
+	combinations
+}
+
+/// CPUs the miner may run on
+pub fn cpu_count() -> u32 {
+	thread::available_parallelism()
+		.map(|n| n.get() as u32)
+		.unwrap_or(1)
+}
+
+fn targets(entry: &MinerEntry, config: &MinerConfig) -> Result<Vec<Target>, String> {
+	let cpus = cpu_count();
+	match entry.algorithm {
+		Algorithm::RandomX => Ok(vec![Target {
+			name: "randomx".to_owned(),
+			config: config.clone(),
+			plugin: None,
+			combinations: grid(&[("threads", thread_candidates(cpus))]),
+		}]),
+		Algorithm::Cuckoo => {
+			// defaults of the plugins, as they report them
+			let devices = (entry.capabilities)(config)
+				.map_err(|e| format!("{}", e))?
+				.devices;
+			let mut targets = vec![];
+			for (i, plugin) in config.miner_plugin_config.iter().enumerate() {
+				let default = |name: &str| {
+					devices
+						.iter()
+						.find(|d| d.name == plugin.plugin_name)
+						.and_then(|d| d.params.iter().find(|p| p.name == name))
+						.and_then(|p| p.default.parse::<u32>().ok())
+						.unwrap_or(0)
+				};
+				let params = if plugin.plugin_name.contains("cuda") || plugin.plugin_name.contains("ocl") {
+					vec![("expand", vec![0, 1, 2]), ("ntrims", around(default("ntrims")))]
+				} else {
+					vec![("nthreads", thread_candidates(cpus))]
+				};
+				let mut plugin_config = config.clone();
+				plugin_config.miner_plugin_config = vec![plugin.clone()];
+				targets.push(Target {
+					name: format!("{} ({})", plugin.plugin_name, i),
+					config: plugin_config,
+					plugin: Some(i),
+					combinations: grid(&params),
+				});
+			}
+			if targets.is_empty() {
+				return Err("No cuckoo plugins are configured".to_owned());
+			}
+			Ok(targets)
+		}
+		Algorithm::ProgPow => Err("The progpow miner has nothing to tune".to_owned()),
+	}
+}
+
+/// Applies tuned parameter values to a configuration
+fn apply(config: &mut MinerConfig, plugin: Option<usize>, params: &BTreeMap<String, u32>) {
+	match plugin {
+		None => {
+			if let Some(threads) = params.get("threads") {
+				config.randomx_config.threads = *threads as u64;
+			}
+		}
+		Some(i) => {
+			let plugin = &mut config.miner_plugin_config[i];
+			let mut parameters = plugin.parameters.clone().unwrap_or_default();
+			for (k, v) in params {
+				parameters.insert(k.clone(), *v);
+			}
+			plugin.parameters = Some(parameters);
+		}
+	}
+}
+
+/// The fastest round that ran without errors and fits the budget
+fn best<'a>(rounds: &[&'a Round], budget: Option<u64>) -> Option<&'a Round> {
+	rounds
+		.iter()
+		.filter(|r| r.error.is_none() && r.rate > 0.0)
+		.filter(|r| match (budget, r.memory) {
+			(Some(b), Some(m)) => m <= b,
+			_ => true,
+		})
+		.max_by(|a, b| a.rate.partial_cmp(&b.rate).unwrap())
+		.cloned()
+}
+
+/// Tunes the miner, returns the configuration with the best parameters
+/// found applied. `budget` is in bytes, `duration` is the length of a
+/// single round once warmed up
+pub fn run(
+	entry: &MinerEntry,
+	config: &MinerConfig,
+	duration: Duration,
+	budget: Option<u64>,
+	state: &mut State,
+) -> Result<MinerConfig, String> {
+	let mut tuned = config.clone();
+	let mut budget = budget;
+	for target in targets(entry, config)? {
+		let mut rounds = vec![];
+		for params in &target.combinations {
+			let round = match state.find(&target.name, params) {
+				Some(r) => r.clone(),
+				None => {
+					let mut round_config = target.config.clone();
+					apply(&mut round_config, target.plugin.map(|_| 0), params);
+					let result = benchmark::run(entry, &round_config, duration);
+					let round = Round {
+						target: target.name.clone(),
+						params: params.clone(),
+						rate: result.total_rate,
+						memory: result.memory,
+						error: result
+							.error
+							.clone()
+							.or_else(|| result.devices.iter().filter_map(|d| d.error.clone()).next()),
+					};
+					state.add(round.clone())?;
+					round
+				}
+			};
+			eprintln!(
+				"{} {}: {}",
+				target.name,
+				round.params_str(),
+				match round.error {
+					Some(ref e) => format!("failed, {}", e),
+					None => format!(
+						"{:.2} {}{}",
+						round.rate,
+						benchmark::rate_unit(entry.algorithm),
+						round.memory.map_or(String::new(), |m| format!(", {} MiB", m >> 20))
+					),
+				}
+			);
+			rounds.push(round);
+		}
+		let found: Vec<&Round> = rounds.iter().collect();
+		match best(&found, budget) {
+			Some(r) => {
+				eprintln!("{}: picked {}", target.name, r.params_str());
+				apply(&mut tuned, target.plugin, &r.params);
+				if let (Some(b), Some(m)) = (budget, r.memory) {
+					budget = Some(b.saturating_sub(m));
+				}
+			}
+			None => {
+				return Err(format!(
+					"No parameters for {} ran without errors within the memory budget",
+					target.name
+				))
+			}
+		}
+	}
+	Ok(tuned)
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn candidates() {
+		assert_eq!(thread_candidates(1), vec![1]);
+		assert_eq!(thread_candidates(8), vec![1, 2, 4, 6, 7, 8]);
+		assert_eq!(thread_candidates(12), vec![1, 2, 3, 4, 6, 8, 9, 11, 12]);
+		assert_eq!(around(176), vec![132, 176, 220]);
+		assert_eq!(around(0), vec![0]);
+		assert_eq!(grid(&[("a", vec![1, 2]), ("b", vec![3, 4])]).len(), 4);
+	}
+
+	#[test]
+	fn pick_best() {
+		let round = |threads, rate, memory, error: Option<&str>| Round {
+			target: "randomx".to_owned(),
+			params: grid(&[("threads", vec![threads])]).remove(0),
+			rate: rate,
+			memory: Some(memory),
+			error: error.map(|e| e.to_owned()),
+		};
+		let rounds = vec![
+			round(1, 100.0, 10, None),
+			round(2, 190.0, 20, None),
+			round(4, 300.0, 40, Some("out of memory")),
+			round(3, 250.0, 30, None),
+		];
+		let rounds: Vec<&Round> = rounds.iter().collect();
+		assert_eq!(best(&rounds, None).unwrap().params["threads"], 3);
+		assert_eq!(best(&rounds, Some(25)).unwrap().params["threads"], 2);
+		assert!(best(&rounds, Some(5)).is_none());
+	}
+}
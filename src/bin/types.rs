@@ -12,7 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use core::{AlgorithmParams, Solution};
+use core::{AlgorithmParams, MinerEvent, Solution};
 use serde_json::Value;
 
 /// Types used for stratum
@@ -87,19 +87,30 @@ pub struct WorkerStatus {
 }
 
 /// Types used for internal communication from stratum client to miner
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Debug)]
 pub enum MinerMessage {
 	// Height, difficulty, pre_pow
 	ReceivedJob(u64, u64, u64, String),
 	// current_seed, next_seed
 	ReceivedSeed(Vec<(u64,u64, [u8; 32])>),
 	StopJob,
+	// Pause mining until told to resume, jobs keep being tracked
+	Pause,
+	Resume,
+	// solver instance to pause, resume or stop for good, the others keep going
+	PauseSolver(usize),
+	ResumeSolver(usize),
+	RemoveSolver(usize),
 	Shutdown,
+	// solution or stats update pushed by the miner itself
+	MinerEvent(MinerEvent),
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub enum ClientMessage {
 	// height, job_id, edge_bits, nonce, pow
 	FoundSolution(u64, Solution),
+	// stratum server address to connect to instead of the current one
+	SwitchServer(String),
 	Shutdown,
 }
@@ -0,0 +1,178 @@
+// Copyright 2019 The Epic Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Minimal server side WebSocket (RFC 6455) support, just what's needed
+//! to push the event stream to clients: the upgrade handshake, unmasked
+//! text frames out, and control frames in.
+
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use base64;
+use sha1_smol::Sha1;
+use util::LOGGER;
+
+const ACCEPT_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Biggest control frame payload allowed by the spec
+const MAX_CONTROL_PAYLOAD: u64 = 125;
+
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xA;
+
+/// How long the stream may stay silent before we ping the client
+const PING_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Value of the Sec-WebSocket-Accept header for the client's key
+pub fn accept_key(key: &str) -> String {
+	let mut sha = Sha1::new();
+	sha.update(key.trim().as_bytes());
+	sha.update(ACCEPT_GUID.as_bytes());
+	base64::encode(&sha.digest().bytes())
+}
+
+fn write_frame<W: Write>(w: &mut W, opcode: u8, payload: &[u8]) -> io::Result<()> {
+	let mut frame = Vec::with_capacity(payload.len() + 10);
+	frame.push(0x80 | opcode);
+	let len = payload.len();
+	if len < 126 {
+		frame.push(len as u8);
+	} else if len <= u16::max_value() as usize {
+		frame.push(126);
+		frame.extend_from_slice(&(len as u16).to_be_bytes());
+	} else {
+		frame.push(127);
+		frame.extend_from_slice(&(len as u64).to_be_bytes());
+	}
+	frame.extend_from_slice(payload);
+	w.write_all(&frame)?;
+	w.flush()
+}
+
+/// Reads a single frame from the client, returning its opcode and unmasked
+/// payload. We never expect data from clients, so anything but a small
+/// control frame is an error.
+fn read_frame<R: Read>(r: &mut R) -> io::Result<(u8, Vec<u8>)> {
+	let mut head = [0u8; 2];
+	r.read_exact(&mut head)?;
+	let opcode = head[0] & 0x0F;
+	let masked = head[1] & 0x80 != 0;
+	let len = match head[1] & 0x7F {
+		126 => {
+			let mut l = [0u8; 2];
+			r.read_exact(&mut l)?;
+			u16::from_be_bytes(l) as u64
+		}
+		127 => {
+			let mut l = [0u8; 8];
+			r.read_exact(&mut l)?;
+			u64::from_be_bytes(l)
+		}
+		l => l as u64,
+	};
+	if len > MAX_CONTROL_PAYLOAD {
+		return Err(io::Error::new(
+			io::ErrorKind::InvalidData,
+			"Unexpected data frame from client",
+		));
+	}
+	let mut mask = [0u8; 4];
+	if masked {
+		r.read_exact(&mut mask)?;
+	}
+	let mut payload = vec![0u8; len as usize];
+	r.read_exact(&mut payload)?;
+	if masked {
+		for (i, b) in payload.iter_mut().enumerate() {
+			*b ^= mask[i % 4];
+		}
+	}
+	Ok((opcode, payload))
+}
+
+/// Completes the upgrade handshake and forwards every message received on
+/// `rx` as a text frame until either side goes away. Blocks the calling
+/// thread.
+pub fn serve(stream: TcpStream, key: &str, rx: mpsc::Receiver<String>) -> io::Result<()> {
+	let mut stream = stream;
+	stream.set_read_timeout(None)?;
+	stream.set_write_timeout(Some(Duration::from_secs(10)))?;
+	let head = format!(
+		"HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+		accept_key(key)
+	);
+	stream.write_all(head.as_bytes())?;
+
+	// client frames are handled on their own thread, writes are shared
+	let mut reader = stream.try_clone()?;
+	let writer = Arc::new(Mutex::new(stream));
+	let reader_writer = writer.clone();
+	let _ = thread::Builder::new()
+		.name("api_websocket".to_string())
+		.spawn(move || {
+			loop {
+				match read_frame(&mut reader) {
+					Ok((OPCODE_PING, payload)) => {
+						let mut w = reader_writer.lock().unwrap();
+						if write_frame(&mut *w, OPCODE_PONG, &payload).is_err() {
+							break;
+						}
+					}
+					Ok((OPCODE_CLOSE, _)) | Err(_) => break,
+					Ok(_) => {}
+				}
+			}
+			// makes the next write on the other side fail
+			let w = reader_writer.lock().unwrap();
+			let _ = w.shutdown(Shutdown::Both);
+		});
+
+	loop {
+		let (opcode, payload) = match rx.recv_timeout(PING_INTERVAL) {
+			Ok(message) => (OPCODE_TEXT, message.into_bytes()),
+			Err(mpsc::RecvTimeoutError::Timeout) => (OPCODE_PING, vec![]),
+			Err(mpsc::RecvTimeoutError::Disconnected) => {
+				// dropped by the event bus
+				let mut w = writer.lock().unwrap();
+				let _ = write_frame(&mut *w, OPCODE_CLOSE, &1008u16.to_be_bytes());
+				let _ = w.shutdown(Shutdown::Both);
+				return Ok(());
+			}
+		};
+		let mut w = writer.lock().unwrap();
+		if let Err(e) = write_frame(&mut *w, opcode, &payload) {
+			debug!(LOGGER, "WebSocket client went away: {}", e);
+			let _ = w.shutdown(Shutdown::Both);
+			return Ok(());
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn accept_key_matches_rfc_example() {
+		assert_eq!(
+			accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+			"s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+		);
+	}
+}
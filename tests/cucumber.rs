@@ -58,16 +58,16 @@ mod miner_test {
 
 			match algo.as_str() {
 				"progpow" => {
-					world.miner = Some(TargetMiner::ProgPow(progpow::PpMiner::new(&miner_config)));
+					world.miner = Some(TargetMiner::ProgPow(progpow::PpMiner::new(&miner_config).unwrap()));
 				},
 				"randomx" => {
-					world.miner = Some(TargetMiner::RandomX(randomx::RxMiner::new(&miner_config)));
+					world.miner = Some(TargetMiner::RandomX(randomx::RxMiner::new(&miner_config).unwrap()));
 				},
 				"cuckoo" => {
 					//let plugin = PluginConfig::new(mining_plugin_dir_for_tests(), &world.plugin).unwrap();
 					miner_config.miner_plugin_dir = Some(mining_plugin_dir_for_tests());
 					miner_config.miner_plugin_config = vec![EpicMinerPluginConfig{ plugin_name: world.plugin.clone(), ..Default::default()}];
-					world.miner = Some(TargetMiner::Cuckoo(cuckoo::CuckooMiner::new(&miner_config)));
+					world.miner = Some(TargetMiner::Cuckoo(cuckoo::CuckooMiner::new(&miner_config).unwrap()));
 				},
 				_ => {
 					panic!("Algorithm not supported");
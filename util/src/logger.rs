@@ -14,8 +14,9 @@
 //! Logging wrapper to be used throughout all crates in the workspace
 use std::fs::OpenOptions;
 use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::ops::Deref;
-use slog::{Discard, Drain, Duplicate, Level, LevelFilter, Logger};
+use slog::{Discard, Drain, Duplicate, Level, Logger, OwnedKVList, Record};
 use slog_term;
 use slog_async;
 
@@ -35,6 +36,31 @@ fn convert_log_level(in_level: &LogLevel) -> Level {
 	}
 }
 
+/// Current stdout and file log levels, can be changed at runtime
+static STDOUT_LOG_LEVEL: AtomicUsize = AtomicUsize::new(0);
+static FILE_LOG_LEVEL: AtomicUsize = AtomicUsize::new(0);
+
+/// Level filter reading its level from one of the atomics above, so
+/// the level can be changed without rebuilding the logger
+struct RuntimeLevelFilter<D: Drain> {
+	drain: D,
+	level: &'static AtomicUsize,
+}
+
+impl<D: Drain> Drain for RuntimeLevelFilter<D> {
+	type Ok = Option<D::Ok>;
+	type Err = D::Err;
+
+	fn log(&self, record: &Record, values: &OwnedKVList) -> Result<Self::Ok, Self::Err> {
+		let level = Level::from_usize(self.level.load(Ordering::Relaxed)).unwrap_or(Level::Info);
+		if record.level().is_at_least(level) {
+			self.drain.log(record, values).map(Some)
+		} else {
+			Ok(None)
+		}
+	}
+}
+
 lazy_static! {
 	/// Flag to observe whether logging was explicitly initialised (don't output otherwise)
 	static ref WAS_INIT: Mutex<bool> = Mutex::new(false);
@@ -48,8 +74,8 @@ lazy_static! {
 	pub static ref LOGGER: Logger = {
 		let was_init = WAS_INIT.lock().unwrap().clone();
 		let config = LOGGING_CONFIG.lock().unwrap();
-		let slog_level_stdout = convert_log_level(&config.stdout_log_level);
-		let slog_level_file = convert_log_level(&config.file_log_level);
+		STDOUT_LOG_LEVEL.store(convert_log_level(&config.stdout_log_level).as_usize(), Ordering::Relaxed);
+		FILE_LOG_LEVEL.store(convert_log_level(&config.file_log_level).as_usize(), Ordering::Relaxed);
 		if config.tui_running.is_some() && config.tui_running.unwrap() {
 			let mut tui_running_ref = TUI_RUNNING.lock().unwrap();
 			*tui_running_ref = true;
@@ -58,7 +84,11 @@ lazy_static! {
 		//Terminal output drain
 		let terminal_decorator = slog_term::TermDecorator::new().build();
 		let terminal_drain = slog_term::FullFormat::new(terminal_decorator).build().fuse();
-		let terminal_drain = LevelFilter::new(terminal_drain, slog_level_stdout).fuse();
+		let terminal_drain = RuntimeLevelFilter {
+			drain: terminal_drain,
+			level: &STDOUT_LOG_LEVEL,
+		}
+		.fuse();
 		let mut terminal_drain = slog_async::Async::new(terminal_drain).build().fuse();
 		if !config.log_to_stdout || !was_init {
 			terminal_drain = slog_async::Async::new(Discard{}).build().fuse();
@@ -77,7 +107,11 @@ lazy_static! {
 
 			let file_decorator = slog_term::PlainDecorator::new(file);
 			let file_drain = slog_term::FullFormat::new(file_decorator).build().fuse();
-			let file_drain = LevelFilter::new(file_drain, slog_level_file).fuse();
+			let file_drain = RuntimeLevelFilter {
+				drain: file_drain,
+				level: &FILE_LOG_LEVEL,
+			}
+			.fuse();
 			file_drain_final = slog_async::Async::new(file_drain).build().fuse();
 		}
 
@@ -102,6 +136,13 @@ pub fn init_logger(config: Option<LoggingConfig>) {
 	send_panic_to_log();
 }
 
+/// Changes the stdout and file log level of the running logger
+pub fn set_log_level(level: &LogLevel) {
+	let level = convert_log_level(level).as_usize();
+	STDOUT_LOG_LEVEL.store(level, Ordering::Relaxed);
+	FILE_LOG_LEVEL.store(level, Ordering::Relaxed);
+}
+
 /// Initializes the logger for unit and integration tests
 pub fn init_test_logger() {
 	let mut was_init_ref = WAS_INIT.lock().unwrap();